@@ -0,0 +1,42 @@
+//! Bake the versions of the dependencies in Cargo.lock into the build for `getVersions`.
+
+use std::path::{Path, PathBuf};
+
+const PACKAGES: [(&str, &str); 4] = [
+  ("ast-grep-core", "SG_VERSION_CORE"),
+  ("ast-grep-config", "SG_VERSION_CONFIG"),
+  ("web-tree-sitter-sg", "SG_VERSION_WEB_TREE_SITTER"),
+  ("tree-sitter-facade-sg", "SG_VERSION_TREE_SITTER_FACADE"),
+];
+
+// the lock file is next to the manifest, or in a parent directory for workspace members
+fn find_lock(dir: &Path) -> Option<PathBuf> {
+  dir
+    .ancestors()
+    .map(|d| d.join("Cargo.lock"))
+    .find(|lock| lock.exists())
+}
+
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+  let name = format!("name = \"{}\"", package);
+  let mut lines = lock.lines();
+  lines.find(|line| *line == name)?;
+  let version = lines.next()?.strip_prefix("version = \"")?;
+  Some(version.trim_end_matches('"').to_owned())
+}
+
+fn main() {
+  let dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+  let lock = find_lock(&dir);
+  println!("cargo:rerun-if-changed=build.rs");
+  if let Some(lock) = &lock {
+    println!("cargo:rerun-if-changed={}", lock.display());
+  }
+  let lock = lock
+    .and_then(|lock| std::fs::read_to_string(lock).ok())
+    .unwrap_or_default();
+  for (package, var) in PACKAGES {
+    let version = locked_version(&lock, package).unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env={}={}", var, version);
+  }
+}
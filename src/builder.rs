@@ -0,0 +1,177 @@
+use crate::context::global;
+use crate::error::{tag, Code};
+use crate::rule::RuleHandle;
+use crate::types::Config;
+use crate::{serialize, shape};
+
+use serde_json::{json, Map, Value};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// A config built step by step, `rule().pattern("$A == $A").inside(rule().kind("if_statement"))`.
+/// Every rule method adds a condition the node must meet too, several are combined with `all`.
+/// Builders passed to other builders are copied, so they can be reused.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct RuleBuilder {
+  conditions: Vec<Value>,
+  /// fields of a relation, only used when the rule is the related node of another
+  until: Option<Value>,
+  immediate: bool,
+  /// the other keys of the config, e.g. `fix` and `language`
+  config: Map<String, Value>,
+}
+
+/// Start an empty `RuleBuilder`.
+#[wasm_bindgen]
+pub fn rule() -> RuleBuilder {
+  RuleBuilder::default()
+}
+
+impl RuleBuilder {
+  fn with(mut self, key: &str, value: Value) -> Self {
+    self.conditions.push(json!({ key: value }));
+    self
+  }
+
+  fn set(mut self, key: &str, value: Value) -> Self {
+    self.config.insert(key.into(), value);
+    self
+  }
+
+  fn rule_value(&self) -> Value {
+    match self.conditions.as_slice() {
+      [only] => only.clone(),
+      // an empty rule is left for the shape check to report
+      [] => json!({}),
+      all => json!({ "all": all }),
+    }
+  }
+
+  fn relation_value(&self) -> Value {
+    let mut relation = self.rule_value();
+    if let Value::Object(object) = &mut relation {
+      if let Some(until) = &self.until {
+        object.insert("until".into(), until.clone());
+      }
+      if self.immediate {
+        object.insert("immediate".into(), true.into());
+      }
+    }
+    relation
+  }
+
+  fn config_value(&self) -> Value {
+    let mut config = self.config.clone();
+    config.insert("rule".into(), self.rule_value());
+    Value::Object(config)
+  }
+}
+
+#[wasm_bindgen]
+impl RuleBuilder {
+  pub fn pattern(self, pattern: String) -> RuleBuilder {
+    self.with("pattern", pattern.into())
+  }
+
+  /// A contextual pattern, `selector` is the kind of the node of `context` to match.
+  #[wasm_bindgen(js_name = patternInContext)]
+  pub fn pattern_in_context(self, context: String, selector: String) -> RuleBuilder {
+    self.with(
+      "pattern",
+      json!({ "context": context, "selector": selector }),
+    )
+  }
+
+  pub fn kind(self, kind: String) -> RuleBuilder {
+    self.with("kind", kind.into())
+  }
+
+  pub fn inside(self, other: &RuleBuilder) -> RuleBuilder {
+    self.with("inside", other.relation_value())
+  }
+
+  pub fn has(self, other: &RuleBuilder) -> RuleBuilder {
+    self.with("has", other.relation_value())
+  }
+
+  pub fn precedes(self, other: &RuleBuilder) -> RuleBuilder {
+    self.with("precedes", other.relation_value())
+  }
+
+  pub fn follows(self, other: &RuleBuilder) -> RuleBuilder {
+    self.with("follows", other.relation_value())
+  }
+
+  pub fn not(self, other: &RuleBuilder) -> RuleBuilder {
+    self.with("not", other.rule_value())
+  }
+
+  /// Match what this rule or `other` matches, the conditions added after apply to both.
+  pub fn or(self, other: &RuleBuilder) -> RuleBuilder {
+    let any = json!([self.rule_value(), other.rule_value()]);
+    let conditions = vec![];
+    RuleBuilder { conditions, ..self }.with("any", any)
+  }
+
+  /// Stop the search of a relation using this rule at nodes matching `other`.
+  pub fn until(mut self, other: &RuleBuilder) -> RuleBuilder {
+    self.until = Some(other.rule_value());
+    self
+  }
+
+  /// Only look at the next related node of a relation using this rule.
+  pub fn immediate(mut self) -> RuleBuilder {
+    self.immediate = true;
+    self
+  }
+
+  pub fn language(self, language: String) -> RuleBuilder {
+    self.set("language", language.into())
+  }
+
+  pub fn fix(self, fix: String) -> RuleBuilder {
+    self.set("fix", fix.into())
+  }
+
+  /// Constrain the metavariable `name`, without `$`, by `{ regex }`, `{ pattern }` or `{ kind }`.
+  pub fn constraint(mut self, name: String, matcher: JsValue) -> Result<RuleBuilder, JsValue> {
+    let matcher: Value =
+      serde_wasm_bindgen::from_value(matcher).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let constraints = self
+      .config
+      .entry("constraints")
+      .or_insert_with(|| json!({}));
+    if let Value::Object(constraints) = constraints {
+      constraints.insert(name, matcher);
+    }
+    Ok(self)
+  }
+
+  pub fn id(self, id: String) -> RuleBuilder {
+    self.set("id", id.into())
+  }
+
+  pub fn message(self, message: String) -> RuleBuilder {
+    self.set("message", message.into())
+  }
+
+  pub fn severity(self, severity: String) -> RuleBuilder {
+    self.set("severity", severity.into())
+  }
+
+  /// The built config as a plain object, checked like configs passed to `findNodes`.
+  /// Throws `INVALID_CONFIG` with the `path` of the mistake, e.g. for a rule without conditions.
+  #[wasm_bindgen(js_name = toConfig)]
+  pub fn to_config(&self) -> Result<Config, JsValue> {
+    let config = self.config_value();
+    shape::check_config(&config)?;
+    Ok(serialize::to_json_value(&config)?.unchecked_into())
+  }
+
+  /// Compile the built config with the module wide languages, like `compileRule`.
+  pub fn compile(&self) -> Result<RuleHandle, JsValue> {
+    let config = self.to_config()?;
+    Ok(RuleHandle::new(global().compile_config(config.into())?))
+  }
+}
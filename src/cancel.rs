@@ -0,0 +1,106 @@
+use crate::error::{error, Code};
+use crate::metrics::now;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// How many nodes a scan visits between two checks of its cancel token.
+const CHECK_INTERVAL: usize = 1024;
+
+/// A flag that aborts a running scan. Scans are synchronous, so the flag can only change
+/// mid-scan when it lives in a `SharedArrayBuffer` cancelled from another thread:
+/// create the token in the worker, post `token.buffer` to the page
+/// and call `cancel()` on `CancelToken.fromBuffer(buffer)` there.
+#[wasm_bindgen]
+pub struct CancelToken {
+  flag: js_sys::Int32Array,
+}
+
+#[wasm_bindgen]
+impl CancelToken {
+  /// Wrap a buffer shared by a token created on another thread.
+  #[wasm_bindgen(js_name = fromBuffer)]
+  pub fn from_buffer(buffer: JsValue) -> CancelToken {
+    CancelToken {
+      flag: js_sys::Int32Array::new(&buffer),
+    }
+  }
+
+  pub fn cancel(&self) {
+    let _ = js_sys::Atomics::store(&self.flag, 0, 1);
+  }
+
+  #[wasm_bindgen(getter, js_name = isCancelled)]
+  pub fn is_cancelled(&self) -> bool {
+    js_sys::Atomics::load(&self.flag, 0).is_ok_and(|v| v != 0)
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn buffer(&self) -> JsValue {
+    self.flag.buffer().into()
+  }
+}
+
+/// Create a token backed by a `SharedArrayBuffer` when the page is cross-origin isolated.
+#[wasm_bindgen(js_name = createCancelToken)]
+pub fn create_cancel_token() -> CancelToken {
+  let shared = js_sys::Reflect::has(&js_sys::global(), &"SharedArrayBuffer".into());
+  let buffer: JsValue = if shared.unwrap_or(false) {
+    js_sys::SharedArrayBuffer::new(4).into()
+  } else {
+    js_sys::ArrayBuffer::new(4).into()
+  };
+  CancelToken::from_buffer(buffer)
+}
+
+#[wasm_bindgen]
+extern "C" {
+  /// A `CancelToken` passed to a scan, borrowed by reference so the token stays usable in JS.
+  #[wasm_bindgen(typescript_type = "CancelToken")]
+  pub type CancelSignal;
+
+  #[wasm_bindgen(method, getter, js_name = isCancelled)]
+  fn is_cancelled(this: &CancelSignal) -> bool;
+}
+
+/// Counts visited nodes and checks the token and time budget every `CHECK_INTERVAL` of them.
+pub struct CancelCheck<'a> {
+  token: Option<&'a CancelSignal>,
+  deadline: Option<f64>,
+  visited: usize,
+  pub timed_out: bool,
+}
+
+impl<'a> CancelCheck<'a> {
+  /// The time budget starts now, so create the check before parsing.
+  pub fn new(token: Option<&'a CancelSignal>, timeout_ms: Option<f64>) -> Self {
+    Self {
+      token,
+      deadline: timeout_ms.map(|ms| now() + ms),
+      visited: 0,
+      timed_out: false,
+    }
+  }
+
+  pub fn visited(&self) -> usize {
+    self.visited
+  }
+
+  /// Err if the scan is cancelled, `Ok(false)` once the time budget is spent.
+  pub fn visit(&mut self) -> Result<bool, JsValue> {
+    self.visited += 1;
+    if self.visited % CHECK_INTERVAL != 0 {
+      return Ok(true);
+    }
+    if self.token.is_some_and(|t| t.is_cancelled()) {
+      let error: js_sys::Error = error(Code::Cancelled, "scan was cancelled").unchecked_into();
+      error.set_name("Cancelled");
+      return Err(error.into());
+    }
+    if self.deadline.is_some_and(|d| now() > d) {
+      self.timed_out = true;
+      return Ok(false);
+    }
+    Ok(true)
+  }
+}
@@ -0,0 +1,146 @@
+use crate::context::{global, Context};
+use crate::error::{error_with, Code};
+use crate::lines::LineIndex;
+use crate::rule::CompiledRule;
+use crate::types::{CaptureGroups, Config, StringArray};
+use crate::{limits, match_range, parse_root, serialize, utils};
+
+use ast_grep_core::meta_var::MetaVarEnv;
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::{HashMap, HashSet};
+
+/// The name of a metavariable without `$`, erring if the rule does not capture it.
+fn capture_name<'n>(rule: &CompiledRule, name: &'n str) -> Result<&'n str, JsValue> {
+  let name = name.trim_start_matches('$');
+  if !rule.captures.iter().any(|c| c == name) {
+    let message = format!("`${}` is not captured by the rule", name);
+    return Err(error_with(
+      Code::InvalidArgument,
+      &message,
+      &[("name", name.into())],
+    ));
+  }
+  Ok(name)
+}
+
+/// The text captured as `$NAME`, or spanned by the nodes of `$$$NAME`.
+fn captured_text(
+  env: &MetaVarEnv<ts::Language>,
+  name: &str,
+  src: &str,
+  lines: &LineIndex,
+) -> Option<String> {
+  if let Some(node) = env.get_match(name) {
+    return Some(node.text().into_owned());
+  }
+  let nodes = env.get_multiple_matches(name);
+  let (first, last) = (nodes.first()?, nodes.last()?);
+  let (start, end) = (first.range().start, last.range().end);
+  Some(src[lines.byte_of(start)..lines.byte_of(end)].to_string())
+}
+
+#[derive(Serialize)]
+struct CaptureGroup {
+  text: String,
+  count: usize,
+  ranges: Vec<[usize; 4]>,
+}
+
+/// Group the matches of `config` in `src` by the text they capture as `name`, e.g. `METHOD`
+/// for `console.$METHOD($$$)`, returning `[{ text, count, ranges }]` with the most frequent
+/// text first. `name` may have its `$`. Matches without the capture are left out, and
+/// `INVALID_ARGUMENT` is thrown if no pattern of the rule captures it.
+#[wasm_bindgen(js_name = groupByCapture)]
+pub fn group_by_capture(
+  src: String,
+  config: Config,
+  name: String,
+) -> Result<CaptureGroups, JsValue> {
+  global()
+    .group_by_capture(src, config.into(), name)
+    .map(JsCast::unchecked_into)
+}
+
+/// The distinct texts `config` captures as `name` in `src`, in order of first match, e.g.
+/// every imported module with `import $$$ from '$MOD'`. `name` may have its `$`, and
+/// `INVALID_ARGUMENT` is thrown if no pattern of the rule captures it.
+#[wasm_bindgen(js_name = collectCaptures)]
+pub fn collect_captures(src: String, config: Config, name: String) -> Result<StringArray, JsValue> {
+  global()
+    .collect_captures(src, config.into(), name)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn group_by_capture(
+    &self,
+    src: String,
+    config: JsValue,
+    name: String,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("groupByCapture", src.len());
+    let rule = self.compile_config(config)?;
+    let name = capture_name(&rule, &name)?;
+    let root = parse_root(&src, &rule.lang)?;
+    let lines = LineIndex::new(&src);
+    let mut groups: Vec<CaptureGroup> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut count = 0;
+    for node in root.root().find_all(&*rule) {
+      count += 1;
+      limits::check_matches(count)?;
+      let text = match captured_text(node.get_env(), name, &src, &lines) {
+        Some(text) => text,
+        None => continue,
+      };
+      let range = match_range(&node);
+      match index.get(&text) {
+        Some(&i) => {
+          groups[i].count += 1;
+          groups[i].ranges.push(range);
+        }
+        None => {
+          index.insert(text.clone(), groups.len());
+          groups.push(CaptureGroup {
+            text,
+            count: 1,
+            ranges: vec![range],
+          });
+        }
+      }
+    }
+    // stable, so texts as frequent stay in order of first match
+    groups.sort_by_key(|g| std::cmp::Reverse(g.count));
+    Ok(serialize::to_value(&groups)?)
+  }
+
+  pub fn collect_captures(
+    &self,
+    src: String,
+    config: JsValue,
+    name: String,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("collectCaptures", src.len());
+    let rule = self.compile_config(config)?;
+    let name = capture_name(&rule, &name)?;
+    let root = parse_root(&src, &rule.lang)?;
+    let lines = LineIndex::new(&src);
+    let mut seen = HashSet::new();
+    let mut ret = vec![];
+    let mut count = 0;
+    for node in root.root().find_all(&*rule) {
+      count += 1;
+      limits::check_matches(count)?;
+      if let Some(text) = captured_text(node.get_env(), name, &src, &lines) {
+        if seen.insert(text.clone()) {
+          ret.push(text);
+        }
+      }
+    }
+    Ok(serialize::to_json_value(&ret)?)
+  }
+}
@@ -0,0 +1,328 @@
+use crate::lines::LineIndex;
+use crate::match_range;
+
+use ast_grep_core::Node;
+use serde::Serialize;
+use tree_sitter as ts;
+
+use std::ops::Range;
+
+/// An edit in node offsets of the old source, see `LineIndex::js_index`.
+pub struct RangeEdit {
+  pub position: usize,
+  pub deleted: usize,
+  pub inserted: usize,
+}
+
+impl RangeEdit {
+  /// Whether `old`, an offset of the old source, is `new` after the edit.
+  fn maps(&self, old: usize, new: usize) -> bool {
+    let kept = old <= self.position && new == old;
+    let shifted = old >= self.position + self.deleted && new + self.deleted == old + self.inserted;
+    kept || shifted
+  }
+
+  /// Whether `range` of the old source overlaps the edit or borders it.
+  fn touches(&self, range: &Range<usize>) -> bool {
+    range.start <= self.position + self.deleted && range.end >= self.position
+  }
+}
+
+/// A node of the new tree whose syntax differs from the old tree.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedRange {
+  range: [usize; 4],
+  start_offset: usize,
+  end_offset: usize,
+}
+
+/// What the diff reads of a tree, so it runs on trees without a grammar too.
+trait Tree: Clone {
+  fn kind_id(&self) -> u16;
+  fn range(&self) -> Range<usize>;
+  fn children(&self) -> Vec<Self>;
+  fn text(&self) -> String;
+}
+
+/// A node with the source its text is read from, `Node::text` slices by node offsets.
+#[derive(Clone)]
+struct SourceNode<'r> {
+  node: Node<'r, ts::Language>,
+  src: &'r str,
+  lines: &'r LineIndex,
+}
+
+impl<'r> Tree for SourceNode<'r> {
+  fn kind_id(&self) -> u16 {
+    self.node.kind_id()
+  }
+
+  fn range(&self) -> Range<usize> {
+    self.node.range()
+  }
+
+  fn children(&self) -> Vec<Self> {
+    let children = self.node.children();
+    children.map(|node| Self { node, ..*self }).collect()
+  }
+
+  fn text(&self) -> String {
+    let range = self.node.range();
+    let (start, end) = (
+      self.lines.byte_of(range.start),
+      self.lines.byte_of(range.end),
+    );
+    self.src[start..end].to_owned()
+  }
+}
+
+/// What is kept of the old tree to diff the new one against, so the old root is not:
+/// the nodes the edit touches with their children, and the text of the touched leaves.
+pub struct OldNode {
+  kind_id: u16,
+  range: Range<usize>,
+  touched: bool,
+  text: Option<String>,
+  children: Vec<OldNode>,
+}
+
+impl OldNode {
+  /// The part of `root`, of the source before `edit`, that the diff after it reads.
+  pub fn new(root: Node<ts::Language>, src: &str, lines: &LineIndex, edit: &RangeEdit) -> Self {
+    let root = SourceNode {
+      node: root,
+      src,
+      lines,
+    };
+    Self::of(&root, edit)
+  }
+
+  fn of<T: Tree>(node: &T, edit: &RangeEdit) -> Self {
+    let range = node.range();
+    let touched = edit.touches(&range);
+    let children = if touched { node.children() } else { vec![] };
+    Self {
+      kind_id: node.kind_id(),
+      text: (touched && children.is_empty()).then(|| node.text()),
+      children: children.iter().map(|c| Self::of(c, edit)).collect(),
+      range,
+      touched,
+    }
+  }
+}
+
+/// Whether `old` and `new` are the same node apart from their children.
+fn same_node<T: Tree>(old: &OldNode, new: &T, edit: &RangeEdit) -> bool {
+  let range = new.range();
+  old.kind_id == new.kind_id()
+    && edit.maps(old.range.start, range.start)
+    && edit.maps(old.range.end, range.end)
+    && old.text.as_ref().map_or(true, |text| *text == new.text())
+}
+
+fn diff<T: Tree>(old: &OldNode, new: &T, edit: &RangeEdit, out: &mut Vec<T>) {
+  if !same_node(old, new, edit) {
+    out.push(new.clone());
+    return;
+  }
+  // the same kind and extent, and the edit is not in its text
+  if !old.touched {
+    return;
+  }
+  let children = new.children();
+  let (old_children, new_children) = (&old.children[..], &children[..]);
+  // children before and after the edit pair up from either end, the rest in order
+  let lead = old_children
+    .iter()
+    .zip(new_children)
+    .take_while(|(o, n)| o.range.end <= edit.position && same_node(o, *n, edit))
+    .count();
+  let trail = old_children[lead..]
+    .iter()
+    .rev()
+    .zip(new_children[lead..].iter().rev())
+    .take_while(|(o, n)| o.range.start >= edit.position + edit.deleted && same_node(o, *n, edit))
+    .count();
+  let old_middle = &old_children[lead..old_children.len() - trail];
+  let new_middle = &new_children[lead..new_children.len() - trail];
+  if old_middle.len() > new_middle.len() {
+    // no node of the new tree stands for a removed child
+    out.push(new.clone());
+    return;
+  }
+  let paired = old_children[..lead]
+    .iter()
+    .zip(&new_children[..lead])
+    .chain(old_middle.iter().zip(new_middle));
+  for (old, new) in paired {
+    diff(old, new, edit, out);
+  }
+  out.extend(new_middle[old_middle.len()..].iter().cloned());
+  let trailing = old_children[old_children.len() - trail..]
+    .iter()
+    .zip(&new_children[new_children.len() - trail..]);
+  for (old, new) in trailing {
+    diff(old, new, edit, out);
+  }
+}
+
+/// The outermost nodes of `new` that differ from `old` in kind, extent, children or,
+/// for leaves, text, in document order. Unlike the changed ranges of tree-sitter,
+/// leaves whose text was edited count as changed. A node is only descended into when
+/// the edit touches it, a parent stands for the children an edit removed.
+pub fn changed_nodes<'r>(
+  old: &OldNode,
+  new: Node<'r, ts::Language>,
+  src: &'r str,
+  lines: &'r LineIndex,
+  edit: &RangeEdit,
+) -> Vec<Node<'r, ts::Language>> {
+  let new = SourceNode {
+    node: new,
+    src,
+    lines,
+  };
+  let mut ret = vec![];
+  diff(old, &new, edit, &mut ret);
+  ret.into_iter().map(|n| n.node).collect()
+}
+
+impl ChangedRange {
+  pub fn new(node: &Node<ts::Language>, lines: &LineIndex) -> Self {
+    let range = node.range();
+    Self {
+      range: match_range(node),
+      start_offset: lines.js_index(range.start),
+      end_offset: lines.js_index(range.end),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::cell::Cell;
+
+  thread_local! {
+    // how often the diff listed children, to tell it did not descend
+    static LISTED: Cell<usize> = const { Cell::new(0) };
+  }
+
+  #[derive(Clone, Debug, PartialEq)]
+  struct Fake {
+    kind: u16,
+    range: Range<usize>,
+    text: String,
+    children: Vec<Fake>,
+  }
+
+  impl Tree for Fake {
+    fn kind_id(&self) -> u16 {
+      self.kind
+    }
+    fn range(&self) -> Range<usize> {
+      self.range.clone()
+    }
+    fn children(&self) -> Vec<Self> {
+      LISTED.with(|l| l.set(l.get() + 1));
+      self.children.clone()
+    }
+    fn text(&self) -> String {
+      self.text.clone()
+    }
+  }
+
+  fn leaf(kind: u16, start: usize, text: &str) -> Fake {
+    Fake {
+      kind,
+      range: start..start + text.len(),
+      text: text.into(),
+      children: vec![],
+    }
+  }
+
+  fn node(kind: u16, end: usize, children: Vec<Fake>) -> Fake {
+    Fake {
+      kind,
+      range: 0..end,
+      text: String::new(),
+      children,
+    }
+  }
+
+  fn edit(position: usize, deleted: usize, inserted: usize) -> RangeEdit {
+    RangeEdit {
+      position,
+      deleted,
+      inserted,
+    }
+  }
+
+  fn changed(old: &Fake, new: &Fake, edit: &RangeEdit) -> Vec<Fake> {
+    let old = OldNode::of(old, edit);
+    let mut ret = vec![];
+    diff(&old, new, edit, &mut ret);
+    ret
+  }
+
+  // `a b`
+  fn tree() -> Fake {
+    node(0, 3, vec![leaf(1, 0, "a"), leaf(1, 2, "b")])
+  }
+
+  #[test]
+  fn test_insert_at_edges() {
+    // `a b c`
+    let new = node(
+      0,
+      5,
+      vec![leaf(1, 0, "a"), leaf(1, 2, "b"), leaf(1, 4, "c")],
+    );
+    assert_eq!(changed(&tree(), &new, &edit(3, 0, 2)), [leaf(1, 4, "c")]);
+    // `c a b`
+    let new = node(
+      0,
+      5,
+      vec![leaf(1, 0, "c"), leaf(1, 2, "a"), leaf(1, 4, "b")],
+    );
+    assert_eq!(changed(&tree(), &new, &edit(0, 0, 2)), [leaf(1, 0, "c")]);
+  }
+
+  #[test]
+  fn test_delete_at_edges() {
+    // the root stands for the removed child
+    let new = node(0, 1, vec![leaf(1, 0, "a")]);
+    assert_eq!(changed(&tree(), &new, &edit(1, 2, 0)), vec![new]);
+    let new = node(0, 1, vec![leaf(1, 0, "b")]);
+    assert_eq!(changed(&tree(), &new, &edit(0, 2, 0)), vec![new]);
+  }
+
+  #[test]
+  fn test_edited_text() {
+    let new = node(0, 3, vec![leaf(1, 0, "a"), leaf(1, 2, "c")]);
+    assert_eq!(changed(&tree(), &new, &edit(2, 1, 1)), [leaf(1, 2, "c")]);
+    // the same text again
+    assert!(changed(&tree(), &tree(), &edit(2, 1, 1)).is_empty());
+    let new = node(0, 3, vec![leaf(1, 0, "a"), leaf(2, 2, "b")]);
+    assert_eq!(changed(&tree(), &new, &edit(2, 1, 1)), [leaf(2, 2, "b")]);
+  }
+
+  #[test]
+  fn test_untouched_subtrees() {
+    // `(a b) c`, editing `c` lists the children of the roots and of the old `c` only
+    let old = node(
+      0,
+      7,
+      vec![
+        node(2, 5, vec![leaf(1, 1, "a"), leaf(1, 3, "b")]),
+        leaf(1, 6, "c"),
+      ],
+    );
+    let mut new = old.clone();
+    new.children[1] = leaf(1, 6, "d");
+    LISTED.with(|l| l.set(0));
+    assert_eq!(changed(&old, &new, &edit(6, 1, 1)), [leaf(1, 6, "d")]);
+    assert_eq!(LISTED.with(Cell::get), 3);
+  }
+}
@@ -0,0 +1,192 @@
+use crate::context::{global, Context};
+use crate::editor::report_matches;
+use crate::error::{tag, Code};
+use crate::lines::LineIndex;
+use crate::types::ConfigArray;
+use crate::utils;
+use crate::{serialize, SourceFile};
+
+use ast_grep_config::Severity;
+use ast_grep_core::meta_var::{MatchResult, MetaVarEnv};
+use ast_grep_core::{MetaVariable, Node};
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+struct Offsets {
+  start: usize,
+  end: usize,
+}
+
+#[derive(Serialize)]
+struct Position {
+  line: usize,
+  column: usize,
+}
+
+/// `byteOffset` is in UTF-8 bytes and positions are zero based, like the CLI.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Range {
+  byte_offset: Offsets,
+  start: Position,
+  end: Position,
+}
+
+impl Range {
+  fn of(node: &Node<ts::Language>, lines: &LineIndex) -> Self {
+    let range = node.range();
+    let (start, end) = (node.start_pos(), node.end_pos());
+    Self {
+      byte_offset: Offsets {
+        start: lines.byte_of(range.start),
+        end: lines.byte_of(range.end),
+      },
+      start: Position {
+        line: start.0,
+        column: start.1,
+      },
+      end: Position {
+        line: end.0,
+        column: end.1,
+      },
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct Capture {
+  text: String,
+  range: Range,
+}
+
+impl Capture {
+  fn of(node: &Node<ts::Language>, lines: &LineIndex) -> Self {
+    Self {
+      text: node.text().into_owned(),
+      range: Range::of(node, lines),
+    }
+  }
+}
+
+#[derive(Default, Serialize)]
+struct MetaVariables {
+  single: BTreeMap<String, Capture>,
+  multi: BTreeMap<String, Vec<Capture>>,
+  /// transforms are not supported, see `getCapabilities`
+  transformed: BTreeMap<String, String>,
+}
+
+impl MetaVariables {
+  fn of(env: &MetaVarEnv<ts::Language>, names: &[String], lines: &LineIndex) -> Self {
+    let mut ret = Self::default();
+    for name in names {
+      if let Some(MatchResult::Single(node)) = env.get(&MetaVariable::Named(name.clone())) {
+        ret.single.insert(name.clone(), Capture::of(node, lines));
+      }
+      let multi = env.get(&MetaVariable::NamedEllipsis(name.clone()));
+      if let Some(MatchResult::Multi(nodes)) = multi {
+        let captures = nodes.iter().map(|n| Capture::of(n, lines)).collect();
+        ret.multi.insert(name.clone(), captures);
+      }
+    }
+    ret
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CliMatch<'a> {
+  text: String,
+  range: Range,
+  file: &'a str,
+  /// the whole lines the match spans
+  lines: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  replacement: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  replacement_offsets: Option<Offsets>,
+  language: &'a str,
+  meta_variables: MetaVariables,
+  rule_id: Option<&'a str>,
+  severity: &'a Severity,
+  note: Option<&'a str>,
+  message: &'a str,
+}
+
+/// The whole lines of `src` around `start..end`, without the last line break.
+fn lines_of(src: &str, start: usize, end: usize) -> &str {
+  let from = src[..start].rfind('\n').map_or(0, |i| i + 1);
+  let to = src[end..].find('\n').map_or(src.len(), |i| end + i);
+  &src[from..to]
+}
+
+/// Scan `[{ name, src }]` like `scanMany` into the output of `sg scan --json`, one flat array of
+/// `{ text, range, file, lines, replacement?, replacementOffsets?, language, metaVariables,
+/// ruleId, severity, note, message }` with the CLI's field names and range conventions,
+/// so consumers of the CLI output can switch to this module as is.
+/// `ruleId`, `severity` and `message` come from the configs, `severity` defaults to `hint` like the CLI.
+/// `language` is the name the grammar was registered with, e.g. `javascript` where the CLI says
+/// `JavaScript`, and `metaVariables.transformed` is always empty.
+#[wasm_bindgen(js_name = scanJson)]
+pub fn scan_json(sources: JsValue, configs: ConfigArray) -> Result<JsValue, JsValue> {
+  global().scan_json(sources, configs.into())
+}
+
+impl Context {
+  pub fn scan_json(&self, sources: JsValue, configs: js_sys::Array) -> Result<JsValue, JsValue> {
+    utils::enter("scanJson", 0);
+    let sources: Vec<SourceFile> =
+      serde_wasm_bindgen::from_value(sources).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let language = match rules.first() {
+      Some(rule) => self.language_name_of(&rule.lang),
+      None => None,
+    };
+    let language = language.as_deref().unwrap_or("unknown");
+    let ret = js_sys::Array::new();
+    for source in &sources {
+      utils::enter("scanJson", source.src.len());
+      let src = &source.src;
+      report_matches(&rules, src, |report| {
+        let rule = report.rule;
+        let node = &report.node;
+        let lines = report.lines;
+        let range = node.range();
+        let edit = rule.fixer.as_ref().map(|fixer| node.replace_by(fixer));
+        let matched = CliMatch {
+          text: node.text().into_owned(),
+          range: Range::of(node, lines),
+          file: &source.name,
+          lines: lines_of(src, lines.byte_of(range.start), lines.byte_of(range.end)),
+          replacement_offsets: edit.as_ref().map(|e| Offsets {
+            start: lines.byte_of(e.position),
+            end: lines.byte_of(e.position + e.deleted_length),
+          }),
+          replacement: edit.map(|e| e.inserted_text),
+          language,
+          meta_variables: MetaVariables::of(node.get_env(), &rule.captures, lines),
+          rule_id: rule.id.as_deref(),
+          severity: rule.severity.as_ref().unwrap_or(&Severity::Hint),
+          note: None,
+          message: rule.message.as_deref().unwrap_or_default(),
+        };
+        ret.push(&serialize::to_json_value(&matched)?);
+        Ok(())
+      })?;
+    }
+    Ok(ret.into())
+  }
+
+  /// The name `language` was registered with, `None` if it was set up from bytes.
+  pub fn language_name_of(&self, language: &ts::Language) -> Option<String> {
+    let languages = self.languages.borrow();
+    let mut names = languages.registered.iter();
+    names
+      .find(|(_, lang)| ***lang == *language)
+      .map(|(name, _)| name.clone())
+  }
+}
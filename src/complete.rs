@@ -0,0 +1,306 @@
+use crate::context::{global, Context};
+use crate::error::{error_with, Code};
+use crate::lines::LineIndex;
+use crate::types::Completions;
+use crate::validate::KNOWN_KEYS;
+use crate::{serialize, utils};
+
+use ast_grep_config::{SerializableMetaVarMatcher, SerializableRule};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::{forward_to_deserialize_any, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+const RELATION_KEYS: [&str; 2] = ["until", "immediate"];
+
+/// Records the variants serde declares for an enum, then errs to stop deserializing.
+struct Variants<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for Variants<'a> {
+  type Error = de::value::Error;
+
+  fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+    Err(de::Error::custom("not an enum"))
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _: &'static str,
+    variants: &'static [&'static str],
+    _: V,
+  ) -> Result<V::Value, Self::Error> {
+    *self.0 = variants;
+    Err(de::Error::custom("only the variants are read"))
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+    option unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+  }
+}
+
+/// The keys of an externally tagged enum like `SerializableRule`, so only keys
+/// the ast-grep-config of this build accepts are offered.
+fn variants<T: DeserializeOwned>() -> &'static [&'static str] {
+  let mut variants: &'static [&'static str] = &[];
+  let _ = T::deserialize(Variants(&mut variants));
+  variants
+}
+
+#[derive(Serialize)]
+struct Completion {
+  label: String,
+  /// `keyword` for keys, `type` for kinds, `property` for fields and `enum` for other values
+  #[serde(rename = "type")]
+  kind: &'static str,
+  /// the text to insert if not `label`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  apply: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CompletionList {
+  /// the word the completions replace, as JS string indices
+  from: usize,
+  to: usize,
+  options: Vec<Completion>,
+}
+
+/// A line split into its indentation, counting `- ` of sequence items, and the rest.
+fn split_indent(line: &str) -> (usize, &str) {
+  let mut rest = line.trim_start_matches(' ');
+  let mut indent = line.len() - rest.len();
+  while let Some(item) = rest.strip_prefix("- ") {
+    let trimmed = item.trim_start_matches(' ');
+    indent += rest.len() - trimmed.len();
+    rest = trimmed;
+  }
+  (indent, rest)
+}
+
+/// The key of a `key: value` or `key:` line.
+fn key_of(rest: &str) -> Option<&str> {
+  let key = rest.split(':').next()?.trim();
+  let is_key = rest.contains(':') && !key.is_empty() && !key.starts_with('#');
+  is_key.then_some(key)
+}
+
+/// Keys of the mappings enclosing a line of `indent`, outermost first.
+fn parents<'a>(lines: &[&'a str], indent: usize) -> Vec<&'a str> {
+  let mut ret = vec![];
+  let mut limit = indent;
+  for line in lines.iter().rev() {
+    if limit == 0 {
+      break;
+    }
+    let (indent, rest) = split_indent(line);
+    if rest.is_empty() || rest.starts_with('#') || indent >= limit {
+      continue;
+    }
+    if let Some(key) = key_of(rest) {
+      ret.push(key);
+    }
+    limit = indent;
+  }
+  ret.reverse();
+  ret
+}
+
+fn is_word(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+fn keys(keys: &[&str]) -> Vec<Completion> {
+  keys
+    .iter()
+    .map(|key| Completion {
+      label: key.to_string(),
+      kind: "keyword",
+      apply: Some(format!("{}: ", key)),
+    })
+    .collect()
+}
+
+fn values(values: &[&str]) -> Vec<Completion> {
+  let values = values.iter().map(|v| v.to_string()).collect();
+  named(values, "enum")
+}
+
+fn named(labels: Vec<String>, kind: &'static str) -> Vec<Completion> {
+  labels
+    .into_iter()
+    .map(|label| Completion {
+      label,
+      kind,
+      apply: None,
+    })
+    .collect()
+}
+
+/// The `language` of the top level of a YAML config.
+fn language_of(lines: &[&str]) -> Option<String> {
+  lines.iter().find_map(|line| {
+    let value = line.strip_prefix("language:")?.trim();
+    let value = value.trim_matches(|c| c == '"' || c == '\'');
+    (!value.is_empty()).then(|| value.to_string())
+  })
+}
+
+/// Completions at `cursorOffset`, a JS string index, of a YAML config being typed:
+/// the kinds of its language after `kind:`, the values of `severity`, `lineEndings` and flags,
+/// and elsewhere the keys that fit the enclosing mapping. Returns `{ from, to, options: [{ label, type, apply? }] }`,
+/// shaped like a completion result of CodeMirror, with the options starting with the word
+/// at the cursor. Kinds are left out when no language is known.
+#[wasm_bindgen(js_name = getCompletions)]
+pub fn get_completions(yaml: String, cursor_offset: usize) -> Result<Completions, JsValue> {
+  global()
+    .get_completions(yaml, cursor_offset)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn get_completions(&self, yaml: String, cursor_offset: usize) -> Result<JsValue, JsValue> {
+    utils::enter("getCompletions", yaml.len());
+    let index = LineIndex::new(&yaml);
+    let cursor = index.checked_byte(&yaml, cursor_offset).ok_or_else(|| {
+      let message = format!("offset {} is out of the text", cursor_offset);
+      error_with(
+        Code::InvalidArgument,
+        &message,
+        &[("offset", cursor_offset.into())],
+      )
+    })?;
+    let kinds = |name: Option<&str>| {
+      let lang = match self.get_language(name) {
+        Ok(lang) => lang,
+        Err(_) => return vec![],
+      };
+      (0..lang.node_kind_count())
+        .filter(|&id| lang.node_kind_is_named(id) && lang.node_kind_is_visible(id))
+        .filter_map(|id| lang.node_kind_for_id(id))
+        .map(|kind| kind.into_owned())
+        .collect()
+    };
+    let ret = complete(&yaml, &index, cursor, &kinds);
+    Ok(serialize::to_value(&ret)?)
+  }
+}
+
+/// The completions at byte `cursor` of `yaml`, `kinds` lists the kinds of a language.
+fn complete(
+  yaml: &str,
+  index: &LineIndex,
+  cursor: usize,
+  kinds: &dyn Fn(Option<&str>) -> Vec<String>,
+) -> CompletionList {
+  let line_start = yaml[..cursor].rfind('\n').map_or(0, |i| i + 1);
+  let line_end = yaml[cursor..].find('\n').map_or(yaml.len(), |i| cursor + i);
+  let before = &yaml[line_start..cursor];
+  let from = before
+    .char_indices()
+    .rev()
+    .take_while(|(_, c)| is_word(*c))
+    .last()
+    .map_or(cursor, |(i, _)| line_start + i);
+  let to = yaml[cursor..line_end]
+    .find(|c| !is_word(c))
+    .map_or(line_end, |i| cursor + i);
+  let word = &yaml[from..cursor];
+  let lines: Vec<_> = yaml[..line_start].lines().collect();
+  let (indent, typed) = split_indent(before);
+  let rule_keys = variants::<SerializableRule>();
+  let options = match typed.find(':') {
+    // a value, named by the key before it
+    Some(colon) => match typed[..colon].trim() {
+      "kind" => {
+        let name = language_of(&yaml.lines().collect::<Vec<_>>());
+        let mut kinds = kinds(name.as_deref());
+        kinds.sort();
+        kinds.dedup();
+        named(kinds, "type")
+      }
+      "severity" => values(&["hint", "info", "warning", "error"]),
+      "lineEndings" => values(&["preserve", "auto", "lf", "crlf"]),
+      "immediate" | "metrics" | "trace" | "coverage" => values(&["true", "false"]),
+      _ => vec![],
+    },
+    None if typed.len() > word.len() => vec![],
+    None => {
+      let parents = parents(&lines, indent);
+      match parents.as_slice() {
+        [] => keys(&KNOWN_KEYS),
+        [.., "inside" | "has" | "precedes" | "follows"] => {
+          keys(&[rule_keys, &RELATION_KEYS[..]].concat())
+        }
+        [.., "pattern"] => keys(&["context", "selector"]),
+        [.., "constraints", _] => keys(variants::<SerializableMetaVarMatcher>()),
+        // metavariable names
+        [.., "constraints"] => vec![],
+        [.., "rule" | "not" | "all" | "any" | "until"] => keys(rule_keys),
+        _ => vec![],
+      }
+    }
+  };
+  let options = options
+    .into_iter()
+    .filter(|c| c.label.starts_with(word))
+    .collect();
+  CompletionList {
+    from: index.utf16(from),
+    to: index.utf16(to),
+    options,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn labels(yaml: &str) -> Vec<String> {
+    let cursor = yaml.find('|').expect("cursor");
+    let yaml = yaml.replace('|', "");
+    let kinds = |name: Option<&str>| match name {
+      Some("js") => vec!["identifier".into(), "if_statement".into()],
+      _ => vec![],
+    };
+    let list = complete(&yaml, &LineIndex::new(&yaml), cursor, &kinds);
+    list.options.into_iter().map(|c| c.label).collect()
+  }
+
+  #[test]
+  fn test_rule_keys() {
+    let rule_keys = [
+      "all", "any", "not", "inside", "has", "precedes", "follows", "pattern", "kind",
+    ];
+    assert_eq!(variants::<SerializableRule>(), rule_keys);
+    assert_eq!(labels("rule:\n  |"), rule_keys);
+    assert_eq!(labels("rule:\n  all:\n    - p|"), ["precedes", "pattern"]);
+    assert!(!labels("rule:\n  |").contains(&"field".to_string()));
+    let relation = labels("rule:\n  has:\n    |");
+    assert_eq!(relation[rule_keys.len()..], ["until", "immediate"]);
+  }
+
+  #[test]
+  fn test_values() {
+    assert_eq!(
+      labels("constraints:\n  A:\n    |"),
+      ["regex", "pattern", "kind"]
+    );
+    assert_eq!(
+      labels("language: js\nrule:\n  kind: i|"),
+      ["identifier", "if_statement"]
+    );
+    assert!(labels("rule:\n  kind: |").is_empty());
+    assert_eq!(labels("severity: w|"), ["warning"]);
+    assert_eq!(labels("rule:\n  has:\n    immediate: |"), ["true", "false"]);
+  }
+
+  #[test]
+  fn test_word_range() {
+    let yaml = "message: é\nrule:\n  al\n";
+    let cursor = yaml.find("al").unwrap() + 1;
+    let list = complete(yaml, &LineIndex::new(yaml), cursor, &|_| vec![]);
+    // JS string indices, é is one
+    assert_eq!((list.from, list.to), (19, 21));
+  }
+}
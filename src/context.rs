@@ -0,0 +1,629 @@
+use crate::cancel::CancelSignal;
+use crate::memo::Memo;
+use crate::rule::{RuleCache, RuleHandle};
+use crate::source;
+use crate::tags::TagQueries;
+use crate::types::{
+  CaptureGroups, CodeMirrorDiagnostics, Completions, Config, ConfigArray, ConfigSource,
+  ConfigSourceArray, Diagnostics, DumpNode, DuplicateGroups, EslintResults, IdentifierMatches,
+  KindMatches, MatchRange, Matches, MetaVariableHoles, MonacoMarkers, NodeInfo, PatternMatches,
+  QuickFixDiagnostics, RuleForNode, RuleReports, ScanManyResult, ScanResult, SimilarMatches,
+  SourceInput, StringArray, SuppressionEdit, Tags,
+};
+use crate::workspace::Workspace;
+use crate::{find_language, Languages, MatchIter, MatchList, ParserSource, SgRoot};
+
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::future_to_promise;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Loaded languages, compiled rules and memoized results.
+/// The module functions share one context, every `AstGrep` instance owns another.
+#[derive(Default)]
+pub struct Context {
+  pub languages: RefCell<Languages>,
+  pub rules: RefCell<RuleCache>,
+  pub find_memo: RefCell<Memo<Vec<[usize; 4]>>>,
+  pub fix_memo: RefCell<Memo<String>>,
+  pub tags: RefCell<TagQueries>,
+}
+
+thread_local! {
+  // wasm runs single threaded, a thread local avoids locking and mutex poisoning
+  static GLOBAL: Rc<Context> = Rc::new(Context::default());
+}
+
+/// The context behind the module functions.
+pub fn global() -> Rc<Context> {
+  GLOBAL.with(Rc::clone)
+}
+
+impl Context {
+  pub fn get_language(&self, name: Option<&str>) -> Result<Rc<ts::Language>, JsValue> {
+    find_language(&self.languages.borrow(), name)
+  }
+
+  pub fn clear(&self) {
+    self.rules.borrow_mut().clear();
+    self.find_memo.borrow_mut().clear();
+    self.fix_memo.borrow_mut().clear();
+  }
+}
+
+/// An ast-grep with its own languages and caches, e.g. one per playground tab sharing a worker.
+/// Methods mirror the module functions, which work on a shared default context.
+/// The grammar loader, parse logging and serialize options stay module wide.
+#[wasm_bindgen(js_name = AstGrep)]
+pub struct Instance {
+  ctx: Rc<Context>,
+}
+
+impl Default for Instance {
+  fn default() -> Self {
+    Self {
+      ctx: Rc::new(Context::default()),
+    }
+  }
+}
+
+#[wasm_bindgen(js_class = AstGrep)]
+impl Instance {
+  #[wasm_bindgen(constructor)]
+  pub fn new() -> Instance {
+    Instance::default()
+  }
+
+  #[wasm_bindgen(js_name = setupParser)]
+  pub fn setup_parser(
+    &self,
+    source: ParserSource,
+    on_progress: Option<js_sys::Function>,
+  ) -> js_sys::Promise {
+    let ctx = self.ctx.clone();
+    future_to_promise(async move {
+      ctx.setup_parser(source.into(), on_progress).await?;
+      Ok(JsValue::UNDEFINED)
+    })
+  }
+
+  #[wasm_bindgen(js_name = registerLanguage)]
+  pub fn register_language(
+    &self,
+    lang_name: String,
+    source: Option<ParserSource>,
+    on_progress: Option<js_sys::Function>,
+  ) -> js_sys::Promise {
+    let ctx = self.ctx.clone();
+    future_to_promise(async move {
+      let source = source.map(JsValue::from);
+      ctx
+        .register_language(lang_name, source, on_progress)
+        .await?;
+      Ok(JsValue::UNDEFINED)
+    })
+  }
+
+  pub fn parse(
+    &self,
+    src: SourceInput,
+    language: Option<String>,
+    included_ranges: JsValue,
+  ) -> Result<SgRoot, JsValue> {
+    let src = source::decode(src.into())?;
+    self.ctx.clone().parse(src, language, included_ranges)
+  }
+
+  #[wasm_bindgen(js_name = parseChunks)]
+  pub fn parse_chunks(
+    &self,
+    read: js_sys::Function,
+    language: Option<String>,
+  ) -> Result<SgRoot, JsValue> {
+    self.ctx.clone().parse_chunks(read, language)
+  }
+
+  #[wasm_bindgen(js_name = findNodes)]
+  pub fn find_nodes(
+    &self,
+    src: SourceInput,
+    config: Config,
+    cancel: Option<CancelSignal>,
+  ) -> Result<Matches, JsValue> {
+    source::scan_decoded(src.into(), |src| {
+      self.ctx.find_nodes(src, config.into(), cancel)
+    })
+    .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findByPattern)]
+  pub fn find_by_pattern(
+    &self,
+    src: String,
+    pattern: String,
+    language: Option<String>,
+  ) -> Result<Matches, JsValue> {
+    self
+      .ctx
+      .find_by_pattern(src, pattern, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findByPatternPrefiltered)]
+  pub fn find_by_pattern_prefiltered(
+    &self,
+    src: String,
+    pattern: String,
+    language: Option<String>,
+  ) -> Result<Matches, JsValue> {
+    self
+      .ctx
+      .find_by_pattern_prefiltered(src, pattern, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findByPatterns)]
+  pub fn find_by_patterns(
+    &self,
+    src: String,
+    patterns: StringArray,
+    language: Option<String>,
+  ) -> Result<PatternMatches, JsValue> {
+    self
+      .ctx
+      .find_by_patterns(src, patterns.into(), language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findIdentifier)]
+  pub fn find_identifier(
+    &self,
+    src: String,
+    name: String,
+    language: Option<String>,
+  ) -> Result<IdentifierMatches, JsValue> {
+    self
+      .ctx
+      .find_identifier(src, name, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findByKind)]
+  pub fn find_by_kind(
+    &self,
+    src: String,
+    kind: String,
+    language: Option<String>,
+  ) -> Result<KindMatches, JsValue> {
+    self
+      .ctx
+      .find_by_kind(src, kind, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = replaceByPattern)]
+  pub fn replace_by_pattern(
+    &self,
+    src: String,
+    pattern: String,
+    fix: String,
+    language: Option<String>,
+  ) -> Result<String, JsValue> {
+    self.ctx.replace_by_pattern(src, pattern, fix, language)
+  }
+
+  #[wasm_bindgen(js_name = fixErrors)]
+  pub fn fix_errors(&self, src: String, config: Config) -> Result<String, JsValue> {
+    self.ctx.fix_errors(src, config.into())
+  }
+
+  #[wasm_bindgen(js_name = fixAll)]
+  pub fn fix_all(&self, src: String, configs: ConfigArray) -> Result<JsValue, JsValue> {
+    self.ctx.fix_all(src, configs.into())
+  }
+
+  #[wasm_bindgen(js_name = scanRules)]
+  pub fn scan_rules(
+    &self,
+    src: SourceInput,
+    configs: ConfigArray,
+    cancel: Option<CancelSignal>,
+  ) -> Result<ScanResult, JsValue> {
+    source::scan_decoded(src.into(), |src| {
+      self.ctx.scan_rules(src, configs.into(), cancel)
+    })
+    .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = scanMany)]
+  pub fn scan_many(
+    &self,
+    sources: JsValue,
+    configs: ConfigArray,
+    cancel: Option<CancelSignal>,
+  ) -> Result<ScanManyResult, JsValue> {
+    self
+      .ctx
+      .scan_many(sources, configs.into(), cancel)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findMatches)]
+  pub fn find_matches(
+    &self,
+    src: String,
+    config: Config,
+    cancel: Option<CancelSignal>,
+  ) -> Result<MatchList, JsValue> {
+    self.ctx.find_matches(src, config.into(), cancel)
+  }
+
+  #[wasm_bindgen(js_name = findNodesIter)]
+  pub fn find_nodes_iter(&self, src: String, config: Config) -> Result<MatchIter, JsValue> {
+    self.ctx.find_nodes_iter(src, config.into())
+  }
+
+  #[wasm_bindgen(js_name = findNodesEach)]
+  pub fn find_nodes_each(
+    &self,
+    src: String,
+    config: Config,
+    callback: js_sys::Function,
+    cancel: Option<CancelSignal>,
+  ) -> Result<bool, JsValue> {
+    self
+      .ctx
+      .find_nodes_each(src, config.into(), callback, cancel)
+  }
+
+  #[wasm_bindgen(js_name = compileRule)]
+  pub fn compile_rule(&self, config: Config) -> Result<RuleHandle, JsValue> {
+    let inner = self.ctx.compile_config(config.into())?;
+    Ok(RuleHandle::new(inner))
+  }
+
+  #[wasm_bindgen(js_name = validateRule)]
+  pub fn validate_rule(&self, config: ConfigSource) -> Result<Diagnostics, JsValue> {
+    self
+      .ctx
+      .validate_rule(config.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = decodeState)]
+  pub fn decode_state(&self, encoded: String) -> js_sys::Promise {
+    let ctx = self.ctx.clone();
+    future_to_promise(async move { ctx.decode_state(encoded).await })
+  }
+
+  #[wasm_bindgen(js_name = getMetaVariables)]
+  pub fn get_meta_variables(&self, config: ConfigSource) -> Result<MetaVariableHoles, JsValue> {
+    self
+      .ctx
+      .get_meta_variables(config.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = instantiatePattern)]
+  pub fn instantiate_pattern(
+    &self,
+    pattern: String,
+    bindings: JsValue,
+    language: Option<String>,
+  ) -> Result<String, JsValue> {
+    self.ctx.instantiate_pattern(pattern, bindings, language)
+  }
+
+  #[wasm_bindgen(js_name = suggestPattern)]
+  pub fn suggest_pattern(
+    &self,
+    src: String,
+    range: MatchRange,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    self
+      .ctx
+      .suggest_pattern(src, range.into(), options, language)
+  }
+
+  #[wasm_bindgen(js_name = getCompletions)]
+  pub fn get_completions(
+    &self,
+    yaml: String,
+    cursor_offset: usize,
+  ) -> Result<Completions, JsValue> {
+    self
+      .ctx
+      .get_completions(yaml, cursor_offset)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = getNodeInfo)]
+  pub fn get_node_info(
+    &self,
+    src: String,
+    id: usize,
+    language: Option<String>,
+  ) -> Result<NodeInfo, JsValue> {
+    self
+      .ctx
+      .get_node_info(src, id, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = collectCaptures)]
+  pub fn collect_captures(
+    &self,
+    src: String,
+    config: Config,
+    name: String,
+  ) -> Result<StringArray, JsValue> {
+    self
+      .ctx
+      .collect_captures(src, config.into(), name)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = groupByCapture)]
+  pub fn group_by_capture(
+    &self,
+    src: String,
+    config: Config,
+    name: String,
+  ) -> Result<CaptureGroups, JsValue> {
+    self
+      .ctx
+      .group_by_capture(src, config.into(), name)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findSimilar)]
+  pub fn find_similar(
+    &self,
+    src: String,
+    pattern: String,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<SimilarMatches, JsValue> {
+    self
+      .ctx
+      .find_similar(src, pattern, options, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findDuplicates)]
+  pub fn find_duplicates(
+    &self,
+    src: String,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<DuplicateGroups, JsValue> {
+    self
+      .ctx
+      .find_duplicates(src, options, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = ruleForNode)]
+  pub fn rule_for_node(
+    &self,
+    src: String,
+    id: usize,
+    language: Option<String>,
+  ) -> Result<RuleForNode, JsValue> {
+    self
+      .ctx
+      .rule_for_node(src, id, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = loadTagsQuery)]
+  pub fn load_tags_query(&self, query: String, language: Option<String>) -> Result<(), JsValue> {
+    self.ctx.load_tags_query(query, language)
+  }
+
+  #[wasm_bindgen(js_name = extractTags)]
+  pub fn extract_tags(&self, src: String, language: Option<String>) -> Result<Tags, JsValue> {
+    self
+      .ctx
+      .extract_tags(src, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  /// An empty `Workspace` using the languages of this instance.
+  #[wasm_bindgen(js_name = createWorkspace)]
+  pub fn create_workspace(&self) -> Workspace {
+    Workspace::new(self.ctx.clone())
+  }
+
+  #[wasm_bindgen(js_name = lintRule)]
+  pub fn lint_rule(&self, config: ConfigSource, options: JsValue) -> Result<Diagnostics, JsValue> {
+    self
+      .ctx
+      .lint_rule(config.into(), options)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = checkRuleset)]
+  pub fn check_ruleset(&self, configs: ConfigSourceArray) -> Result<RuleReports, JsValue> {
+    self
+      .ctx
+      .check_ruleset(configs.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = lintDiagnostics)]
+  pub fn lint_diagnostics(
+    &self,
+    src: String,
+    configs: ConfigArray,
+  ) -> Result<CodeMirrorDiagnostics, JsValue> {
+    self
+      .ctx
+      .lint_diagnostics(src, configs.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = quickFixes)]
+  pub fn quick_fixes(
+    &self,
+    src: String,
+    configs: ConfigArray,
+  ) -> Result<QuickFixDiagnostics, JsValue> {
+    self
+      .ctx
+      .quick_fixes(src, configs.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = suppressionEdit)]
+  pub fn suppression_edit(
+    &self,
+    src: String,
+    diagnostic: JsValue,
+    language: Option<String>,
+  ) -> Result<SuppressionEdit, JsValue> {
+    self
+      .ctx
+      .suppression_edit(src, diagnostic, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = monacoMarkers)]
+  pub fn monaco_markers(
+    &self,
+    src: String,
+    configs: ConfigArray,
+  ) -> Result<MonacoMarkers, JsValue> {
+    self
+      .ctx
+      .monaco_markers(src, configs.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = eslintResults)]
+  pub fn eslint_results(
+    &self,
+    sources: JsValue,
+    configs: ConfigArray,
+  ) -> Result<EslintResults, JsValue> {
+    self
+      .ctx
+      .eslint_results(sources, configs.into())
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = scanJson)]
+  pub fn scan_json(&self, sources: JsValue, configs: ConfigArray) -> Result<JsValue, JsValue> {
+    self.ctx.scan_json(sources, configs.into())
+  }
+
+  #[wasm_bindgen(js_name = githubCommands)]
+  pub fn github_commands(&self, sources: JsValue, configs: ConfigArray) -> Result<String, JsValue> {
+    self.ctx.github_commands(sources, configs.into())
+  }
+
+  #[wasm_bindgen(js_name = githubAnnotations)]
+  pub fn github_annotations(
+    &self,
+    sources: JsValue,
+    configs: ConfigArray,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.github_annotations(sources, configs.into())
+  }
+
+  #[wasm_bindgen(js_name = lspDiagnostics)]
+  pub fn lsp_diagnostics(
+    &self,
+    uri: String,
+    src: String,
+    configs: ConfigArray,
+    version: Option<i32>,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.lsp_diagnostics(uri, src, configs.into(), version)
+  }
+
+  #[wasm_bindgen(js_name = lspCodeActions)]
+  pub fn lsp_code_actions(
+    &self,
+    uri: String,
+    src: String,
+    configs: ConfigArray,
+    range: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.lsp_code_actions(uri, src, configs.into(), range)
+  }
+
+  #[wasm_bindgen(js_name = runCatalogExample)]
+  pub fn run_catalog_example(&self, example: JsValue) -> Result<JsValue, JsValue> {
+    self.ctx.run_catalog_example(example)
+  }
+
+  #[wasm_bindgen(js_name = testRule)]
+  pub fn test_rule(
+    &self,
+    config: Config,
+    cases: JsValue,
+    snapshots: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.test_rule(config.into(), cases, snapshots)
+  }
+
+  #[wasm_bindgen(js_name = assertMatches)]
+  pub fn assert_matches(
+    &self,
+    src: String,
+    config: Config,
+    expected_ranges: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.assert_matches(src, config.into(), expected_ranges)
+  }
+
+  #[wasm_bindgen(js_name = assertFixed)]
+  pub fn assert_fixed(
+    &self,
+    src: String,
+    config: Config,
+    expected_output: String,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.assert_fixed(src, config.into(), expected_output)
+  }
+
+  #[wasm_bindgen(js_name = debugQuery)]
+  pub fn debug_query(&self, config: Config) -> Result<JsValue, JsValue> {
+    self.ctx.debug_query(config.into())
+  }
+
+  #[wasm_bindgen(js_name = explainNoMatch)]
+  pub fn explain_no_match(
+    &self,
+    src: String,
+    config: Config,
+    position: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    self.ctx.explain_no_match(src, config.into(), position)
+  }
+
+  #[wasm_bindgen(js_name = dumpASTNodes)]
+  pub fn dump_ast_nodes(&self, src: String, language: Option<String>) -> Result<DumpNode, JsValue> {
+    self
+      .ctx
+      .dump_ast_nodes(src, language)
+      .map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = getKindTable)]
+  pub fn get_kind_table(&self, language: Option<String>) -> Result<Vec<JsValue>, JsValue> {
+    self.ctx.get_kind_table(language)
+  }
+
+  /// Drop this instance's compiled rule cache and memoized results.
+  #[wasm_bindgen(js_name = disposeAll)]
+  pub fn dispose_all(&self) {
+    self.ctx.clear();
+  }
+}
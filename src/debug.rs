@@ -0,0 +1,201 @@
+use crate::context::{global, Context};
+use crate::kinds;
+use crate::rule::{pattern_node, potential_kinds, rule_key, split_relation};
+use crate::types::Config;
+use crate::validate::check_compilable;
+use crate::{serialize, shape, utils};
+
+use ast_grep_config::{PatternStyle, SerializableMetaVarMatcher, SerializableRule};
+use ast_grep_core::language::Language;
+use ast_grep_core::{KindMatcher, MetaVariable, Node};
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+/// A node of a parsed pattern, metavariables are slots matching any node.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PatternNode {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  kind: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  kind_id: Option<u16>,
+  /// e.g. `$A`, `$$$ARGS` or `$_`, only for metavariables
+  #[serde(skip_serializing_if = "Option::is_none")]
+  meta_var: Option<String>,
+  /// the text a leaf must equal, only for leaves other than metavariables
+  #[serde(skip_serializing_if = "Option::is_none")]
+  text: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  children: Vec<PatternNode>,
+}
+
+/// A rule as the matcher reads it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Query {
+  /// `all`, `any`, `not`, `inside`, `has`, `precedes`, `follows`, `pattern` or `kind`
+  rule: &'static str,
+  /// kinds of the nodes the rule can match, absent if it can match any
+  #[serde(skip_serializing_if = "Option::is_none")]
+  kinds: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pattern: Option<PatternNode>,
+  /// the kind a contextual pattern selects from its context
+  #[serde(skip_serializing_if = "Option::is_none")]
+  selector: Option<String>,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  immediate: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  until: Option<Box<Query>>,
+  /// sub-rules of `all`, `any` and `not`, or the rule of the related node
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  children: Vec<Query>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Constraint {
+  meta_var: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  regex: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  kind: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pattern: Option<PatternNode>,
+}
+
+#[derive(Serialize)]
+struct Debug {
+  query: Query,
+  constraints: Vec<Constraint>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fix: Option<PatternNode>,
+}
+
+/// Describe how a config is interpreted without running it, returning `{ query, constraints, fix? }`.
+/// `query` is the rule tree with the kinds each rule can match and its patterns parsed,
+/// where metavariables show as `metaVar` slots and other leaves with the `text` they must equal.
+#[wasm_bindgen(js_name = debugQuery)]
+pub fn debug_query(config: Config) -> Result<JsValue, JsValue> {
+  global().debug_query(config.into())
+}
+
+impl Context {
+  pub fn debug_query(&self, config: JsValue) -> Result<JsValue, JsValue> {
+    utils::enter("debugQuery", 0);
+    let config = shape::read_config(config)?;
+    let lang = self.get_language(config.language.as_deref())?;
+    // describing parses the patterns, which panics on the ones compiling rejects
+    check_compilable(&config, &lang)?;
+    let mut constraints: Vec<_> = config
+      .constraints
+      .into_iter()
+      .flatten()
+      .map(|(meta_var, matcher)| describe_constraint(meta_var, matcher, &lang))
+      .collect();
+    // constraints are a HashMap, sort for stable output
+    constraints.sort_by(|a, b| a.meta_var.cmp(&b.meta_var));
+    let ret = Debug {
+      query: describe(&config.rule, &lang)?,
+      constraints,
+      fix: config.fix.map(|fix| parse_pattern(&fix, &lang)),
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+}
+
+fn describe(rule: &SerializableRule, lang: &ts::Language) -> Result<Query, JsValue> {
+  use SerializableRule as S;
+  let kinds = potential_kinds(rule, lang).map(|kinds| {
+    let ts_lang = lang.get_ts_language();
+    kinds
+      .into_iter()
+      .filter_map(|id| ts_lang.node_kind_for_id(id).map(|k| k.into_owned()))
+      .collect()
+  });
+  let mut query = Query {
+    rule: rule_key(rule),
+    kinds,
+    pattern: None,
+    selector: None,
+    immediate: false,
+    until: None,
+    children: vec![],
+  };
+  match rule {
+    S::All(rules) | S::Any(rules) => {
+      query.children = rules
+        .iter()
+        .map(|r| describe(r, lang))
+        .collect::<Result<_, _>>()?;
+    }
+    S::Not(rule) => query.children = vec![describe(rule, lang)?],
+    S::Inside(r) | S::Has(r) | S::Precedes(r) | S::Follows(r) => {
+      let relation = split_relation(r)?;
+      query.immediate = relation.immediate;
+      query.children = vec![describe(&relation.rule, lang)?];
+      if let Some(until) = &relation.until {
+        query.until = Some(Box::new(describe(until, lang)?));
+      }
+    }
+    S::Pattern(PatternStyle::Str(pattern)) => query.pattern = Some(parse_pattern(pattern, lang)),
+    S::Pattern(PatternStyle::Contextual { context, selector }) => {
+      let root = lang.ast_grep(lang.pre_process_pattern(context));
+      let selected = root.root().find(KindMatcher::new(selector, lang.clone()));
+      query.pattern = selected.map(|n| pattern_tree(n.into(), lang));
+      query.selector = Some(selector.clone());
+    }
+    S::Kind(_) => (),
+  }
+  Ok(query)
+}
+
+fn describe_constraint(
+  meta_var: String,
+  matcher: SerializableMetaVarMatcher,
+  lang: &ts::Language,
+) -> Constraint {
+  use SerializableMetaVarMatcher as M;
+  let mut constraint = Constraint {
+    meta_var: format!("${}", meta_var),
+    regex: None,
+    kind: None,
+    pattern: None,
+  };
+  match matcher {
+    M::Regex(regex) => constraint.regex = Some(regex),
+    M::Kind(kind) => constraint.kind = Some(kind),
+    M::Pattern(pattern) => constraint.pattern = Some(parse_pattern(&pattern, lang)),
+  }
+  constraint
+}
+
+fn parse_pattern(pattern: &str, lang: &ts::Language) -> PatternNode {
+  let root = lang.ast_grep(lang.pre_process_pattern(pattern));
+  pattern_tree(pattern_node(root.root()), lang)
+}
+
+fn pattern_tree(node: Node<ts::Language>, lang: &ts::Language) -> PatternNode {
+  use MetaVariable as M;
+  let meta_var = match node.is_leaf() {
+    true => lang.extract_meta_var(&node.text()),
+    false => None,
+  };
+  // shown with `$`, whatever the language substitutes for it before parsing
+  let meta_var = meta_var.map(|m| match m {
+    M::Named(name) => format!("${}", name),
+    M::NamedEllipsis(name) => format!("$$${}", name),
+    M::Anonymous => "$_".into(),
+    M::Ellipsis => "$$$".into(),
+  });
+  let text = (node.is_leaf() && meta_var.is_none()).then(|| node.text().into_owned());
+  let (kind, kind_id) = kinds::kind_of(&node);
+  PatternNode {
+    kind,
+    kind_id,
+    meta_var,
+    text,
+    children: node.children().map(|n| pattern_tree(n, lang)).collect(),
+  }
+}
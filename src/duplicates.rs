@@ -0,0 +1,143 @@
+use crate::context::{global, Context};
+use crate::error::{tag, Code};
+use crate::types::DuplicateGroups;
+use crate::{limits, match_range, parse_root, serialize, utils};
+
+use ast_grep_core::Node;
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct DuplicateOptions {
+  /// the fewest nodes, named or not, a copy must have
+  min_nodes: usize,
+  /// treat subtrees differing only in identifiers as copies
+  ignore_identifiers: bool,
+}
+
+impl Default for DuplicateOptions {
+  fn default() -> Self {
+    Self {
+      min_nodes: 10,
+      ignore_identifiers: false,
+    }
+  }
+}
+
+struct Subtree {
+  size: usize,
+  bytes: Range<usize>,
+  range: [usize; 4],
+  kind: String,
+}
+
+#[derive(Serialize)]
+struct Group {
+  /// nodes in each copy
+  nodes: usize,
+  kind: String,
+  ranges: Vec<[usize; 4]>,
+}
+
+/// Hash the structure of `node`, pushing its subtrees of `min_nodes` or more to `out`.
+/// Returns the hash and the number of nodes.
+fn visit(
+  node: &Node<ts::Language>,
+  options: &DuplicateOptions,
+  out: &mut HashMap<u64, Vec<Subtree>>,
+) -> (u64, usize) {
+  let mut hasher = DefaultHasher::new();
+  node.kind_id().hash(&mut hasher);
+  let mut size = 1;
+  if node.is_leaf() {
+    let identifier = node.is_named() && node.kind().ends_with("identifier");
+    if !(options.ignore_identifiers && identifier) {
+      node.text().hash(&mut hasher);
+    }
+  } else {
+    for child in node.children() {
+      let (hash, child_size) = visit(&child, options, out);
+      hash.hash(&mut hasher);
+      size += child_size;
+    }
+  }
+  let hash = hasher.finish();
+  if size >= options.min_nodes {
+    out.entry(hash).or_default().push(Subtree {
+      size,
+      bytes: node.range(),
+      range: match_range(node),
+      kind: node.kind().into_owned(),
+    });
+  }
+  (hash, size)
+}
+
+/// Groups of structurally identical subtrees of `src`, a copy-paste detector,
+/// `[{ nodes, kind, ranges }]` with the largest copies first. Trees are compared token by
+/// token, so layout does not matter, and copies inside larger copies are left out.
+/// `options` is `{ minNodes?: 10, ignoreIdentifiers?: false }`, the latter to also group
+/// copies that differ only in identifiers.
+#[wasm_bindgen(js_name = findDuplicates)]
+pub fn find_duplicates(
+  src: String,
+  options: JsValue,
+  language: Option<String>,
+) -> Result<DuplicateGroups, JsValue> {
+  global()
+    .find_duplicates(src, options, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn find_duplicates(
+    &self,
+    src: String,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findDuplicates", src.len());
+    let options: DuplicateOptions = if options.is_undefined() || options.is_null() {
+      DuplicateOptions::default()
+    } else {
+      serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+    };
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    let mut subtrees = HashMap::new();
+    visit(&root.root(), &options, &mut subtrees);
+    let mut groups: Vec<_> = subtrees
+      .into_values()
+      .filter(|copies| copies.len() > 1)
+      .collect();
+    groups.sort_by_key(|copies| (std::cmp::Reverse(copies[0].size), copies[0].bytes.start));
+    let mut reported: Vec<Range<usize>> = vec![];
+    let mut ret = vec![];
+    for copies in groups {
+      let inside = |s: &Subtree| {
+        reported
+          .iter()
+          .any(|r| r.start <= s.bytes.start && s.bytes.end <= r.end)
+      };
+      if copies.iter().all(inside) {
+        continue;
+      }
+      limits::check_matches(ret.len() + 1)?;
+      reported.extend(copies.iter().map(|s| s.bytes.clone()));
+      ret.push(Group {
+        nodes: copies[0].size,
+        kind: copies[0].kind.clone(),
+        ranges: copies.iter().map(|s| s.range).collect(),
+      });
+    }
+    Ok(serialize::to_value(&ret)?)
+  }
+}
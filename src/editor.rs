@@ -0,0 +1,356 @@
+use crate::context::{global, Context};
+use crate::error::{tag, Code};
+use crate::limits;
+use crate::lines::LineIndex;
+use crate::rule::CompiledRule;
+use crate::suppress;
+use crate::types::{
+  CodeMirrorDiagnostics, ConfigArray, EslintResults, MonacoMarkers, QuickFixDiagnostics,
+};
+use crate::utils;
+use crate::{parse_root, serialize, SourceFile};
+
+use ast_grep_config::Severity;
+use ast_grep_core::NodeMatch;
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::rc::Rc;
+
+// the action has to be a JS closure, CodeMirror calls it with the positions mapped through later edits
+#[wasm_bindgen(inline_js = "
+export function fix_action(insert) {
+  return { name: 'Fix', apply: (view, from, to) => view.dispatch({ changes: { from, to, insert } }) }
+}")]
+extern "C" {
+  fn fix_action(insert: &str) -> JsValue;
+}
+
+/// A match of one of the configs scanned by an editor adapter.
+pub struct Report<'a, 'r> {
+  pub rule: &'a CompiledRule,
+  pub node: NodeMatch<'r, ts::Language>,
+  pub severity: &'a Severity,
+  pub message: &'a str,
+  pub source: Option<&'a str>,
+  /// of the scanned source, to convert the offsets of `node`
+  pub lines: &'a LineIndex,
+}
+
+/// Call `report` with each match of `rules` in `src`, in config order,
+/// leaving out the matches an `ast-grep-ignore` comment suppresses.
+pub fn report_matches(
+  rules: &[Rc<CompiledRule>],
+  src: &str,
+  mut report: impl FnMut(Report) -> Result<(), JsValue>,
+) -> Result<(), JsValue> {
+  let lang = match rules.first() {
+    Some(rule) => rule.lang.clone(),
+    None => return Ok(()),
+  };
+  let root = parse_root(src, &lang)?;
+  let lines = LineIndex::new(src);
+  let mut count = 0;
+  for (i, rule) in rules.iter().enumerate() {
+    let fallback = match &rule.id {
+      Some(id) => format!("matched by {}", id),
+      None => format!("matched by config {}", i),
+    };
+    for node in root.root().find_all(&**rule) {
+      let at = lines.byte_of(node.range().start);
+      if suppress::is_suppressed(src, at, rule.id.as_deref()) {
+        continue;
+      }
+      count += 1;
+      limits::check_matches(count)?;
+      report(Report {
+        rule,
+        node,
+        severity: rule.severity.as_ref().unwrap_or(&Severity::Warning),
+        message: rule.message.as_deref().unwrap_or(&fallback),
+        source: rule.id.as_deref(),
+        lines: &lines,
+      })?;
+    }
+  }
+  Ok(())
+}
+
+#[derive(Serialize)]
+struct Diagnostic<'a> {
+  from: usize,
+  to: usize,
+  severity: &'a Severity,
+  message: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  source: Option<&'a str>,
+}
+
+/// Scan `src` with many configs into diagnostics of `@codemirror/lint`,
+/// `[{ from, to, severity, message, source?, actions }]` with `from` and `to` in JS string indices,
+/// ready to be returned by a `linter` source. `severity`, `message` and `source` come from the
+/// `severity`, `message` and `id` of each config, `severity` defaults to `warning`.
+/// Configs with a `fix` get a `Fix` action applying it. Diagnostics are in config order.
+/// The action is defined in a JS snippet, so this needs the `web` or `bundler` build.
+#[wasm_bindgen(js_name = lintDiagnostics)]
+pub fn lint_diagnostics(
+  src: String,
+  configs: ConfigArray,
+) -> Result<CodeMirrorDiagnostics, JsValue> {
+  global()
+    .lint_diagnostics(src, configs.into())
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Marker<'a> {
+  severity: u8,
+  message: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  source: Option<&'a str>,
+  start_line_number: usize,
+  start_column: usize,
+  end_line_number: usize,
+  end_column: usize,
+}
+
+/// the values of Monaco's `MarkerSeverity` enum
+fn marker_severity(severity: &Severity) -> u8 {
+  match severity {
+    Severity::Hint => 1,
+    Severity::Info => 2,
+    Severity::Warning => 4,
+    Severity::Error => 8,
+  }
+}
+
+/// Scan `src` with many configs into Monaco `IMarkerData`, `[{ severity, message, source?,
+/// startLineNumber, startColumn, endLineNumber, endColumn }]` for `monaco.editor.setModelMarkers`.
+/// Lines and columns are one based, `severity` is a `MarkerSeverity` value.
+/// Messages and severities come from the configs like in `lintDiagnostics`.
+#[wasm_bindgen(js_name = monacoMarkers)]
+pub fn monaco_markers(src: String, configs: ConfigArray) -> Result<MonacoMarkers, JsValue> {
+  global()
+    .monaco_markers(src, configs.into())
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Serialize)]
+struct EslintFix {
+  range: [usize; 2],
+  text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintMessage {
+  rule_id: Option<String>,
+  severity: u8,
+  message: String,
+  line: usize,
+  column: usize,
+  end_line: usize,
+  end_column: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fix: Option<EslintFix>,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintResult<'a> {
+  file_path: &'a str,
+  messages: Vec<EslintMessage>,
+  // always empty, ESLint's directives are not read, serialized as `[]` for its consumers
+  suppressed_messages: Vec<()>,
+  error_count: usize,
+  fatal_error_count: usize,
+  warning_count: usize,
+  fixable_error_count: usize,
+  fixable_warning_count: usize,
+}
+
+/// Scan `[{ name, src }]` like `scanMany` into ESLint's JSON results, one per source,
+/// `[{ filePath, messages, errorCount, warningCount, ... }]` with each message
+/// `{ ruleId, severity, message, line, column, endLine, endColumn, fix? }`.
+/// `ruleId` is the `id` of the config, `severity` is 2 for `error` and 1 otherwise,
+/// lines and columns are one based and `fix.range` is in JS string indices.
+#[wasm_bindgen(js_name = eslintResults)]
+pub fn eslint_results(sources: JsValue, configs: ConfigArray) -> Result<EslintResults, JsValue> {
+  global()
+    .eslint_results(sources, configs.into())
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Serialize)]
+struct Change {
+  from: usize,
+  to: usize,
+  insert: String,
+}
+
+#[derive(Serialize)]
+struct QuickFix {
+  title: String,
+  edits: Vec<Change>,
+}
+
+/// a `Diagnostic` with its actions, not flattened as that would serialize to a `Map`
+#[derive(Serialize)]
+struct Diagnosed<'a> {
+  from: usize,
+  to: usize,
+  severity: &'a Severity,
+  message: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  source: Option<&'a str>,
+  actions: Vec<QuickFix>,
+}
+
+/// Scan `src` with many configs into diagnostics like `lintDiagnostics` with plain actions,
+/// `actions: [{ title, edits: [{ from, to, insert }] }]` computed up front,
+/// for quick fix menus of any editor and for results crossing a worker boundary.
+/// Configs with a `fix` get one action titled by their `id`, offsets are JS string indices.
+#[wasm_bindgen(js_name = quickFixes)]
+pub fn quick_fixes(src: String, configs: ConfigArray) -> Result<QuickFixDiagnostics, JsValue> {
+  global()
+    .quick_fixes(src, configs.into())
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn lint_diagnostics(&self, src: String, configs: js_sys::Array) -> Result<JsValue, JsValue> {
+    utils::enter("lintDiagnostics", src.len());
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    report_matches(&rules, &src, |report| {
+      let range = report.node.range();
+      let diagnostic = Diagnostic {
+        from: report.lines.js_index(range.start),
+        to: report.lines.js_index(range.end),
+        severity: report.severity,
+        message: report.message,
+        source: report.source,
+      };
+      let value = serialize::to_value(&diagnostic)?;
+      let actions = js_sys::Array::new();
+      if let Some(fixer) = &report.rule.fixer {
+        actions.push(&fix_action(&report.node.replace_by(fixer).inserted_text));
+      }
+      js_sys::Reflect::set(&value, &"actions".into(), &actions)?;
+      ret.push(&value);
+      Ok(())
+    })?;
+    Ok(ret.into())
+  }
+
+  pub fn quick_fixes(&self, src: String, configs: js_sys::Array) -> Result<JsValue, JsValue> {
+    utils::enter("quickFixes", src.len());
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    report_matches(&rules, &src, |report| {
+      let range = report.node.range();
+      let (from, to) = (
+        report.lines.js_index(range.start),
+        report.lines.js_index(range.end),
+      );
+      let actions = report.rule.fixer.iter().map(|fixer| {
+        let title = match report.source {
+          Some(id) => format!("Fix {}", id),
+          None => "Fix".into(),
+        };
+        let insert = report.node.replace_by(fixer).inserted_text;
+        let edits = vec![Change { from, to, insert }];
+        QuickFix { title, edits }
+      });
+      let diagnosed = Diagnosed {
+        from,
+        to,
+        severity: report.severity,
+        message: report.message,
+        source: report.source,
+        actions: actions.collect(),
+      };
+      ret.push(&serialize::to_value(&diagnosed)?);
+      Ok(())
+    })?;
+    Ok(ret.into())
+  }
+
+  pub fn monaco_markers(&self, src: String, configs: js_sys::Array) -> Result<JsValue, JsValue> {
+    utils::enter("monacoMarkers", src.len());
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    report_matches(&rules, &src, |report| {
+      let range = report.node.range();
+      let (start_line_number, start_column) = report.lines.line_column(range.start);
+      let (end_line_number, end_column) = report.lines.line_column(range.end);
+      let marker = Marker {
+        severity: marker_severity(report.severity),
+        message: report.message,
+        source: report.source,
+        start_line_number,
+        start_column,
+        end_line_number,
+        end_column,
+      };
+      ret.push(&serialize::to_value(&marker)?);
+      Ok(())
+    })?;
+    Ok(ret.into())
+  }
+
+  pub fn eslint_results(
+    &self,
+    sources: JsValue,
+    configs: js_sys::Array,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("eslintResults", 0);
+    let sources: Vec<SourceFile> =
+      serde_wasm_bindgen::from_value(sources).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    for source in &sources {
+      utils::enter("eslintResults", source.src.len());
+      let src = &source.src;
+      let mut result = EslintResult {
+        file_path: &source.name,
+        ..Default::default()
+      };
+      report_matches(&rules, src, |report| {
+        let range = report.node.range();
+        let lines = report.lines;
+        let (line, column) = lines.line_column(range.start);
+        let (end_line, end_column) = lines.line_column(range.end);
+        let fix = report.rule.fixer.as_ref().map(|fixer| EslintFix {
+          range: [lines.js_index(range.start), lines.js_index(range.end)],
+          text: report.node.replace_by(fixer).inserted_text,
+        });
+        let error = matches!(report.severity, Severity::Error);
+        let fixable = fix.is_some() as usize;
+        if error {
+          result.error_count += 1;
+          result.fixable_error_count += fixable;
+        } else {
+          result.warning_count += 1;
+          result.fixable_warning_count += fixable;
+        }
+        result.messages.push(EslintMessage {
+          rule_id: report.source.map(String::from),
+          severity: if error { 2 } else { 1 },
+          message: report.message.into(),
+          line,
+          column,
+          end_line,
+          end_column,
+          fix,
+        });
+        Ok(())
+      })?;
+      ret.push(&serialize::to_json_value(&result)?);
+    }
+    Ok(ret.into())
+  }
+}
@@ -0,0 +1,153 @@
+use wasm_bindgen::prelude::*;
+
+/// Stable, machine readable reasons set as `code` on every `Error` the module throws.
+/// Messages are for humans and may change, codes only grow. The README lists them.
+#[derive(Clone, Copy)]
+pub enum Code {
+  /// no language is given and `setupParser` was not called
+  NoLanguage,
+  /// the requested language is not loaded, see `LanguageMismatch`
+  LanguageMismatch,
+  /// configs scanned together use different languages
+  MixedLanguages,
+  /// a config does not have the shape of a config
+  InvalidConfig,
+  /// a rule, pattern or fix cannot be compiled
+  InvalidRule,
+  /// a constraint cannot be compiled, e.g. a bad regex
+  InvalidConstraint,
+  /// `fixErrors` or `fixRule` with a config without `fix`
+  MissingFix,
+  /// an argument other than a config is malformed or out of range
+  InvalidArgument,
+  /// an edit of `applyEdit` does not fit the source
+  InvalidEdit,
+  /// web-tree-sitter failed to initialize or create a parser
+  ParserInit,
+  /// a grammar could not be fetched or loaded
+  GrammarLoad,
+  /// the scan was cancelled by its `CancelToken`
+  Cancelled,
+  /// a cap of `setLimits` was exceeded
+  LimitExceeded,
+  /// a constraint regex compiles to a program over the size cap
+  RegexTooExpensive,
+  /// a bug, the module should be reloaded
+  Panic,
+}
+
+impl Code {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Code::NoLanguage => "NO_LANGUAGE",
+      Code::LanguageMismatch => "LANGUAGE_MISMATCH",
+      Code::MixedLanguages => "MIXED_LANGUAGES",
+      Code::InvalidConfig => "INVALID_CONFIG",
+      Code::InvalidRule => "INVALID_RULE",
+      Code::InvalidConstraint => "INVALID_CONSTRAINT",
+      Code::MissingFix => "MISSING_FIX",
+      Code::InvalidArgument => "INVALID_ARGUMENT",
+      Code::InvalidEdit => "INVALID_EDIT",
+      Code::ParserInit => "PARSER_INIT",
+      Code::GrammarLoad => "GRAMMAR_LOAD",
+      Code::Cancelled => "CANCELLED",
+      Code::LimitExceeded => "LIMIT_EXCEEDED",
+      Code::RegexTooExpensive => "REGEX_TOO_EXPENSIVE",
+      Code::Panic => "PANIC",
+    }
+  }
+}
+
+impl serde::Serialize for Code {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+/// An `Error` with `message`, `code` and empty `params`.
+pub fn error(code: Code, message: &str) -> JsValue {
+  let error = js_sys::Error::new(message);
+  let _ = js_sys::Reflect::set(&error, &"code".into(), &code.as_str().into());
+  let _ = js_sys::Reflect::set(&error, &"params".into(), &js_sys::Object::new());
+  error.into()
+}
+
+/// An `error` whose `params` are `params`, e.g. for hosts to localize the message.
+pub fn error_with(code: Code, message: &str, params: &[(&str, JsValue)]) -> JsValue {
+  let error = error(code, message);
+  set_params(&error, params);
+  error
+}
+
+/// Add `params` to the `params` of an error, and as its own properties like before `params`.
+pub fn set_params(error: &JsValue, params: &[(&str, JsValue)]) {
+  let object = js_sys::Reflect::get(error, &"params".into()).unwrap_or_default();
+  for (key, value) in params {
+    let _ = js_sys::Reflect::set(error, &(*key).into(), value);
+    let _ = js_sys::Reflect::set(&object, &(*key).into(), value);
+  }
+}
+
+/// Give an error from a dependency or a JS callback a `code`, keeping the code it already has.
+/// Values thrown that are not errors are wrapped in one.
+pub fn tag(code: Code, err: impl Into<JsValue>) -> JsValue {
+  use wasm_bindgen::JsCast;
+  let err = err.into();
+  match err.dyn_into::<js_sys::Error>() {
+    Ok(error) => {
+      let has_code = js_sys::Reflect::has(&error, &"code".into()).unwrap_or(false);
+      if !has_code {
+        let _ = js_sys::Reflect::set(&error, &"code".into(), &code.as_str().into());
+        let _ = js_sys::Reflect::set(&error, &"params".into(), &js_sys::Object::new());
+      }
+      error.into()
+    }
+    Err(value) => {
+      let message = value.as_string().unwrap_or_else(|| format!("{:?}", value));
+      error(code, &message)
+    }
+  }
+}
+
+/// The line `line` of `src` with a caret under `column`, both zero based, e.g.
+///
+/// ```text
+/// 3 |   patern: foo
+///   |   ^
+/// ```
+pub fn source_frame(src: &str, line: usize, column: usize) -> Option<String> {
+  let text = src.lines().nth(line)?.trim_end_matches('\r');
+  let number = (line + 1).to_string();
+  let gutter = " ".repeat(number.len());
+  // keep tabs so the caret lines up however they are shown
+  let pad: String = text
+    .chars()
+    .take(column)
+    .map(|c| if c == '\t' { '\t' } else { ' ' })
+    .collect();
+  Some(format!("{} | {}\n{} | {}^", number, text, gutter, pad))
+}
+
+/// Deserialize YAML, throwing `code` with the source frame of the error in the message
+/// and its zero based `line`, `column` and `frame` on the error.
+pub fn parse_yaml<T: serde::de::DeserializeOwned>(yaml: &str, code: Code) -> Result<T, JsValue> {
+  ast_grep_config::from_str(yaml).map_err(|e| {
+    let location = e.location().map(|l| (l.line() - 1, l.column() - 1));
+    let frame = location.and_then(|(line, column)| source_frame(yaml, line, column));
+    let message = match &frame {
+      Some(frame) => format!("{}\n\n{}", e, frame),
+      None => e.to_string(),
+    };
+    match (location, frame) {
+      (Some((line, column)), Some(frame)) => {
+        let params = [
+          ("line", JsValue::from(line)),
+          ("column", JsValue::from(column)),
+          ("frame", JsValue::from(frame)),
+        ];
+        error_with(code, &message, &params)
+      }
+      _ => error(code, &message),
+    }
+  })
+}
@@ -0,0 +1,194 @@
+use crate::context::{global, Context};
+use crate::error::{error_with, Code};
+use crate::lines::LineIndex;
+use crate::rule::CompiledRule;
+use crate::types::ConfigArray;
+use crate::utils;
+use crate::{limits, match_range, parse_root, serialize};
+
+use ast_grep_core::AstGrep;
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+/// An edit proposed by the config at index `rule` for the match at `range`.
+struct Proposal {
+  rule: usize,
+  range: [usize; 4],
+  start: usize,
+  end: usize,
+  inserted_text: String,
+}
+
+#[derive(Serialize)]
+struct Site {
+  rule: usize,
+  range: [usize; 4],
+}
+
+/// An edit left out because it overlaps one already applied.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Conflict {
+  rule: usize,
+  range: [usize; 4],
+  conflicts_with: Site,
+}
+
+#[derive(Serialize)]
+struct FixAll {
+  output: String,
+  conflicts: Vec<Conflict>,
+}
+
+/// Apply the fixes of many configs to `src` in one pass, returning `{ output, conflicts }`.
+/// Edits are applied in source order, the earlier config first at the same position,
+/// and an edit overlapping one applied before is skipped and reported in `conflicts`
+/// as `{ rule, range, conflictsWith: { rule, range } }`, where `rule` is the index of the config.
+#[wasm_bindgen(js_name = fixAll)]
+pub fn fix_all(src: String, configs: ConfigArray) -> Result<JsValue, JsValue> {
+  global().fix_all(src, configs.into())
+}
+
+impl Context {
+  pub fn fix_all(&self, src: String, configs: js_sys::Array) -> Result<JsValue, JsValue> {
+    utils::enter("fixAll", src.len());
+    let rules = self.compile_configs(configs)?;
+    if let Some(i) = rules.iter().position(|r| r.fixer.is_none()) {
+      let message = format!("config {} has no fix, fix is required for rewriting", i);
+      return Err(error_with(
+        Code::MissingFix,
+        &message,
+        &[("config", i.into())],
+      ));
+    }
+    let lang = match rules.first() {
+      Some(rule) => rule.lang.clone(),
+      None => return Ok(serialize::to_value(&apply(&src, vec![]))?),
+    };
+    let root = parse_root(&src, &lang)?;
+    let lines = LineIndex::new(&src);
+    let mut proposals = vec![];
+    for (i, rule) in rules.iter().enumerate() {
+      proposals.extend(propose(&root, &lines, i, rule));
+      limits::check_matches(proposals.len())?;
+    }
+    proposals.sort_by_key(|p| (p.start, p.rule));
+    Ok(serialize::to_value(&apply(&src, proposals))?)
+  }
+}
+
+fn propose(
+  root: &AstGrep<ts::Language>,
+  lines: &LineIndex,
+  index: usize,
+  rule: &CompiledRule,
+) -> Vec<Proposal> {
+  let fixer = match &rule.fixer {
+    Some(fixer) => fixer,
+    None => return vec![],
+  };
+  // both walk the tree in the same order, one edit per match
+  let ranges = root.root().find_all(rule).map(|n| match_range(&n));
+  let edits = root.root().replace_all(rule, fixer);
+  ranges
+    .zip(edits)
+    .map(|(range, edit)| Proposal {
+      rule: index,
+      range,
+      start: lines.byte_of(edit.position),
+      end: lines.byte_of(edit.position + edit.deleted_length),
+      inserted_text: edit.inserted_text,
+    })
+    .collect()
+}
+
+fn apply(src: &str, proposals: Vec<Proposal>) -> FixAll {
+  let mut output = String::with_capacity(src.len());
+  let mut conflicts = vec![];
+  let mut last: Option<Proposal> = None;
+  for proposal in proposals {
+    if let Some(applied) = &last {
+      // two insertions at the same position have no order either
+      if proposal.start < applied.end || proposal.start == applied.start {
+        conflicts.push(Conflict {
+          rule: proposal.rule,
+          range: proposal.range,
+          conflicts_with: Site {
+            rule: applied.rule,
+            range: applied.range,
+          },
+        });
+        continue;
+      }
+    }
+    let from = last.as_ref().map_or(0, |p| p.end);
+    output.push_str(&src[from..proposal.start]);
+    output.push_str(&proposal.inserted_text);
+    last = Some(proposal);
+  }
+  output.push_str(&src[last.map_or(0, |p| p.end)..]);
+  FixAll { output, conflicts }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn proposal(rule: usize, start: usize, end: usize, text: &str) -> Proposal {
+    Proposal {
+      rule,
+      range: [0, start, 0, end],
+      start,
+      end,
+      inserted_text: text.into(),
+    }
+  }
+
+  fn conflicts(fixed: &FixAll) -> Vec<(usize, usize)> {
+    let c = &fixed.conflicts;
+    c.iter().map(|c| (c.rule, c.conflicts_with.rule)).collect()
+  }
+
+  #[test]
+  fn test_apply_adjacent() {
+    let src = "let a = b";
+    let proposals = vec![
+      proposal(0, 0, 3, "const"),
+      proposal(1, 3, 4, "  "),
+      proposal(0, 8, 9, "c"),
+      proposal(2, 9, 9, ";"),
+    ];
+    let fixed = apply(src, proposals);
+    assert_eq!(fixed.output, "const  a = c;");
+    assert!(fixed.conflicts.is_empty());
+  }
+
+  #[test]
+  fn test_apply_overlapping() {
+    let src = "let a = b";
+    let proposals = vec![
+      proposal(0, 0, 5, "var x"),
+      proposal(1, 4, 9, "y = z"),
+      proposal(2, 5, 5, " "),
+    ];
+    let fixed = apply(src, proposals);
+    assert_eq!(fixed.output, "var x  = b");
+    assert_eq!(conflicts(&fixed), vec![(1, 0)]);
+    assert_eq!(fixed.conflicts[0].range, [0, 4, 0, 9]);
+  }
+
+  #[test]
+  fn test_apply_same_position() {
+    let src = "a";
+    let proposals = vec![
+      proposal(0, 0, 0, "x"),
+      proposal(1, 0, 0, "y"),
+      proposal(2, 0, 1, "b"),
+    ];
+    let fixed = apply(src, proposals);
+    // neither insertion goes first, so the later ones are left out
+    assert_eq!(fixed.output, "xa");
+    assert_eq!(conflicts(&fixed), vec![(1, 0), (2, 0)]);
+  }
+}
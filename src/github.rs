@@ -0,0 +1,156 @@
+use crate::context::{global, Context};
+use crate::editor::{report_matches, Report};
+use crate::error::{tag, Code};
+use crate::types::ConfigArray;
+use crate::utils;
+use crate::{serialize, SourceFile};
+
+use ast_grep_config::Severity;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Where a match is, one based like GitHub expects.
+struct Place {
+  line: usize,
+  column: usize,
+  end_line: usize,
+  end_column: usize,
+}
+
+impl Place {
+  fn of(report: &Report) -> Self {
+    let range = report.node.range();
+    let (line, column) = report.lines.line_column(range.start);
+    let (end_line, end_column) = report.lines.line_column(range.end);
+    Self {
+      line,
+      column,
+      end_line,
+      end_column,
+    }
+  }
+}
+
+/// escape the message of a workflow command
+fn escape_data(text: &str) -> String {
+  text
+    .replace('%', "%25")
+    .replace('\r', "%0D")
+    .replace('\n', "%0A")
+}
+
+/// escape a property of a workflow command, which also ends at `,` and `::`
+fn escape_property(text: &str) -> String {
+  escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Scan `[{ name, src }]` like `scanMany` into GitHub Actions workflow commands,
+/// one `::warning file=NAME,line=1,col=1,endLine=1,endColumn=5,title=ID::MESSAGE` per line,
+/// to be printed by a step so the matches show up as annotations of the run.
+/// The command is `error` for configs with severity `error`, `notice` for `info` and `hint`,
+/// `warning` otherwise, and messages come from the configs like in `lintDiagnostics`.
+#[wasm_bindgen(js_name = githubCommands)]
+pub fn github_commands(sources: JsValue, configs: ConfigArray) -> Result<String, JsValue> {
+  global().github_commands(sources, configs.into())
+}
+
+#[derive(Serialize)]
+struct Annotation<'a> {
+  path: &'a str,
+  start_line: usize,
+  end_line: usize,
+  // only allowed by GitHub within one line
+  #[serde(skip_serializing_if = "Option::is_none")]
+  start_column: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  end_column: Option<usize>,
+  annotation_level: &'static str,
+  message: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  title: Option<&'a str>,
+}
+
+/// Scan `[{ name, src }]` like `scanMany` into the `annotations` of a check run of the GitHub API,
+/// `[{ path, start_line, end_line, start_column?, end_column?, annotation_level, message, title? }]`.
+/// Columns are only set for matches within one line, as the API requires.
+/// GitHub takes at most 50 annotations per request, send longer lists in chunks.
+#[wasm_bindgen(js_name = githubAnnotations)]
+pub fn github_annotations(sources: JsValue, configs: ConfigArray) -> Result<JsValue, JsValue> {
+  global().github_annotations(sources, configs.into())
+}
+
+impl Context {
+  pub fn github_commands(
+    &self,
+    sources: JsValue,
+    configs: js_sys::Array,
+  ) -> Result<String, JsValue> {
+    utils::enter("githubCommands", 0);
+    let sources: Vec<SourceFile> =
+      serde_wasm_bindgen::from_value(sources).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let mut ret = String::new();
+    for source in &sources {
+      utils::enter("githubCommands", source.src.len());
+      report_matches(&rules, &source.src, |report| {
+        let command = match report.severity {
+          Severity::Error => "error",
+          Severity::Warning => "warning",
+          Severity::Info | Severity::Hint => "notice",
+        };
+        let place = Place::of(&report);
+        ret.push_str(&format!(
+          "::{} file={},line={},col={},endLine={},endColumn={}",
+          command,
+          escape_property(&source.name),
+          place.line,
+          place.column,
+          place.end_line,
+          place.end_column,
+        ));
+        if let Some(id) = report.source {
+          ret.push_str(&format!(",title={}", escape_property(id)));
+        }
+        ret.push_str(&format!("::{}\n", escape_data(report.message)));
+        Ok(())
+      })?;
+    }
+    Ok(ret)
+  }
+
+  pub fn github_annotations(
+    &self,
+    sources: JsValue,
+    configs: js_sys::Array,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("githubAnnotations", 0);
+    let sources: Vec<SourceFile> =
+      serde_wasm_bindgen::from_value(sources).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    for source in &sources {
+      utils::enter("githubAnnotations", source.src.len());
+      report_matches(&rules, &source.src, |report| {
+        let place = Place::of(&report);
+        let one_line = place.line == place.end_line;
+        let annotation = Annotation {
+          path: &source.name,
+          start_line: place.line,
+          end_line: place.end_line,
+          start_column: one_line.then_some(place.column),
+          end_column: one_line.then_some(place.end_column),
+          annotation_level: match report.severity {
+            Severity::Error => "failure",
+            Severity::Warning => "warning",
+            Severity::Info | Severity::Hint => "notice",
+          },
+          message: report.message,
+          title: report.source,
+        };
+        ret.push(&serialize::to_value(&annotation)?);
+        Ok(())
+      })?;
+    }
+    Ok(ret.into())
+  }
+}
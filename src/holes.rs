@@ -0,0 +1,195 @@
+use crate::context::{global, Context};
+use crate::error::{error, parse_yaml, tag, Code};
+use crate::lines::LineIndex;
+use crate::rule::walk_rule;
+use crate::types::{ConfigSource, MetaVariableHoles};
+use crate::{match_range, serialize, shape, utils, WASMConfig};
+
+use ast_grep_config::{PatternStyle, SerializableRule};
+use ast_grep_core::language::Language;
+use ast_grep_core::MetaVariable;
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Role {
+  /// captured by a pattern of the rule
+  Pattern,
+  Constraint,
+  Fix,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Hole {
+  /// without `$`
+  name: String,
+  /// used as `$$$NAME`, capturing many nodes
+  multi: bool,
+  roles: Vec<Role>,
+  /// where the patterns capturing it are, e.g. `rule.all[0].pattern`
+  paths: Vec<String>,
+}
+
+#[derive(Default)]
+struct Holes(Vec<Hole>);
+
+impl Holes {
+  fn add(&mut self, name: String, multi: bool, role: Role, path: Option<String>) {
+    let hole = match self.0.iter().position(|h| h.name == name) {
+      Some(i) => &mut self.0[i],
+      None => {
+        self.0.push(Hole {
+          name,
+          multi: false,
+          roles: vec![],
+          paths: vec![],
+        });
+        self.0.last_mut().unwrap()
+      }
+    };
+    hole.multi |= multi;
+    if !hole.roles.contains(&role) {
+      hole.roles.push(role);
+    }
+    hole.paths.extend(path);
+  }
+
+  fn add_text(&mut self, text: &str, lang: &ts::Language, role: Role, path: Option<&str>) {
+    for (name, multi) in named_meta_vars(text, lang) {
+      self.add(name, multi, role, path.map(String::from));
+    }
+  }
+
+  fn add_rule(&mut self, rule: &SerializableRule, path: &str, lang: &ts::Language) {
+    use SerializableRule as S;
+    walk_rule(rule, path, &mut |path, rule| match rule {
+      Ok(S::Pattern(PatternStyle::Str(pattern))) => {
+        let path = format!("{}.pattern", path);
+        self.add_text(pattern, lang, Role::Pattern, Some(&path));
+      }
+      Ok(S::Pattern(PatternStyle::Contextual { context, .. })) => {
+        let path = format!("{}.pattern.context", path);
+        self.add_text(context, lang, Role::Pattern, Some(&path));
+      }
+      _ => (),
+    });
+  }
+}
+
+/// Named metavariables in a pattern or fix, in order, with whether they are `$$$` ones.
+fn named_meta_vars(text: &str, lang: &ts::Language) -> Vec<(String, bool)> {
+  let root = lang.ast_grep(lang.pre_process_pattern(text));
+  let names = root
+    .root()
+    .dfs()
+    .filter(|node| node.is_leaf())
+    .filter_map(|node| match lang.extract_meta_var(&node.text()) {
+      Some(MetaVariable::Named(name)) => Some((name, false)),
+      Some(MetaVariable::NamedEllipsis(name)) => Some((name, true)),
+      _ => None,
+    })
+    .collect();
+  names
+}
+
+/// Every named metavariable of a config, an object or YAML, with where it is used,
+/// `[{ name, multi, roles, paths }]` in order of first use, e.g. to render one input per hole.
+/// `roles` has `pattern` if a pattern of the rule captures it, `constraint` and `fix` if those use it,
+/// `paths` are the patterns capturing it and `multi` is true for `$$$` metavariables.
+#[wasm_bindgen(js_name = getMetaVariables)]
+pub fn get_meta_variables(config: ConfigSource) -> Result<MetaVariableHoles, JsValue> {
+  global()
+    .get_meta_variables(config.into())
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn get_meta_variables(&self, config: JsValue) -> Result<JsValue, JsValue> {
+    utils::enter("getMetaVariables", 0);
+    let config: WASMConfig = match config.as_string() {
+      Some(yaml) => parse_yaml(&yaml, Code::InvalidConfig)?,
+      None => shape::read_config(config)?,
+    };
+    let lang = self.get_language(config.language.as_deref())?;
+    let mut holes = Holes::default();
+    holes.add_rule(&config.rule, "rule", &lang);
+    let mut constrained: Vec<_> = config.constraints.iter().flat_map(|c| c.keys()).collect();
+    constrained.sort();
+    for name in constrained {
+      holes.add(name.clone(), false, Role::Constraint, None);
+    }
+    if let Some(fix) = &config.fix {
+      holes.add_text(fix, &lang, Role::Fix, None);
+    }
+    Ok(serialize::to_value(&holes.0)?)
+  }
+}
+
+/// Fill the metavariables of `pattern` with the texts of `bindings`, `{ NAME: text }` keyed
+/// without `$`, and return the code, e.g. `{ A: "a", B: "b" }` turns `$A + $B` into `a + b`.
+/// `$$$NAME` takes its text as is. The code is reparsed with `language`, or the current one,
+/// and `INVALID_ARGUMENT` is thrown for an unbound or anonymous metavariable
+/// or for code that has syntax errors.
+#[wasm_bindgen(js_name = instantiatePattern)]
+pub fn instantiate_pattern(
+  pattern: String,
+  bindings: JsValue,
+  language: Option<String>,
+) -> Result<String, JsValue> {
+  global().instantiate_pattern(pattern, bindings, language)
+}
+
+impl Context {
+  pub fn instantiate_pattern(
+    &self,
+    pattern: String,
+    bindings: JsValue,
+    language: Option<String>,
+  ) -> Result<String, JsValue> {
+    utils::enter("instantiatePattern", pattern.len());
+    let bindings: HashMap<String, String> =
+      serde_wasm_bindgen::from_value(bindings).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let lang = self.get_language(language.as_deref())?;
+    let processed = lang.pre_process_pattern(&pattern);
+    let root = lang.ast_grep(&processed);
+    let lines = LineIndex::new(&processed);
+    let mut code = String::with_capacity(processed.len());
+    let mut start = 0;
+    for node in root.root().dfs().filter(|node| node.is_leaf()) {
+      let name = match lang.extract_meta_var(&node.text()) {
+        Some(MetaVariable::Named(name) | MetaVariable::NamedEllipsis(name)) => name,
+        Some(_) => {
+          let message = format!("`{}` is anonymous and cannot be filled", node.text());
+          return Err(error(Code::InvalidArgument, &message));
+        }
+        None => continue,
+      };
+      let text = bindings.get(&name).ok_or_else(|| {
+        error(
+          Code::InvalidArgument,
+          &format!("`${}` has no binding", name),
+        )
+      })?;
+      let range = node.range();
+      code.push_str(&processed[start..lines.byte_of(range.start)]);
+      code.push_str(text);
+      start = lines.byte_of(range.end);
+    }
+    code.push_str(&processed[start..]);
+    let generated = lang.ast_grep(&code);
+    // missing nodes cannot be told from zero width tokens like automatic semicolons
+    let broken = generated.root().dfs().find(|n| n.kind() == "ERROR");
+    if let Some(node) = broken {
+      let [line, column, ..] = match_range(&node);
+      let message = format!("the generated code does not parse at {}:{}", line, column);
+      return Err(error(Code::InvalidArgument, &message));
+    }
+    Ok(code)
+  }
+}
@@ -0,0 +1,114 @@
+use crate::context::{global, Context};
+use crate::target::node_by_id;
+use crate::types::NodeInfo;
+use crate::{match_range, parse_root, serialize, utils};
+
+use ast_grep_core::language::Language;
+use ast_grep_core::Node;
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+  kind: String,
+  /// the field of the parent holding the node, if any
+  field: Option<String>,
+  is_named: bool,
+  range: [usize; 4],
+  child_count: usize,
+  is_error: bool,
+  /// inserted by the parser to recover from a syntax error, it has no text
+  is_missing: bool,
+  /// the above as markdown for a tooltip
+  hover: String,
+}
+
+fn field_name(node: &Node<ts::Language>) -> Option<String> {
+  let parent = node.parent()?;
+  let lang = node.lang().get_ts_language();
+  // field ids start at 1
+  (1..=lang.field_count())
+    .filter_map(|id| lang.field_name_for_id(id))
+    .find(|name| {
+      parent
+        .field_children(name)
+        .any(|c| c.range() == node.range() && c.kind_id() == node.kind_id())
+    })
+    .map(|name| name.into_owned())
+}
+
+fn hover_text(info: &Info) -> String {
+  let mut text = format!("**{}**", info.kind);
+  if !info.is_named {
+    text.push_str(" (anonymous)");
+  }
+  if let Some(field) = &info.field {
+    text.push_str(&format!(" in field `{}`", field));
+  }
+  if info.is_error {
+    text.push_str(", syntax error");
+  }
+  if info.is_missing {
+    text.push_str(", missing");
+  }
+  let [start_line, start_column, end_line, end_column] = info.range;
+  let children = match info.child_count {
+    1 => "1 child".to_string(),
+    n => format!("{} children", n),
+  };
+  // one based like editors show positions
+  text.push_str(&format!(
+    "\n\n{}:{}-{}:{}, {}",
+    start_line + 1,
+    start_column + 1,
+    end_line + 1,
+    end_column + 1,
+    children
+  ));
+  text
+}
+
+/// Details of the node with `id` in a dump of `src`, for a tooltip of a tree view,
+/// `{ kind, field, isNamed, range, childCount, isError, isMissing, hover }`
+/// where `hover` is markdown summing up the rest.
+#[wasm_bindgen(js_name = getNodeInfo)]
+pub fn get_node_info(
+  src: String,
+  id: usize,
+  language: Option<String>,
+) -> Result<NodeInfo, JsValue> {
+  global()
+    .get_node_info(src, id, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn get_node_info(
+    &self,
+    src: String,
+    id: usize,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("getNodeInfo", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    let node = node_by_id(&root, id)?;
+    // core has no accessor for missing nodes, tree-sitter prints them as `(MISSING ..)`
+    let is_missing = node.range().is_empty() && node.to_sexp().starts_with("(MISSING");
+    let mut info = Info {
+      kind: node.kind().into_owned(),
+      field: field_name(&node),
+      is_named: node.is_named(),
+      range: match_range(&node),
+      child_count: node.children().len(),
+      is_error: node.kind() == "ERROR",
+      is_missing,
+      hover: String::new(),
+    };
+    info.hover = hover_text(&info);
+    Ok(serialize::to_value(&info)?)
+  }
+}
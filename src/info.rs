@@ -0,0 +1,112 @@
+use crate::context::global;
+use crate::serialize;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Bumped when an existing function changes incompatibly, new functions only add features.
+const API_VERSION: u32 = 1;
+
+/// What this build supports, `false` for features of the ast-grep CLI not available here yet.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Features {
+  /// `registerLanguage`, rules naming their `language`
+  multi_language: bool,
+  /// `new AstGrep()` with its own languages and caches
+  instances: bool,
+  /// `transform` of metavariables in configs
+  transforms: bool,
+  /// `rewriters` in configs
+  rewriters: bool,
+  /// `SgRoot.applyEdit` reparsing only the edited part
+  incremental_parse: bool,
+  /// `parse` restricted to included ranges
+  included_ranges: bool,
+  /// `CancelToken` and `timeoutMs`
+  cancellation: bool,
+  /// `setLimits`
+  limits: bool,
+  /// `findNodesIter` and `findNodesEach`
+  iterators: bool,
+  /// `trace`, `explainNoMatch` and `setVisitTracing`
+  trace: bool,
+  /// `metrics` and `benchmarkRule`
+  metrics: bool,
+  /// `fixAll` with conflict reports
+  fix_all: bool,
+  /// `validateRule`, `lintRule` and `debugQuery`
+  validation: bool,
+  /// grammars compiled into the binary, loaded by `setupParser` without a download
+  bundled_grammars: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Capabilities {
+  api_version: u32,
+  features: Features,
+}
+
+/// The API version and features of this build, `{ apiVersion, features }`,
+/// so embedders can fall back gracefully on deployments of older or slimmer builds.
+#[wasm_bindgen(js_name = getCapabilities)]
+pub fn get_capabilities() -> Result<JsValue, JsValue> {
+  let mut bundled_grammars = vec![];
+  if cfg!(feature = "lang-javascript") {
+    bundled_grammars.push("javascript");
+  }
+  if cfg!(feature = "lang-typescript") {
+    bundled_grammars.push("typescript");
+  }
+  let ret = Capabilities {
+    api_version: API_VERSION,
+    features: Features {
+      multi_language: true,
+      instances: true,
+      transforms: false,
+      rewriters: false,
+      incremental_parse: true,
+      included_ranges: true,
+      cancellation: true,
+      limits: true,
+      iterators: true,
+      trace: true,
+      metrics: true,
+      fix_all: true,
+      validation: true,
+      bundled_grammars,
+    },
+  };
+  Ok(serialize::to_value(&ret)?)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Versions {
+  ast_grep_wasm: &'static str,
+  ast_grep_core: &'static str,
+  ast_grep_config: &'static str,
+  /// the crate binding web-tree-sitter, the JS runtime itself comes from the host page
+  web_tree_sitter_sg: &'static str,
+  tree_sitter_facade_sg: &'static str,
+  /// ABI version of the grammar set up by `setupParser`, absent before it is called
+  #[serde(skip_serializing_if = "Option::is_none")]
+  language_abi: Option<u32>,
+}
+
+/// Versions of this crate and of the ones it was built with, as locked in Cargo.lock,
+/// e.g. to include them in bug reports.
+#[wasm_bindgen(js_name = getVersions)]
+pub fn get_versions() -> Result<JsValue, JsValue> {
+  let ret = Versions {
+    ast_grep_wasm: env!("CARGO_PKG_VERSION"),
+    // set by build.rs, `unknown` if Cargo.lock was not found
+    ast_grep_core: env!("SG_VERSION_CORE"),
+    ast_grep_config: env!("SG_VERSION_CONFIG"),
+    web_tree_sitter_sg: env!("SG_VERSION_WEB_TREE_SITTER"),
+    tree_sitter_facade_sg: env!("SG_VERSION_TREE_SITTER_FACADE"),
+    language_abi: global().get_language(None).ok().map(|lang| lang.version()),
+  };
+  Ok(serialize::to_value(&ret)?)
+}
@@ -0,0 +1,37 @@
+use crate::context::{global, Context};
+use crate::{serialize, utils};
+
+use ast_grep_core::Node;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+/// The `kind` and `kindId` of a node in a dump, only one of them is set following `kindIds`.
+pub fn kind_of(node: &Node<ts::Language>) -> (Option<String>, Option<u16>) {
+  if serialize::kind_ids() {
+    (None, Some(node.kind_id()))
+  } else {
+    (Some(node.kind().into_owned()), None)
+  }
+}
+
+/// The kind names of `language`, or the current one, indexed by kind id,
+/// to read the `kindId`s of dumps made with `setSerializeOptions({ kindIds: true })`.
+/// Ids without a name, e.g. of hidden rules, map to an empty string.
+#[wasm_bindgen(js_name = getKindTable)]
+pub fn get_kind_table(language: Option<String>) -> Result<Vec<JsValue>, JsValue> {
+  global().get_kind_table(language)
+}
+
+impl Context {
+  pub fn get_kind_table(&self, language: Option<String>) -> Result<Vec<JsValue>, JsValue> {
+    utils::enter("getKindTable", 0);
+    let lang = self.get_language(language.as_deref())?;
+    let table = (0..lang.node_kind_count())
+      .map(|id| {
+        let kind = lang.node_kind_for_id(id).unwrap_or_default();
+        JsValue::from_str(&kind)
+      })
+      .collect();
+    Ok(table)
+  }
+}
@@ -1,92 +1,1149 @@
+mod builder;
+mod cancel;
+mod captures;
+mod changes;
+mod cli;
+mod complete;
+mod context;
+mod debug;
+mod duplicates;
+mod editor;
+mod error;
+mod fix;
+mod github;
+mod holes;
+mod hover;
+mod info;
+mod kinds;
+mod limits;
+mod lines;
+mod live;
+mod lsp;
+mod match_iter;
+mod match_list;
+mod memo;
+mod metrics;
+mod node;
+mod quick;
+mod rule;
+mod sarif;
+mod serialize;
+mod shape;
+mod share;
+mod similar;
+mod source;
+mod stats;
+mod suggest;
+mod suppress;
+mod tags;
+mod target;
+mod testing;
+mod trace;
+mod types;
 mod utils;
+mod validate;
+mod visits;
+mod workspace;
 
-use ast_grep_config::{
-  deserialize_rule, try_deserialize_matchers, RuleWithConstraint, SerializableMetaVarMatcher,
-  SerializableRule,
-};
+use ast_grep_config::{SerializableMetaVarMatcher, SerializableRule, Severity};
 use ast_grep_core::language::Language;
-use ast_grep_core::meta_var::MetaVarMatchers;
-use ast_grep_core::{Pattern, Node};
+use ast_grep_core::{AstGrep, Matcher, Node, NodeMatch};
+pub use builder::{rule, RuleBuilder};
+pub use cancel::{create_cancel_token, CancelToken};
+use cancel::{CancelCheck, CancelSignal};
+pub use captures::{collect_captures, group_by_capture};
+pub use cli::scan_json;
+pub use complete::get_completions;
+pub use context::Instance;
+use context::{global, Context};
+pub use debug::debug_query;
+pub use duplicates::find_duplicates;
+pub use editor::{eslint_results, lint_diagnostics, monaco_markers, quick_fixes};
+use error::{error, error_with, set_params, tag, Code};
+pub use fix::fix_all;
+pub use github::{github_annotations, github_commands};
+pub use holes::{get_meta_variables, instantiate_pattern};
+pub use hover::get_node_info;
+pub use info::{get_capabilities, get_versions};
+pub use kinds::get_kind_table;
+pub use limits::set_limits;
+pub use lsp::{lsp_code_actions, lsp_diagnostics};
+pub use match_iter::{find_nodes_each, find_nodes_iter, MatchIter};
+pub use match_list::{find_matches, MatchList};
+pub use metrics::benchmark_rule;
+use metrics::{Metrics, RuleMetrics};
+pub use node::SgNode;
+pub use quick::{
+  find_by_kind, find_by_pattern, find_by_pattern_prefiltered, find_by_patterns, find_identifier,
+  replace_by_pattern,
+};
+use rule::CompiledRule;
+pub use rule::{compile_rule, RuleHandle};
+pub use sarif::to_sarif;
+pub use serialize::set_serialize_options;
+pub use share::{decode_state, encode_state};
+pub use similar::find_similar;
+pub use stats::get_memory_stats;
 use std::collections::HashMap;
+pub use suggest::suggest_pattern;
+pub use suppress::suppression_edit;
+pub use tags::{extract_tags, load_tags_query};
+pub use target::rule_for_node;
+pub use testing::{assert_fixed, assert_matches, run_catalog_example, test_rule};
+pub use trace::explain_no_match;
+use types::{
+  ChangedRanges, Config, ConfigArray, DumpNode, Matches, ScanManyResult, ScanResult, SourceInput,
+};
+pub use utils::install_panic_hook;
+pub use validate::{check_ruleset, lint_rule, validate_rule};
+pub use visits::set_visit_tracing;
+use visits::Visits;
+pub use workspace::{create_workspace, Workspace};
 
 use serde::{Deserialize, Serialize};
 use tree_sitter as ts;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 
-use std::sync::Mutex;
+use std::cell::RefCell;
+use std::rc::Rc;
 
+#[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// Name of the global allocator compiled in: `wee_alloc` or `std` (dlmalloc on wasm32).
+#[wasm_bindgen(js_name = activeAllocator)]
+pub fn active_allocator() -> String {
+  let name = if cfg!(feature = "wee_alloc") {
+    "wee_alloc"
+  } else {
+    "std"
+  };
+  name.into()
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct WASMConfig {
+  /// case insensitive name of a registered language, defaults to the one set by `setupParser`
+  pub language: Option<String>,
   pub rule: SerializableRule,
   pub fix: Option<String>,
   pub constraints: Option<HashMap<String, SerializableMetaVarMatcher>>,
+  /// time budget of findNodes and scanRules, which return the matches found so far
+  /// as an array with `timedOut: true` when it is spent
+  #[serde(rename = "timeoutMs")]
+  pub timeout_ms: Option<f64>,
+  /// attach parse, compile and match timings as `metrics` to the results
+  #[serde(default)]
+  pub metrics: bool,
+  /// attach to findNodes results `traces`, how each sub-rule fared on each match,
+  /// or `candidates`, how many nodes each sub-rule matches, when nothing matched
+  #[serde(default)]
+  pub trace: bool,
+  /// attach to scanRules and scanMany results `unmatched`, the indices of configs without matches
+  #[serde(default)]
+  pub coverage: bool,
+  /// name, message and severity of the rule like in sg rule files, reported by `lintDiagnostics`
+  pub id: Option<String>,
+  pub message: Option<String>,
+  pub severity: Option<Severity>,
+  /// line breaks of the output of fixErrors, e.g. `auto` for sources pasted from Windows
+  #[serde(rename = "lineEndings", default)]
+  pub line_endings: lines::LineEndings,
 }
 
-static INSTANCE: Mutex<Option<ts::Language>> = Mutex::new(None);
+#[derive(Default)]
+struct Languages {
+  current: Option<Rc<ts::Language>>,
+  current_name: Option<String>,
+  registered: HashMap<String, Rc<ts::Language>>,
+}
+
+/// A config asks for a language whose grammar is not loaded, e.g. `language: Python`
+/// while only javascript is set up. Thrown as an `Error` named `LanguageMismatch`
+/// with `requested`, `active` and `loaded` properties.
+#[derive(Debug)]
+struct LanguageMismatch {
+  requested: String,
+  active: Option<String>,
+  loaded: Vec<String>,
+}
+
+impl std::fmt::Display for LanguageMismatch {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "rule language `{}` is not loaded", self.requested)?;
+    match &self.active {
+      Some(active) => write!(f, ", the active grammar is `{}`", active),
+      None if self.loaded.is_empty() => write!(f, ", no grammar is loaded"),
+      None => write!(f, ", loaded grammars: {}", self.loaded.join(", ")),
+    }
+  }
+}
+
+impl From<LanguageMismatch> for JsValue {
+  fn from(e: LanguageMismatch) -> Self {
+    let error: js_sys::Error = error(Code::LanguageMismatch, &e.to_string()).unchecked_into();
+    error.set_name("LanguageMismatch");
+    let loaded: js_sys::Array = e.loaded.iter().map(JsValue::from).collect();
+    let props = [
+      ("requested", JsValue::from(e.requested)),
+      ("active", e.active.map_or(JsValue::NULL, JsValue::from)),
+      ("loaded", loaded.into()),
+    ];
+    set_params(&error, &props);
+    error.into()
+  }
+}
+
+fn find_language(languages: &Languages, name: Option<&str>) -> Result<Rc<ts::Language>, JsValue> {
+  let name = match name {
+    Some(name) => name,
+    None => {
+      let lang = languages.current.clone();
+      return lang.ok_or_else(|| error(Code::NoLanguage, "current language is not set"));
+    }
+  };
+  if let Some(lang) = languages.registered.get(&name.to_lowercase()) {
+    return Ok(lang.clone());
+  }
+  let mut loaded: Vec<_> = languages.registered.keys().cloned().collect();
+  loaded.sort();
+  let mismatch = LanguageMismatch {
+    requested: name.to_string(),
+    active: languages.current_name.clone(),
+    loaded,
+  };
+  Err(mismatch.into())
+}
+
+/// `tree-sitter-javascript.wasm`, `/grammars/javascript.wasm` and `javascript` all name `javascript`
+fn language_name(path: &str) -> &str {
+  let file = path.rsplit('/').next().unwrap_or(path);
+  let file = file.strip_suffix(".wasm").unwrap_or(file);
+  file.strip_prefix("tree-sitter-").unwrap_or(file)
+}
+
+#[wasm_bindgen]
+extern "C" {
+  /// A grammar to load: a path/url to fetch or the wasm bytes of the grammar, e.g. read by
+  /// `fs.readFile` in Node.js or cached in IndexedDB by the host page to skip downloading it.
+  /// A compiled `WebAssembly.Module` is rejected as `INVALID_ARGUMENT`.
+  /// Edge runtimes without a path loader can pass a fetch `Response` or a promise of either.
+  #[wasm_bindgen(typescript_type = "GrammarInput | Promise<GrammarInput>")]
+  pub type ParserSource;
+}
+
+/// Loading phases reported to the optional `onProgress` callback of `setupParser`.
+/// web-tree-sitter downloads and compiles a grammar in one step, so `load` covers both.
+struct Progress(Option<js_sys::Function>);
+
+impl Progress {
+  fn report(&self, phase: &str) {
+    if let Some(callback) = &self.0 {
+      // progress is informational, a throwing callback should not abort loading
+      let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(phase));
+    }
+  }
+}
+
+thread_local! {
+  static GRAMMAR_LOADER: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+}
+
+/// Register `(langName) => GrammarInput | Promise<GrammarInput>` to resolve the string
+/// passed to `setupParser`, e.g. to fetch grammars from a custom CDN or test fixtures.
+/// Pass `null` to fall back to web-tree-sitter's path loader.
+#[wasm_bindgen(js_name = registerGrammarLoader)]
+pub fn register_grammar_loader(loader: Option<js_sys::Function>) {
+  GRAMMAR_LOADER.with(|l| *l.borrow_mut() = loader);
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const GRAMMAR_INPUT: &'static str = r#"
+type GrammarInput = string | Uint8Array | ArrayBuffer | { arrayBuffer(): Promise<ArrayBuffer> };
+"#;
 
 #[wasm_bindgen(js_name = setupParser)]
-pub async fn setup_parser(parser_path: String) -> Result<(), JsError> {
-  ts::TreeSitter::init().await?;
-  let mut parser = ts::Parser::new()?;
-  let lang = get_lang(parser_path).await?;
-  parser.set_language(&lang)?;
-  let mut curr_lang = INSTANCE.lock().expect_throw("set language error");
-  *curr_lang = Some(lang);
+pub async fn setup_parser(
+  source: ParserSource,
+  on_progress: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+  global().setup_parser(source.into(), on_progress).await
+}
+
+/// Load a grammar under `langName` for configs selecting it with `language`,
+/// without changing the default language set by `setupParser`.
+/// `source` defaults to `langName`, which is resolved by embedded grammars or the grammar loader.
+#[wasm_bindgen(js_name = registerLanguage)]
+pub async fn register_language(
+  lang_name: String,
+  source: Option<ParserSource>,
+  on_progress: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+  let source = source.map(JsValue::from);
+  global()
+    .register_language(lang_name, source, on_progress)
+    .await
+}
+
+impl Context {
+  async fn setup_parser(
+    &self,
+    source: JsValue,
+    on_progress: Option<js_sys::Function>,
+  ) -> Result<(), JsValue> {
+    let name = source.as_string();
+    let lang = Rc::new(load_language(source, on_progress).await?);
+    let name = name.map(|name| language_name(&name).to_lowercase());
+    let mut languages = self.languages.borrow_mut();
+    if let Some(name) = &name {
+      languages.registered.insert(name.clone(), lang.clone());
+    }
+    languages.current = Some(lang);
+    languages.current_name = name;
+    Ok(())
+  }
+
+  async fn register_language(
+    &self,
+    lang_name: String,
+    source: Option<JsValue>,
+    on_progress: Option<js_sys::Function>,
+  ) -> Result<(), JsValue> {
+    let source = source.unwrap_or_else(|| JsValue::from_str(&lang_name));
+    let lang = Rc::new(load_language(source, on_progress).await?);
+    let mut languages = self.languages.borrow_mut();
+    languages.registered.insert(lang_name.to_lowercase(), lang);
+    Ok(())
+  }
+}
+
+/// web-tree-sitter errors differ by target, all of them convert to `JsError`
+fn parser_init<E>(e: E) -> JsValue
+where
+  JsError: From<E>,
+{
+  tag(Code::ParserInit, JsError::from(e))
+}
+
+async fn load_language(
+  source: JsValue,
+  on_progress: Option<js_sys::Function>,
+) -> Result<ts::Language, JsValue> {
+  let progress = Progress(on_progress);
+  progress.report("init");
+  ts::TreeSitter::init().await.map_err(parser_init)?;
+  let mut parser = ts::Parser::new().map_err(parser_init)?;
+  progress.report("load");
+  let lang = get_lang(source)
+    .await
+    .map_err(|e| tag(Code::GrammarLoad, e))?;
+  parser
+    .set_language(&lang)
+    .map_err(|e| tag(Code::GrammarLoad, JsError::from(e)))?;
+  progress.report("ready");
+  Ok(lang)
+}
+
+#[wasm_bindgen]
+extern "C" {
+  #[wasm_bindgen(js_namespace = console, js_name = debug)]
+  fn console_debug(message: &str);
+}
+
+thread_local! {
+  // parser carrying the console logger, only present when parse logging is enabled
+  static LOG_PARSER: RefCell<Option<ts::Parser>> = const { RefCell::new(None) };
+}
+
+#[cfg(target_arch = "wasm32")]
+fn parse_logger() -> ts::Logger<'static> {
+  // web-tree-sitter passes the message as the first argument
+  Box::new(|message: ts::LogType, _params: js_sys::JsString| console_debug(&String::from(message)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_logger() -> ts::Logger<'static> {
+  Box::new(|_log_type, message: &str| console_debug(message))
+}
+
+/// Print tree-sitter's lexer and parser decisions to `console.debug` for every parsed source.
+/// It helps grammar authors to see why a snippet parses oddly, at the cost of parsing twice.
+#[wasm_bindgen(js_name = setParseLogging)]
+pub fn set_parse_logging(enabled: bool) -> Result<(), JsValue> {
+  let parser = if enabled {
+    let mut parser = ts::Parser::new().map_err(parser_init)?;
+    parser.set_logger(Some(parse_logger()));
+    Some(parser)
+  } else {
+    None
+  };
+  LOG_PARSER.with(|p| *p.borrow_mut() = parser);
   Ok(())
 }
 
-#[wasm_bindgen(js_name = findNodes)]
-pub fn find_nodes(src: String, config: JsValue) -> Result<JsValue, JsError> {
-  let config: WASMConfig = serde_wasm_bindgen::from_value(config)?;
-  let lang = INSTANCE
-    .lock()
-    .expect_throw("get language error")
-    .clone()
-    .expect_throw("current language is not set");
+// ast-grep-core parses with its own parser so logging needs a separate parse
+fn log_parse(src: &str, lang: &ts::Language) {
+  LOG_PARSER.with(|p| {
+    if let Some(parser) = p.borrow_mut().as_mut() {
+      if parser.set_language(lang).is_ok() {
+        let _ = parser.parse(src, None);
+      }
+    }
+  });
+}
+
+fn parse_root(src: &str, lang: &ts::Language) -> Result<AstGrep<ts::Language>, JsValue> {
+  utils::input(src.len());
+  limits::check_source(src.len())?;
+  log_parse(src, lang);
   let root = lang.ast_grep(src);
-  let rule = deserialize_rule(config.rule, lang.clone())?;
-  let matchers = if let Some(c) = config.constraints {
-    try_deserialize_matchers(c, lang).unwrap()
-  } else {
-    MetaVarMatchers::default()
+  limits::check_depth(&root)?;
+  Ok(root)
+}
+
+/// A parsed source which can be searched, fixed and dumped repeatedly
+/// so "type rule, see results" loops only parse when the source changes.
+#[wasm_bindgen]
+pub struct SgRoot {
+  // shared with the SgNodes of the root, an edit copies the tree if nodes are still alive
+  inner: Rc<AstGrep<ts::Language>>,
+  // configs given to the root are compiled with the cache of the context that parsed it
+  ctx: Rc<Context>,
+  // built by the first offset or position conversion, dropped by edits
+  lines: lines::Lines,
+  // bytes replaced while decoding a `Uint8Array` source
+  invalid_utf8: Vec<source::InvalidSequence>,
+  // rules of `watchRules` with their last matches
+  live: Option<live::LiveRules>,
+}
+
+impl SgRoot {
+  fn new(inner: AstGrep<ts::Language>, ctx: Rc<Context>) -> Self {
+    let len = inner.source().len();
+    stats::track(|live| {
+      live.roots += 1;
+      live.root_source_bytes += len;
+    });
+    Self {
+      inner: Rc::new(inner),
+      ctx,
+      lines: lines::Lines::default(),
+      invalid_utf8: vec![],
+      live: None,
+    }
+  }
+}
+
+impl Drop for SgRoot {
+  fn drop(&mut self) {
+    let len = self.inner.source().len();
+    stats::track(|live| {
+      live.roots -= 1;
+      live.root_source_bytes -= len;
+    });
+  }
+}
+
+/// Parse `src` with the language registered as `language`, or the current one.
+/// `includedRanges` (`[{ startOffset, endOffset }]` in JS string indices) limits parsing
+/// to those regions, e.g. code blocks of a markdown document,
+/// and positions stay relative to the whole `src`.
+/// The text outside is blanked to spaces rather than skipped like tree-sitter's included ranges:
+/// `text()`, fixes and metavariables read the blanks, a token split across two ranges
+/// parses as two tokens, and blanks before a range count as indentation, e.g. for Python.
+/// Sources may be bytes like for `findNodes`, `SgRoot.invalidUtf8` reports the replaced ones.
+#[wasm_bindgen]
+pub fn parse(
+  src: SourceInput,
+  language: Option<String>,
+  included_ranges: JsValue,
+) -> Result<SgRoot, JsValue> {
+  global().parse(source::decode(src.into())?, language, included_ranges)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextRange {
+  start_offset: usize,
+  end_offset: usize,
+}
+
+/// Blank every char of `src` outside `ranges` except line breaks.
+/// ast-grep-core parses the source itself so the parser's included ranges are unreachable,
+/// each char turns into as many spaces as it is wide in node offsets, UTF-16 units on wasm32
+/// and bytes natively, so offsets, rows and columns of the nodes stay those of the original.
+fn mask_outside(src: &str, ranges: &[TextRange]) -> Result<String, JsValue> {
+  let lines = lines::LineIndex::new(src);
+  let mut inside = vec![false; src.len()];
+  for range in ranges {
+    let start = lines.checked_byte(src, range.start_offset);
+    let end = lines.checked_byte(src, range.end_offset);
+    match (start, end) {
+      (Some(start), Some(end)) if start <= end => inside[start..end].fill(true),
+      _ => {
+        return Err(error(
+          Code::InvalidArgument,
+          "included range is out of bounds",
+        ))
+      }
+    }
+  }
+  let mut masked = String::with_capacity(src.len());
+  // ranges start and end on char boundaries, so every char is kept or blanked whole
+  for (i, c) in src.char_indices() {
+    match c {
+      '\n' | '\r' => masked.push(c),
+      _ if inside[i] => masked.push(c),
+      _ if cfg!(target_arch = "wasm32") => {
+        masked.extend(std::iter::repeat(' ').take(c.len_utf16()))
+      }
+      _ => masked.extend(std::iter::repeat(' ').take(c.len_utf8())),
+    }
+  }
+  Ok(masked)
+}
+
+/// Parse a source supplied in chunks by `read(index)`, following web-tree-sitter's input callback:
+/// `index` is the UTF-16 offset of the next chunk and an empty string or `undefined` ends the input.
+/// ast-grep-core keeps the whole source, so chunks are joined in wasm memory
+/// instead of crossing the JS/WASM boundary as one huge string.
+#[wasm_bindgen(js_name = parseChunks)]
+pub fn parse_chunks(read: js_sys::Function, language: Option<String>) -> Result<SgRoot, JsValue> {
+  global().parse_chunks(read, language)
+}
+
+impl Context {
+  fn parse(
+    self: Rc<Self>,
+    src: source::Decoded,
+    language: Option<String>,
+    included_ranges: JsValue,
+  ) -> Result<SgRoot, JsValue> {
+    let (src, invalid_utf8) = (src.text, src.invalid);
+    utils::enter("parse", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let ranges: Option<Vec<TextRange>> =
+      serde_wasm_bindgen::from_value(included_ranges).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let src = match ranges {
+      Some(ranges) => mask_outside(&src, &ranges)?,
+      None => src,
+    };
+    let inner = parse_root(&src, &lang)?;
+    let mut root = SgRoot::new(inner, self);
+    root.invalid_utf8 = invalid_utf8;
+    Ok(root)
+  }
+
+  fn parse_chunks(
+    self: Rc<Self>,
+    read: js_sys::Function,
+    language: Option<String>,
+  ) -> Result<SgRoot, JsValue> {
+    utils::enter("parseChunks", 0);
+    let lang = self.get_language(language.as_deref())?;
+    let mut src = String::new();
+    let mut index = 0;
+    loop {
+      let chunk = read.call1(&JsValue::NULL, &JsValue::from(index as f64))?;
+      if chunk.is_undefined() || chunk.is_null() {
+        break;
+      }
+      let chunk = chunk
+        .as_string()
+        .ok_or_else(|| error(Code::InvalidArgument, "read callback must return a string"))?;
+      if chunk.is_empty() {
+        break;
+      }
+      index += chunk.encode_utf16().count();
+      src.push_str(&chunk);
+      // stop reading as soon as the source is too large
+      limits::check_source(src.len())?;
+    }
+    let inner = parse_root(&src, &lang)?;
+    Ok(SgRoot::new(inner, self))
+  }
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  /// Free the tree and source now instead of when JS garbage collects the root.
+  pub fn dispose(self) {}
+
+  /// `[{ byteOffset, length }]`, the bytes of a `Uint8Array` source that were no UTF-8
+  /// and are U+FFFD in the parsed text, empty for string sources.
+  #[wasm_bindgen(js_name = invalidUtf8)]
+  pub fn invalid_utf8(&self) -> Result<JsValue, JsValue> {
+    Ok(serialize::to_value(&self.invalid_utf8)?)
+  }
+
+  #[wasm_bindgen(js_name = findNodes)]
+  pub fn find_nodes(
+    &self,
+    config: Config,
+    cancel: Option<CancelSignal>,
+  ) -> Result<Matches, JsValue> {
+    utils::enter("SgRoot.findNodes", self.inner.source().len());
+    let mut metrics = Metrics::start();
+    let rule = self.compile(config.into())?;
+    metrics.compiled();
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.timeout_ms);
+    find_in_root(&self.inner, &rule, &mut check, metrics).map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = fixErrors)]
+  pub fn fix_errors(&self, config: Config) -> Result<String, JsValue> {
+    utils::enter("SgRoot.fixErrors", self.inner.source().len());
+    let rule = self.compile(config.into())?;
+    fix_in_root(&self.inner, &rule)
+  }
+
+  #[wasm_bindgen(js_name = scanRules)]
+  pub fn scan_rules(
+    &self,
+    configs: ConfigArray,
+    cancel: Option<CancelSignal>,
+  ) -> Result<ScanResult, JsValue> {
+    utils::enter("SgRoot.scanRules", self.inner.source().len());
+    let mut metrics = Metrics::start();
+    let rules = configs
+      .iter()
+      .map(|config| self.compile(config))
+      .collect::<Result<Vec<_>, _>>()?;
+    metrics.compiled();
+    let mut check = CancelCheck::new(cancel.as_ref(), min_timeout(&rules));
+    scan_in_root(&self.inner, &rules, &mut check, metrics).map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = findRule)]
+  pub fn find_rule(
+    &self,
+    rule: &RuleHandle,
+    cancel: Option<CancelSignal>,
+  ) -> Result<Matches, JsValue> {
+    utils::enter("SgRoot.findRule", self.inner.source().len());
+    self.check_language(&rule.inner)?;
+    let metrics = Metrics::start();
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.inner.timeout_ms);
+    find_in_root(&self.inner, &rule.inner, &mut check, metrics).map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = fixRule)]
+  pub fn fix_rule(&self, rule: &RuleHandle) -> Result<String, JsValue> {
+    utils::enter("SgRoot.fixRule", self.inner.source().len());
+    self.check_language(&rule.inner)?;
+    fix_in_root(&self.inner, &rule.inner)
+  }
+
+  #[wasm_bindgen(js_name = dumpASTNodes)]
+  pub fn dump_ast_nodes(&self) -> Result<DumpNode, JsValue> {
+    utils::enter("SgRoot.dumpASTNodes", self.inner.source().len());
+    dump_root(&self.inner).map(JsCast::unchecked_into)
+  }
+
+  /// Replace `startOffset..oldEndOffset` with `newText` and reparse incrementally,
+  /// reusing the old tree. Offsets are JS string indices.
+  /// Returns `[{ range, startOffset, endOffset }]`, the outermost nodes of the new tree
+  /// whose syntax or text changed, so editors can rescan and rehighlight only those.
+  #[wasm_bindgen(js_name = applyEdit)]
+  pub fn apply_edit(&mut self, edit: JsValue) -> Result<ChangedRanges, JsValue> {
+    utils::enter("SgRoot.applyEdit", self.inner.source().len());
+    let (old, edit) = self.edit_source(edit)?;
+    // the watched matches would not follow the edit
+    self.live = None;
+    let (src, lines) = (self.inner.source(), self.lines());
+    let changed: Vec<_> = changes::changed_nodes(&old, self.inner.root(), src, lines, &edit)
+      .iter()
+      .map(|node| changes::ChangedRange::new(node, lines))
+      .collect();
+    serialize::to_value(&changed)
+      .map(JsCast::unchecked_into)
+      .map_err(Into::into)
+  }
+}
+
+impl SgRoot {
+  /// Apply an edit of `applyEdit`, returning what the diff needs of the old tree and the edit.
+  fn edit_source(
+    &mut self,
+    edit: JsValue,
+  ) -> Result<(changes::OldNode, changes::RangeEdit), JsValue> {
+    let edit: TextEdit =
+      serde_wasm_bindgen::from_value(edit).map_err(|e| tag(Code::InvalidEdit, e))?;
+    let src = self.inner.source();
+    let new_len: usize = edit.new_text.encode_utf16().count();
+    if edit.start_offset > edit.old_end_offset {
+      let msg = "startOffset must not be after oldEndOffset";
+      return Err(error(Code::InvalidEdit, msg));
+    }
+    // offsets come from JS, their sum may not fit
+    if edit.start_offset.checked_add(new_len) != Some(edit.new_end_offset) {
+      let msg = "newEndOffset must be startOffset plus the length of newText";
+      return Err(error(Code::InvalidEdit, msg));
+    }
+    let lines = self.lines();
+    let to_byte = |offset| {
+      lines.checked_byte(src, offset).ok_or_else(|| {
+        let message = format!("offset {} is out of the source", offset);
+        error_with(Code::InvalidEdit, &message, &[("offset", offset.into())])
+      })
+    };
+    let position = to_byte(edit.start_offset)?;
+    let deleted_length = to_byte(edit.old_end_offset)? - position;
+    let inserted_length = edit.new_text.len();
+    let old_len = src.len();
+    limits::check_source(old_len - deleted_length + inserted_length)?;
+    let start = lines.node_offset(position);
+    let range_edit = changes::RangeEdit {
+      position: start,
+      deleted: lines.node_offset(position + deleted_length) - start,
+      // node offsets count JS string indices on wasm32 and bytes elsewhere
+      inserted: if cfg!(target_arch = "wasm32") {
+        new_len
+      } else {
+        inserted_length
+      },
+    };
+    let old = changes::OldNode::new(self.inner.root(), src, lines, &range_edit);
+    // core does not export its Edit type, derive one from an empty replacement instead
+    let mut ts_edit = NodeMatch::from(self.inner.root()).replace_by("");
+    ts_edit.position = position;
+    ts_edit.deleted_length = deleted_length;
+    ts_edit.inserted_text = edit.new_text;
+    // copies the root only if nodes of the old source are alive
+    Rc::make_mut(&mut self.inner).edit(ts_edit);
+    let src = self.inner.source();
+    self
+      .lines
+      .edit(src, position, deleted_length, inserted_length);
+    stats::track(|live| live.root_source_bytes = live.root_source_bytes - old_len + src.len());
+    log_parse(src, self.inner.lang());
+    Ok((old, range_edit))
+  }
+
+  fn compile(&self, config: JsValue) -> Result<Rc<CompiledRule>, JsValue> {
+    compile_for_root(&self.ctx, &self.inner, config)
+  }
+
+  fn check_language(&self, rule: &CompiledRule) -> Result<(), JsValue> {
+    check_root_language(&self.ctx, &self.inner, rule)
+  }
+}
+
+fn compile_for_root(
+  ctx: &Context,
+  root: &AstGrep<ts::Language>,
+  config: JsValue,
+) -> Result<Rc<CompiledRule>, JsValue> {
+  // configs without language are compiled for the root's language
+  let language = js_sys::Reflect::get(&config, &"language".into()).unwrap_or_default();
+  if language.is_undefined() || language.is_null() {
+    let lang = Rc::new(root.lang().clone());
+    return ctx.compile_config_with(config, lang);
+  }
+  let rule = ctx.compile_config(config)?;
+  check_root_language(ctx, root, &rule)?;
+  Ok(rule)
+}
+
+fn check_root_language(
+  ctx: &Context,
+  root: &AstGrep<ts::Language>,
+  rule: &CompiledRule,
+) -> Result<(), JsValue> {
+  if *rule.lang == *root.lang() {
+    return Ok(());
+  }
+  // `null` for languages set up from bytes, which have no name
+  let rule_name = ctx.language_name_of(&rule.lang);
+  let root_name = ctx.language_name_of(root.lang());
+  let name = |name: &Option<String>| match name {
+    Some(name) => format!(" `{}`", name),
+    None => String::new(),
   };
-  let config = RuleWithConstraint { rule, matchers };
-  let ret: Vec<_> = root
-    .root()
-    .find_all(config)
-    .map(|n| {
-      let start = n.start_pos();
-      let end = n.end_pos();
-      vec![start.0, start.1, end.0, end.1]
-    })
+  let msg = format!(
+    "the rule language{} is not the language{} of the parsed root",
+    name(&rule_name),
+    name(&root_name)
+  );
+  let params = [
+    ("rule", rule_name.map_or(JsValue::NULL, JsValue::from)),
+    ("root", root_name.map_or(JsValue::NULL, JsValue::from)),
+  ];
+  Err(error_with(Code::LanguageMismatch, &msg, &params))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextEdit {
+  start_offset: usize,
+  old_end_offset: usize,
+  new_end_offset: usize,
+  new_text: String,
+}
+
+/// Drop the compiled rule cache and the memoized last results.
+/// Handles returned to JS own their memory and must be disposed one by one,
+/// `getMemoryStats` counts the ones still alive.
+#[wasm_bindgen(js_name = disposeAll)]
+pub fn dispose_all() {
+  global().clear();
+}
+
+/// Find the matches of `config` in `src`. A `Uint8Array` source is decoded as UTF-8,
+/// invalid bytes become U+FFFD and are reported as `invalidUtf8` of the result.
+#[wasm_bindgen(js_name = findNodes)]
+pub fn find_nodes(
+  src: SourceInput,
+  config: Config,
+  cancel: Option<CancelSignal>,
+) -> Result<Matches, JsValue> {
+  source::scan_decoded(src.into(), |src| {
+    global().find_nodes(src, config.into(), cancel)
+  })
+  .map(JsCast::unchecked_into)
+}
+
+#[wasm_bindgen(js_name = fixErrors)]
+pub fn fix_errors(src: String, config: Config) -> Result<String, JsValue> {
+  global().fix_errors(src, config.into())
+}
+
+/// Scan `src` with many configs at once, returning the matches of each config in order.
+/// Sources may be bytes like for `findNodes`.
+#[wasm_bindgen(js_name = scanRules)]
+pub fn scan_rules(
+  src: SourceInput,
+  configs: ConfigArray,
+  cancel: Option<CancelSignal>,
+) -> Result<ScanResult, JsValue> {
+  source::scan_decoded(src.into(), |src| {
+    global().scan_rules(src, configs.into(), cancel)
+  })
+  .map(JsCast::unchecked_into)
+}
+
+/// Scan `[{ name, src }]` with many configs in one call, returning `[{ name, matches }]`
+/// where `matches` has the shape of the result of `scanRules`.
+/// Sources after the time budget is spent are left out and the array is marked `timedOut`.
+/// With `coverage`, the array has `unmatched` too, the configs matching in none of the sources.
+#[wasm_bindgen(js_name = scanMany)]
+pub fn scan_many(
+  sources: JsValue,
+  configs: ConfigArray,
+  cancel: Option<CancelSignal>,
+) -> Result<ScanManyResult, JsValue> {
+  global()
+    .scan_many(sources, configs.into(), cancel)
+    .map(JsCast::unchecked_into)
+}
+
+#[wasm_bindgen(js_name = dumpASTNodes)]
+pub fn dump_ast_nodes(src: String, language: Option<String>) -> Result<DumpNode, JsValue> {
+  global()
+    .dump_ast_nodes(src, language)
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Deserialize)]
+struct SourceFile {
+  name: String,
+  src: String,
+}
+
+impl Context {
+  fn find_nodes(
+    &self,
+    src: String,
+    config: JsValue,
+    cancel: Option<CancelSignal>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findNodes", src.len());
+    let mut metrics = Metrics::start();
+    let rule = self.compile_config(config)?;
+    metrics.compiled();
+    // metrics should time real work and traces need the tree, so they bypass the memo
+    if !rule.metrics && rule.trace.is_none() {
+      let memo = self.find_memo.borrow();
+      let memo = memo.get(&src, &rule).map(serialize::to_value);
+      if let Some(ret) = memo {
+        return Ok(ret?);
+      }
+    }
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.timeout_ms);
+    let root = parse_root(&src, &rule.lang)?;
+    metrics.parsed();
+    let mut ranges = vec![];
+    find_ranges(&root, &rule, &mut check, &mut ranges)?;
+    let ret = matches_value(&ranges, &check, rule.metrics.then_some(metrics))?;
+    trace::attach_traces(&ret, &root, &rule, ranges.len())?;
+    if !check.timed_out {
+      self.find_memo.borrow_mut().set(src, rule, ranges);
+    }
+    Ok(ret)
+  }
+
+  fn fix_errors(&self, src: String, config: JsValue) -> Result<String, JsValue> {
+    utils::enter("fixErrors", src.len());
+    let rule = self.compile_config(config)?;
+    if let Some(ret) = self.fix_memo.borrow().get(&src, &rule).cloned() {
+      return Ok(ret);
+    }
+    let root = parse_root(&src, &rule.lang)?;
+    let ret = fix_in_root(&root, &rule)?;
+    self.fix_memo.borrow_mut().set(src, rule, ret.clone());
+    Ok(ret)
+  }
+
+  fn scan_rules(
+    &self,
+    src: String,
+    configs: js_sys::Array,
+    cancel: Option<CancelSignal>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("scanRules", src.len());
+    let mut metrics = Metrics::start();
+    let rules = self.compile_configs(configs)?;
+    metrics.compiled();
+    let lang = match rules.first() {
+      Some(rule) => rule.lang.clone(),
+      None => return Ok(js_sys::Array::new().into()),
+    };
+    let mut check = CancelCheck::new(cancel.as_ref(), min_timeout(&rules));
+    let root = parse_root(&src, &lang)?;
+    metrics.parsed();
+    scan_in_root(&root, &rules, &mut check, metrics)
+  }
+
+  fn scan_many(
+    &self,
+    sources: JsValue,
+    configs: js_sys::Array,
+    cancel: Option<CancelSignal>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("scanMany", 0);
+    let sources: Vec<SourceFile> =
+      serde_wasm_bindgen::from_value(sources).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    let lang = match rules.first() {
+      Some(rule) => rule.lang.clone(),
+      None => return Ok(ret.into()),
+    };
+    let mut check = CancelCheck::new(cancel.as_ref(), min_timeout(&rules));
+    // a config is dead only if it matched in none of the sources
+    let mut matched = vec![false; rules.len()];
+    for file in sources {
+      if check.timed_out {
+        js_sys::Reflect::set(&ret, &"timedOut".into(), &JsValue::TRUE)?;
+        break;
+      }
+      let mut metrics = Metrics::start();
+      let root = parse_root(&file.src, &lang)?;
+      metrics.parsed();
+      let mut per_rule = per_rule_metrics(&rules);
+      let ranges = scan_ranges(&root, &rules, &mut check, per_rule.as_deref_mut())?;
+      for (matched, ranges) in matched.iter_mut().zip(&ranges) {
+        *matched |= !ranges.is_empty();
+      }
+      let matches = scan_value(&ranges, &rules, &check, metrics, per_rule)?;
+      let entry = js_sys::Object::new();
+      js_sys::Reflect::set(&entry, &"name".into(), &file.name.into())?;
+      js_sys::Reflect::set(&entry, &"matches".into(), &matches)?;
+      ret.push(&entry);
+    }
+    attach_unmatched(&ret, &rules, &matched)?;
+    Ok(ret.into())
+  }
+
+  fn dump_ast_nodes(&self, src: String, language: Option<String>) -> Result<JsValue, JsValue> {
+    utils::enter("dumpASTNodes", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    dump_root(&root)
+  }
+}
+
+// a fixed size array serializes to the same JS array without a heap allocation per match
+fn match_range(n: &Node<ts::Language>) -> [usize; 4] {
+  let start = n.start_pos();
+  let end = n.end_pos();
+  [start.0, start.1, end.0, end.1]
+}
+
+thread_local! {
+  // reused by scans of parsed roots to avoid regrowing a vector on every call
+  static RANGES: RefCell<Vec<[usize; 4]>> = const { RefCell::new(Vec::new()) };
+}
+
+fn find_in_root(
+  root: &AstGrep<ts::Language>,
+  rule: &CompiledRule,
+  check: &mut CancelCheck,
+  metrics: Metrics,
+) -> Result<JsValue, JsValue> {
+  RANGES.with(|ranges| {
+    let mut ranges = ranges.borrow_mut();
+    ranges.clear();
+    find_ranges(root, rule, check, &mut ranges)?;
+    let ret = matches_value(&*ranges, check, rule.metrics.then_some(metrics))?;
+    trace::attach_traces(&ret, root, rule, ranges.len())?;
+    Ok(ret)
+  })
+}
+
+fn find_ranges(
+  root: &AstGrep<ts::Language>,
+  rule: &CompiledRule,
+  check: &mut CancelCheck,
+  ranges: &mut Vec<[usize; 4]>,
+) -> Result<(), JsValue> {
+  // same as find_all but checking for cancellation between nodes
+  // reported when dropped, also when the scan errs
+  let mut visits = Visits::start();
+  for node in root.root().dfs() {
+    if !check.visit()? {
+      break;
+    }
+    if let Some(visits) = &mut visits {
+      visits.record(0, &node, rule);
+    }
+    if let Some(n) = rule.match_node(node) {
+      limits::check_matches(ranges.len() + 1)?;
+      ranges.push(match_range(&n));
+    }
+  }
+  Ok(())
+}
+
+fn matches_value<T: Serialize>(
+  matches: &T,
+  check: &CancelCheck,
+  metrics: Option<Metrics>,
+) -> Result<JsValue, JsValue> {
+  let ret = serialize::to_value(matches)?;
+  if check.timed_out {
+    js_sys::Reflect::set(&ret, &"timedOut".into(), &JsValue::TRUE)?;
+  }
+  if let Some(metrics) = metrics {
+    metrics.attach(&ret, check)?;
+  }
+  Ok(ret)
+}
+
+fn min_timeout(rules: &[Rc<CompiledRule>]) -> Option<f64> {
+  rules.iter().filter_map(|r| r.timeout_ms).reduce(f64::min)
+}
+
+fn scan_in_root(
+  root: &AstGrep<ts::Language>,
+  rules: &[Rc<CompiledRule>],
+  check: &mut CancelCheck,
+  metrics: Metrics,
+) -> Result<JsValue, JsValue> {
+  let mut per_rule = per_rule_metrics(rules);
+  let ranges = scan_ranges(root, rules, check, per_rule.as_deref_mut())?;
+  scan_value(&ranges, rules, check, metrics, per_rule)
+}
+
+fn per_rule_metrics(rules: &[Rc<CompiledRule>]) -> Option<Vec<RuleMetrics>> {
+  let with_metrics = rules.iter().any(|r| r.metrics);
+  with_metrics.then(|| vec![RuleMetrics::default(); rules.len()])
+}
+
+fn scan_value(
+  ranges: &[Vec<[usize; 4]>],
+  rules: &[Rc<CompiledRule>],
+  check: &CancelCheck,
+  mut metrics: Metrics,
+  per_rule: Option<Vec<RuleMetrics>>,
+) -> Result<JsValue, JsValue> {
+  let with_metrics = per_rule.is_some();
+  if let Some(per_rule) = per_rule {
+    metrics.per_rule(per_rule);
+  }
+  let ret = matches_value(&ranges, check, with_metrics.then_some(metrics))?;
+  let matched: Vec<_> = ranges.iter().map(|r| !r.is_empty()).collect();
+  attach_unmatched(&ret, rules, &matched)?;
+  Ok(ret)
+}
+
+/// Set `unmatched`, the indices of rules without matches, if a rule asks for `coverage`.
+fn attach_unmatched(
+  ret: &JsValue,
+  rules: &[Rc<CompiledRule>],
+  matched: &[bool],
+) -> Result<(), JsValue> {
+  if !rules.iter().any(|r| r.coverage) {
+    return Ok(());
+  }
+  let unmatched: js_sys::Array = matched
+    .iter()
+    .enumerate()
+    .filter(|(_, matched)| !**matched)
+    .map(|(i, _)| JsValue::from(i as u32))
     .collect();
-  let ret = serde_wasm_bindgen::to_value(&ret)?;
+  js_sys::Reflect::set(ret, &"unmatched".into(), &unmatched)?;
+  Ok(())
+}
+
+/// Match all rules in one traversal, nodes are only tried against rules that can match their kind.
+fn scan_ranges(
+  root: &AstGrep<ts::Language>,
+  rules: &[Rc<CompiledRule>],
+  check: &mut CancelCheck,
+  mut per_rule: Option<&mut [RuleMetrics]>,
+) -> Result<Vec<Vec<[usize; 4]>>, JsValue> {
+  let mut by_kind: HashMap<u16, Vec<usize>> = HashMap::new();
+  let mut any_kind = vec![];
+  for (i, rule) in rules.iter().enumerate() {
+    match rule.kinds() {
+      Some(kinds) => kinds
+        .iter()
+        .for_each(|k| by_kind.entry(*k).or_default().push(i)),
+      None => any_kind.push(i),
+    }
+  }
+  let mut ret = vec![vec![]; rules.len()];
+  let mut count = 0;
+  // reported when dropped, also when the scan errs
+  let mut visits = Visits::start();
+  for node in root.root().dfs() {
+    if !check.visit()? {
+      break;
+    }
+    let candidates = by_kind.get(&node.kind_id()).map_or(&[][..], |v| v);
+    for &i in candidates.iter().chain(&any_kind) {
+      if let Some(visits) = &mut visits {
+        visits.record(i, &node, &rules[i]);
+      }
+      // timing every node is only worth it when asked for
+      let start = per_rule.is_some().then(metrics::now);
+      let found = rules[i].match_node(node.clone());
+      if let (Some(per_rule), Some(start)) = (per_rule.as_deref_mut(), start) {
+        per_rule[i].match_ms += metrics::now() - start;
+        per_rule[i].nodes_visited += 1;
+      }
+      if let Some(n) = found {
+        // the cap is on the whole result, all rules together
+        count += 1;
+        limits::check_matches(count)?;
+        ret[i].push(match_range(&n));
+      }
+    }
+  }
   Ok(ret)
 }
 
-#[wasm_bindgen(js_name = fixErrors)]
-pub fn fix_errors(src: String, config: JsValue) -> Result<String, JsError> {
-  let config: WASMConfig = serde_wasm_bindgen::from_value(config)?;
-  let lang = INSTANCE
-    .lock()
-    .expect_throw("get language error")
-    .clone()
-    .expect_throw("current language is not set");
-  let fixer = config.fix.expect_throw("fix is required for rewriting");
-  let fixer = Pattern::new(&fixer, lang.clone());
-  let root = lang.ast_grep(&src);
-  let rule = deserialize_rule(config.rule, lang.clone())?;
-  let matchers = if let Some(c) = config.constraints {
-    try_deserialize_matchers(c, lang).unwrap()
-  } else {
-    MetaVarMatchers::default()
-  };
-  let config = RuleWithConstraint { rule, matchers };
-  let edits: Vec<_> = root.root().replace_all(config, fixer);
-  let mut new_content = String::new();
+fn fix_in_root(root: &AstGrep<ts::Language>, rule: &CompiledRule) -> Result<String, JsValue> {
+  let fixer = rule
+    .fixer
+    .as_ref()
+    .ok_or_else(|| error(Code::MissingFix, "fix is required for rewriting"))?;
+  let edits: Vec<_> = root.root().replace_all(rule, fixer);
+  let src = root.source();
+  let mut new_content = String::with_capacity(src.len());
   let mut start = 0;
   for edit in edits {
     new_content.push_str(&src[start..edit.position]);
@@ -95,22 +1152,35 @@ pub fn fix_errors(src: String, config: JsValue) -> Result<String, JsError> {
   }
   // add trailing statements
   new_content.push_str(&src[start..]);
-  Ok(new_content)
+  Ok(rule.line_endings.apply(src, new_content))
 }
 
 #[derive(Deserialize, Serialize)]
 struct DebugNode {
-  kind: String,
+  /// index in a pre-order walk of the dump, see `target::node_by_id`
+  id: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  kind: Option<String>,
+  #[serde(rename = "kindId", skip_serializing_if = "Option::is_none")]
+  kind_id: Option<u16>,
   start: (usize, usize),
   end: (usize, usize),
   is_named: bool,
   children: Vec<DebugNode>,
 }
 
-fn convert_to_debug_node(n: Node<ts::Language>) -> DebugNode {
-  let children = n.children().map(convert_to_debug_node).collect();
+fn convert_to_debug_node(n: Node<ts::Language>, next_id: &mut usize) -> DebugNode {
+  let id = *next_id;
+  *next_id += 1;
+  let children = n
+    .children()
+    .map(|c| convert_to_debug_node(c, next_id))
+    .collect();
+  let (kind, kind_id) = kinds::kind_of(&n);
   DebugNode {
-    kind: n.kind().to_string(),
+    id,
+    kind,
+    kind_id,
     start: n.start_pos(),
     end: n.end_pos(),
     is_named: n.is_named(),
@@ -118,28 +1188,125 @@ fn convert_to_debug_node(n: Node<ts::Language>) -> DebugNode {
   }
 }
 
-#[wasm_bindgen(js_name = dumpASTNodes)]
-pub fn dump_ast_nodes(src: String) -> Result<JsValue, JsError> {
-  let lang = INSTANCE
-    .lock()
-    .expect_throw("get language error")
-    .clone()
-    .expect_throw("current language is not set");
-  let root = lang.ast_grep(&src);
-  let debug_node = convert_to_debug_node(root.root());
-  let ret = serde_wasm_bindgen::to_value(&debug_node)?;
+fn dump_root(root: &AstGrep<ts::Language>) -> Result<JsValue, JsValue> {
+  let debug_node = convert_to_debug_node(root.root(), &mut 0);
+  let ret = serialize::to_value(&debug_node)?;
   Ok(ret)
 }
 
+fn js_error(err: JsValue) -> JsError {
+  match err.dyn_into::<js_sys::Error>() {
+    Ok(error) => JsError::new(&String::from(error.message())),
+    Err(value) => JsError::new(&value.as_string().unwrap_or_else(|| format!("{:?}", value))),
+  }
+}
+
+/// unwrap promises and fetch-like responses into a path or bytes
+async fn resolve_source(source: JsValue) -> Result<JsValue, JsError> {
+  use wasm_bindgen_futures::JsFuture;
+  let source = match source.dyn_into::<js_sys::Promise>() {
+    Ok(promise) => JsFuture::from(promise).await.map_err(js_error)?,
+    Err(source) => source,
+  };
+  if !source.is_object() {
+    return Ok(source);
+  }
+  let array_buffer = js_sys::Reflect::get(&source, &"arrayBuffer".into()).map_err(js_error)?;
+  match array_buffer.dyn_into::<js_sys::Function>() {
+    Ok(array_buffer) => {
+      let promise = array_buffer.call0(&source).map_err(js_error)?;
+      let promise = promise.unchecked_into::<js_sys::Promise>();
+      JsFuture::from(promise).await.map_err(js_error)
+    }
+    Err(_) => Ok(source),
+  }
+}
+
+/// grammars compiled into the binary by `lang-*` features
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+fn embedded_grammar(name: &str) -> Option<&'static [u8]> {
+  match name {
+    #[cfg(feature = "lang-javascript")]
+    "javascript" => Some(include_bytes!(
+      "../website/public/tree-sitter-javascript.wasm"
+    )),
+    #[cfg(feature = "lang-typescript")]
+    "typescript" => Some(include_bytes!(
+      "../website/public/tree-sitter-typescript.wasm"
+    )),
+    _ => None,
+  }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+async fn load_grammar(source: JsValue) -> Result<web_tree_sitter_sg::Language, JsValue> {
+  let loader = GRAMMAR_LOADER.with(|l| l.borrow().clone());
+  let source = match source.as_string() {
+    Some(name) => match (embedded_grammar(&name), loader) {
+      (Some(bytes), _) => js_sys::Uint8Array::from(bytes).into(),
+      (None, Some(loader)) => loader.call1(&JsValue::NULL, &source).map_err(js_error)?,
+      (None, None) => source,
+    },
+    None => source,
+  };
+  let source = resolve_source(source).await?;
+  let lang = if let Some(path) = source.as_string() {
+    web_tree_sitter_sg::Language::load_path(&path).await
+  } else if let Some(bytes) = source.dyn_ref::<js_sys::Uint8Array>() {
+    // Node.js Buffer is a Uint8Array subclass
+    web_tree_sitter_sg::Language::load_bytes(bytes).await
+  } else if let Some(buffer) = source.dyn_ref::<js_sys::ArrayBuffer>() {
+    let bytes = js_sys::Uint8Array::new(buffer);
+    web_tree_sitter_sg::Language::load_bytes(&bytes).await
+  } else if source.is_instance_of::<js_sys::WebAssembly::Module>() {
+    // web-tree-sitter instantiates grammars from bytes via emscripten's dylink,
+    // a compiled module cannot be handed to it.
+    let msg =
+      "WebAssembly.Module is not supported by web-tree-sitter, pass the grammar bytes instead";
+    return Err(error(Code::InvalidArgument, msg));
+  } else {
+    return Err(
+      JsError::new("parser source must be a path, a Uint8Array or an ArrayBuffer").into(),
+    );
+  };
+  lang.map_err(|e| JsError::new(&String::from(e.message())).into())
+}
+
 #[cfg(target_arch = "wasm32")]
-async fn get_lang(parser_path: String) -> Result<ts::Language, JsError> {
-  let lang = web_tree_sitter_sg::Language::load_path(&parser_path)
-    .await
-    .map_err(ts::LanguageError::from)?;
-  Ok(ts::Language::from(lang))
+async fn get_lang(source: JsValue) -> Result<ts::Language, JsValue> {
+  let grammar = load_grammar(source).await?;
+  let lang = ts::Language::from(grammar.clone());
+  tags::remember_grammar(&lang, grammar);
+  Ok(lang)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-async fn get_lang(_path: String) -> Result<ts::Language, JsError> {
+async fn get_lang(_source: JsValue) -> Result<ts::Language, JsValue> {
   unreachable!()
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn range(start_offset: usize, end_offset: usize) -> TextRange {
+    TextRange {
+      start_offset,
+      end_offset,
+    }
+  }
+
+  #[test]
+  fn test_mask_outside() {
+    let src = "# é\n```\nlet a = 1;\n```\n";
+    let masked = mask_outside(src, &[range(8, 19)]).unwrap();
+    // é is two bytes wide natively
+    assert_eq!(masked, "    \n   \nlet a = 1;\n   \n");
+    assert_eq!(masked.len(), src.len());
+    // the blanks become the indentation of the kept text
+    let masked = mask_outside("  x = 1\n", &[range(2, 7)]).unwrap();
+    assert_eq!(masked, "  x = 1\n");
+    let masked = mask_outside("- x = 1\n", &[range(2, 7)]).unwrap();
+    assert_eq!(masked, "  x = 1\n");
+  }
+}
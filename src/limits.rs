@@ -0,0 +1,107 @@
+use crate::error::{error, set_params, tag, Code};
+
+use ast_grep_core::AstGrep;
+use serde::Deserialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::cell::Cell;
+
+/// Caps checked by every scan, unset ones are unlimited.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Limits {
+  /// matches a single result may hold
+  max_matches: Option<usize>,
+  /// UTF-8 bytes of a parsed source
+  max_source_bytes: Option<usize>,
+  /// nesting of the parsed tree, the root is at depth 0
+  max_tree_depth: Option<usize>,
+}
+
+thread_local! {
+  static LIMITS: Cell<Limits> = Cell::new(Limits::default());
+}
+
+/// Set caps protecting the page from huge sources or rules matching nearly everything,
+/// e.g. `{ maxMatches: 10000, maxSourceBytes: 4 << 20, maxTreeDepth: 1000 }`.
+/// A scan over a cap throws an `Error` named `LimitExceeded` with `limit`, `max` and `actual`,
+/// where matches and depth stop being counted at the first one over the cap.
+/// `findNodesIter` and `findNodesEach` hand out matches one by one and ignore `maxMatches`.
+#[wasm_bindgen(js_name = setLimits)]
+pub fn set_limits(limits: JsValue) -> Result<(), JsValue> {
+  let limits: Limits = if limits.is_undefined() || limits.is_null() {
+    Limits::default()
+  } else {
+    serde_wasm_bindgen::from_value(limits).map_err(|e| tag(Code::InvalidArgument, e))?
+  };
+  LIMITS.with(|l| l.set(limits));
+  Ok(())
+}
+
+/// The caps of the last `setLimits`.
+pub fn current() -> Limits {
+  LIMITS.with(Cell::get)
+}
+
+#[derive(Debug)]
+struct LimitExceeded {
+  limit: &'static str,
+  max: usize,
+  actual: usize,
+}
+
+impl std::fmt::Display for LimitExceeded {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} of {} exceeded", self.limit, self.max)
+  }
+}
+
+impl From<LimitExceeded> for JsValue {
+  fn from(e: LimitExceeded) -> Self {
+    let error: js_sys::Error = error(Code::LimitExceeded, &e.to_string()).unchecked_into();
+    error.set_name("LimitExceeded");
+    let props = [
+      ("limit", JsValue::from(e.limit)),
+      ("max", JsValue::from(e.max as f64)),
+      ("actual", JsValue::from(e.actual as f64)),
+    ];
+    set_params(&error, &props);
+    error.into()
+  }
+}
+
+fn check(limit: &'static str, max: Option<usize>, actual: usize) -> Result<(), JsValue> {
+  match max {
+    Some(max) if actual > max => Err(LimitExceeded { limit, max, actual }.into()),
+    _ => Ok(()),
+  }
+}
+
+/// Err if a source of `len` bytes is over `maxSourceBytes`.
+pub fn check_source(len: usize) -> Result<(), JsValue> {
+  let limits = LIMITS.with(Cell::get);
+  check("maxSourceBytes", limits.max_source_bytes, len)
+}
+
+/// Err once a result holds more than `maxMatches`.
+pub fn check_matches(count: usize) -> Result<(), JsValue> {
+  let limits = LIMITS.with(Cell::get);
+  check("maxMatches", limits.max_matches, count)
+}
+
+/// Err if the tree of `root` nests deeper than `maxTreeDepth`.
+pub fn check_depth(root: &AstGrep<ts::Language>) -> Result<(), JsValue> {
+  let max = match LIMITS.with(Cell::get).max_tree_depth {
+    Some(max) => max,
+    None => return Ok(()),
+  };
+  // walk with an explicit stack, stopping at the first node over the cap
+  let mut stack = vec![(root.root(), 0)];
+  while let Some((node, depth)) = stack.pop() {
+    check("maxTreeDepth", Some(max), depth)?;
+    stack.extend(node.children().map(|child| (child, depth + 1)));
+  }
+  Ok(())
+}
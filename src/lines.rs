@@ -0,0 +1,442 @@
+use crate::error::{error_with, Code};
+use crate::types::TextPosition;
+use crate::{serialize, SgRoot};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+/// Line starts of a source in JS string indices, built once per source
+/// so offsets and positions convert without scanning for line breaks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineIndex {
+  starts: Vec<usize>,
+  /// where the line break of each line starts
+  ends: Vec<usize>,
+  len: usize,
+  /// the UTF-8 byte and JS string index after each non-ASCII char, both advance alike between them
+  wide: Vec<(usize, usize)>,
+}
+
+/// A `LineIndex` built on first use, shared by a root and its nodes.
+#[derive(Clone, Default)]
+pub struct Lines(Rc<OnceCell<LineIndex>>);
+
+impl Lines {
+  pub fn get(&self, src: &str) -> &LineIndex {
+    self.0.get_or_init(|| LineIndex::new(src))
+  }
+
+  /// Follow an edit of `LineIndex::edit`, nodes of the old source keep the old index.
+  pub fn edit(&mut self, src: &str, at: usize, deleted: usize, inserted: usize) {
+    if let Some(index) = Rc::make_mut(&mut self.0).get_mut() {
+      index.edit(src, at, deleted, inserted);
+    }
+  }
+}
+
+/// Zero based, the column in JS string indices like web-tree-sitter counts them.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+}
+
+/// An offset, line or column outside of the source, thrown as `INVALID_ARGUMENT`.
+#[derive(Debug, PartialEq)]
+pub struct OutOfRange {
+  message: String,
+  params: Vec<(&'static str, usize)>,
+}
+
+impl From<OutOfRange> for JsValue {
+  fn from(e: OutOfRange) -> Self {
+    let params: Vec<_> = e
+      .params
+      .iter()
+      .map(|(key, value)| (*key, JsValue::from(*value)))
+      .collect();
+    error_with(Code::InvalidArgument, &e.message, &params)
+  }
+}
+
+impl LineIndex {
+  pub fn new(src: &str) -> Self {
+    let mut starts = vec![0];
+    let mut ends = vec![];
+    let mut len = 0;
+    let mut wide = vec![];
+    let mut prev = None;
+    for (byte, c) in src.char_indices() {
+      len += c.len_utf16();
+      if !c.is_ascii() {
+        wide.push((byte + c.len_utf8(), len));
+      }
+      if c == '\n' {
+        // `\r\n` is one line break, a column cannot point between the two
+        ends.push(if prev == Some('\r') { len - 2 } else { len - 1 });
+        starts.push(len);
+      }
+      prev = Some(c);
+    }
+    ends.push(len);
+    Self {
+      starts,
+      ends,
+      len,
+      wide,
+    }
+  }
+
+  /// Follow replacing `deleted` bytes at byte `at` with `inserted` bytes, `src` being the new
+  /// source. Only the lines from the edit to the end of the inserted text are scanned again.
+  pub fn edit(&mut self, src: &str, at: usize, deleted: usize, inserted: usize) {
+    let line = self.locate(self.utf16(at)).line;
+    let start = self.starts[line];
+    let start_byte = self.byte(start);
+    // the rest of the last line edited, up to and with its line break
+    let rest = &src[at + inserted..];
+    let tail = rest.find('\n').map_or(rest.len(), |i| i + 1);
+    let (old_end_byte, end_byte) = (at + deleted + tail, at + inserted + tail);
+    let old_end = self.utf16(old_end_byte);
+    let mut starts = vec![];
+    let mut ends = vec![];
+    let mut wide = vec![];
+    let mut len = start;
+    let mut prev = None;
+    for (i, c) in src[start_byte..end_byte].char_indices() {
+      len += c.len_utf16();
+      if !c.is_ascii() {
+        wide.push((start_byte + i + c.len_utf8(), len));
+      }
+      if c == '\n' {
+        ends.push(if prev == Some('\r') { len - 2 } else { len - 1 });
+        starts.push(len);
+      }
+      prev = Some(c);
+    }
+    let shift = |index: usize| index - old_end + len;
+    // the first line starting after the edited lines, the line they end at keeps its end
+    let after = self.starts.partition_point(|s| *s <= old_end);
+    let kept_wide = self.wide.partition_point(|(b, _)| *b <= start_byte);
+    let moved_wide = self.wide.partition_point(|(b, _)| *b <= old_end_byte);
+    starts.extend(self.starts[after..].iter().map(|s| shift(*s)));
+    ends.extend(self.ends[after - 1..].iter().map(|e| shift(*e)));
+    wide.extend(
+      self.wide[moved_wide..]
+        .iter()
+        .map(|(b, u)| (b - old_end_byte + end_byte, shift(*u))),
+    );
+    self.starts.splice(line + 1.., starts);
+    self.ends.splice(line.., ends);
+    self.wide.splice(kept_wide.., wide);
+    self.len = shift(self.len);
+  }
+
+  pub fn position(&self, offset: usize) -> Result<Position, OutOfRange> {
+    if offset > self.len {
+      return Err(OutOfRange {
+        message: format!(
+          "offset {} is out of the source of length {}",
+          offset, self.len
+        ),
+        params: vec![("offset", offset), ("length", self.len)],
+      });
+    }
+    Ok(self.locate(offset))
+  }
+
+  fn locate(&self, offset: usize) -> Position {
+    let line = self.starts.partition_point(|start| *start <= offset) - 1;
+    Position {
+      line,
+      // between `\r` and `\n` is still the end of the line
+      column: offset.min(self.ends[line]) - self.starts[line],
+    }
+  }
+
+  pub fn offset(&self, line: usize, column: usize) -> Result<usize, OutOfRange> {
+    let start = match self.starts.get(line) {
+      Some(start) => *start,
+      None => {
+        return Err(OutOfRange {
+          message: format!(
+            "line {} is out of the source of {} lines",
+            line,
+            self.starts.len()
+          ),
+          params: vec![("line", line), ("lines", self.starts.len())],
+        })
+      }
+    };
+    let end = self.ends[line];
+    if start + column > end {
+      return Err(OutOfRange {
+        message: format!(
+          "column {} is out of line {} of length {}",
+          column,
+          line,
+          end - start
+        ),
+        params: vec![("line", line), ("column", column), ("length", end - start)],
+      });
+    }
+    Ok(start + column)
+  }
+
+  /// The JS string index of the UTF-8 byte offset `byte`, a char boundary.
+  pub fn utf16(&self, byte: usize) -> usize {
+    let i = self.wide.partition_point(|(b, _)| *b <= byte);
+    match i.checked_sub(1).map(|i| self.wide[i]) {
+      Some((b, index)) => index + byte - b,
+      None => byte,
+    }
+  }
+
+  /// The UTF-8 byte offset of a JS string index from outside,
+  /// `None` past the end of `src` or between the two code units of a char.
+  pub fn checked_byte(&self, src: &str, index: usize) -> Option<usize> {
+    let byte = self.byte(index);
+    (index <= self.len && src.is_char_boundary(byte)).then_some(byte)
+  }
+
+  /// The UTF-8 byte offset of the JS string index `index`, a char boundary.
+  pub fn byte(&self, index: usize) -> usize {
+    let i = self.wide.partition_point(|(_, u)| *u <= index);
+    match i.checked_sub(1).map(|i| self.wide[i]) {
+      Some((byte, u)) => byte + index - u,
+      None => index,
+    }
+  }
+}
+
+// `Node::range` is in JS string indices on wasm32, where web-tree-sitter parses JS strings,
+// and in UTF-8 bytes elsewhere. Offsets of nodes convert here, each in one lookup.
+impl LineIndex {
+  /// The JS string index of the node offset `offset`.
+  pub fn js_index(&self, offset: usize) -> usize {
+    if cfg!(target_arch = "wasm32") {
+      offset
+    } else {
+      self.utf16(offset)
+    }
+  }
+
+  /// The UTF-8 byte offset of the node offset `offset`, to slice the source with.
+  pub fn byte_of(&self, offset: usize) -> usize {
+    if cfg!(target_arch = "wasm32") {
+      self.byte(offset)
+    } else {
+      offset
+    }
+  }
+
+  /// The node offset of the UTF-8 byte offset `byte`.
+  pub fn node_offset(&self, byte: usize) -> usize {
+    if cfg!(target_arch = "wasm32") {
+      self.utf16(byte)
+    } else {
+      byte
+    }
+  }
+
+  /// One based line and column of the node offset `offset`, the column in JS string indices.
+  pub fn line_column(&self, offset: usize) -> (usize, usize) {
+    let position = self.locate(self.js_index(offset).min(self.len));
+    (position.line + 1, position.column + 1)
+  }
+}
+
+/// How `fixErrors` writes the line breaks of its output, fix templates only contain `\n`.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+  /// keep the line breaks of the source and of the fix as they are
+  #[default]
+  Preserve,
+  /// use the first line break of the source everywhere
+  Auto,
+  Lf,
+  Crlf,
+}
+
+impl LineEndings {
+  pub fn apply(self, src: &str, output: String) -> String {
+    let lf = || output.replace("\r\n", "\n");
+    match self {
+      LineEndings::Preserve => output,
+      LineEndings::Auto => match src.find('\n') {
+        Some(i) if src[..i].ends_with('\r') => LineEndings::Crlf.apply(src, output),
+        Some(_) => lf(),
+        None => output,
+      },
+      LineEndings::Lf => lf(),
+      LineEndings::Crlf => lf().replace('\n', "\r\n"),
+    }
+  }
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  /// The zero based `{ line, column }` of the JS string index `offset`,
+  /// with columns counted like the ranges of `findNodes`.
+  #[wasm_bindgen(js_name = offsetToPosition)]
+  pub fn offset_to_position(&self, offset: usize) -> Result<TextPosition, JsValue> {
+    let position = self.lines().position(offset)?;
+    Ok(serialize::to_value(&position)?.unchecked_into())
+  }
+
+  /// The JS string index of the zero based `line` and `column`.
+  /// A column may point at the end of its line but not into or past its line break,
+  /// `\r\n` counts as one line break. A lone `\r` is a character like for tree-sitter.
+  #[wasm_bindgen(js_name = positionToOffset)]
+  pub fn position_to_offset(&self, line: usize, column: usize) -> Result<usize, JsValue> {
+    Ok(self.lines().offset(line, column)?)
+  }
+
+  pub(crate) fn lines(&self) -> &LineIndex {
+    self.lines.get(self.inner.source())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn pos(line: usize, column: usize) -> Position {
+    Position { line, column }
+  }
+
+  #[test]
+  fn test_empty_source() {
+    let lines = LineIndex::new("");
+    assert_eq!(lines.position(0), Ok(pos(0, 0)));
+    assert_eq!(lines.offset(0, 0), Ok(0));
+    let err = lines.position(1).unwrap_err();
+    assert_eq!(err.params, [("offset", 1), ("length", 0)]);
+    assert!(lines.offset(0, 1).is_err());
+    assert!(lines.offset(1, 0).is_err());
+  }
+
+  #[test]
+  fn test_offset_at_length() {
+    let lines = LineIndex::new("ab\ncd");
+    assert_eq!(lines.position(5), Ok(pos(1, 2)));
+    assert_eq!(lines.offset(1, 2), Ok(5));
+    assert!(lines.position(6).is_err());
+    let lines = LineIndex::new("ab\n");
+    assert_eq!(lines.position(3), Ok(pos(1, 0)));
+  }
+
+  #[test]
+  fn test_out_of_range() {
+    let lines = LineIndex::new("ab\ncd");
+    let err = lines.offset(2, 0).unwrap_err();
+    assert_eq!(err.message, "line 2 is out of the source of 2 lines");
+    assert_eq!(err.params, [("line", 2), ("lines", 2)]);
+    let err = lines.offset(0, 3).unwrap_err();
+    assert_eq!(err.message, "column 3 is out of line 0 of length 2");
+    assert_eq!(err.params, [("line", 0), ("column", 3), ("length", 2)]);
+  }
+
+  #[test]
+  fn test_astral_chars() {
+    // 😀 is two UTF-16 code units, like in JS strings
+    let lines = LineIndex::new("a😀b\n😀");
+    assert_eq!(lines.position(3), Ok(pos(0, 3)));
+    assert_eq!(lines.position(4), Ok(pos(0, 4)));
+    assert_eq!(lines.position(7), Ok(pos(1, 2)));
+    assert_eq!(lines.offset(1, 2), Ok(7));
+    assert!(lines.offset(0, 5).is_err());
+  }
+
+  #[test]
+  fn test_byte_offsets() {
+    // é is two bytes and one code unit, 😀 four bytes and two code units
+    let src = "aé😀b\né";
+    let lines = LineIndex::new(src);
+    for (byte, _) in src.char_indices().chain([(src.len(), ' ')]) {
+      let index = src[..byte].encode_utf16().count();
+      assert_eq!(lines.utf16(byte), index);
+      assert_eq!(lines.byte(index), byte);
+    }
+    // inside 😀 and past the end
+    assert_eq!(lines.checked_byte(src, 3), None);
+    assert_eq!(lines.checked_byte(src, 4), Some(7));
+    assert_eq!(lines.checked_byte(src, 8), None);
+    // nodes are in bytes off wasm32
+    assert_eq!(lines.js_index(8), 5);
+    assert_eq!(lines.byte_of(8), 8);
+    assert_eq!(lines.node_offset(8), 8);
+    assert_eq!(lines.line_column(8), (1, 6));
+    assert_eq!(lines.line_column(src.len()), (2, 2));
+  }
+
+  #[test]
+  fn test_crlf() {
+    let lines = LineIndex::new("ab\r\ncd");
+    assert_eq!(lines.position(2), Ok(pos(0, 2)));
+    // between `\r` and `\n`
+    assert_eq!(lines.position(3), Ok(pos(0, 2)));
+    assert_eq!(lines.position(4), Ok(pos(1, 0)));
+    assert!(lines.offset(0, 3).is_err());
+  }
+
+  #[test]
+  fn test_edit() {
+    let inserts = ["", "x", "\n", "\r", "\r\n", "é\n😀", "\n\nab\n", "ü"];
+    let mut src = String::from("ab\r\ncé\n\n😀d\re\nf");
+    let mut lines = LineIndex::new(&src);
+    // a fixed sequence of edits all over the source
+    let mut seed = 7usize;
+    for _ in 0..400 {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+      let bounds: Vec<_> = (0..=src.len())
+        .filter(|i| src.is_char_boundary(*i))
+        .collect();
+      let at = bounds[seed % bounds.len()];
+      let ends: Vec<_> = bounds.iter().filter(|b| **b >= at).take(4).collect();
+      let end = *ends[(seed >> 8) % ends.len()];
+      let text = inserts[(seed >> 12) % inserts.len()];
+      src.replace_range(at..end, text);
+      lines.edit(&src, at, end - at, text.len());
+      assert_eq!(
+        lines,
+        LineIndex::new(&src),
+        "{:?} after {}..{} to {:?}",
+        src,
+        at,
+        end,
+        text
+      );
+      if src.len() > 60 {
+        src.truncate(src.char_indices().nth(20).map_or(src.len(), |(i, _)| i));
+        lines = LineIndex::new(&src);
+      }
+    }
+  }
+
+  #[test]
+  fn test_lone_cr() {
+    let lines = LineIndex::new("a\rb");
+    assert_eq!(lines.position(2), Ok(pos(0, 2)));
+    assert_eq!(lines.offset(0, 3), Ok(3));
+    assert!(lines.offset(1, 0).is_err());
+  }
+
+  #[test]
+  fn test_line_endings() {
+    let output = || "x\ny\r\nz".to_string();
+    let auto = LineEndings::Auto;
+    assert_eq!(auto.apply("a\r\nb\nc", output()), "x\r\ny\r\nz");
+    assert_eq!(auto.apply("a\nb\r\nc", output()), "x\ny\nz");
+    assert_eq!(auto.apply("a", output()), output());
+    assert_eq!(LineEndings::Preserve.apply("a\n", output()), output());
+    assert_eq!(LineEndings::Lf.apply("", output()), "x\ny\nz");
+    // no `\r\r\n` from line breaks that already are `\r\n`
+    assert_eq!(LineEndings::Crlf.apply("", output()), "x\r\ny\r\nz");
+  }
+}
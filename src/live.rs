@@ -0,0 +1,307 @@
+use crate::changes::{changed_nodes, ChangedRange, RangeEdit};
+use crate::rule::CompiledRule;
+use crate::types::{ConfigArray, LiveDiff};
+use crate::{limits, match_range, serialize, utils, SgRoot};
+
+use ast_grep_core::{Matcher, Node};
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Range and kind, which tell a node of a tree apart.
+type Key = (usize, usize, u16);
+
+fn key(node: &Node<ts::Language>) -> Key {
+  let range = node.range();
+  (range.start, range.end, node.kind_id())
+}
+
+/// A match kept from the last scan.
+struct Cached {
+  key: Key,
+  range: [usize; 4],
+}
+
+/// Rules of `watchRules` with their matches in the current source.
+pub struct LiveRules {
+  rules: Vec<Rc<CompiledRule>>,
+  matches: Vec<Vec<Cached>>,
+}
+
+#[derive(Serialize)]
+struct LiveMatch {
+  /// index of the config
+  rule: usize,
+  range: [usize; 4],
+}
+
+#[derive(Serialize)]
+struct Diff {
+  changed: Vec<ChangedRange>,
+  added: Vec<LiveMatch>,
+  removed: Vec<LiveMatch>,
+}
+
+/// The offset of the new source at node offset `old` of the old source, `None` inside the edit.
+fn map_offset(edit: &RangeEdit, old: usize) -> Option<usize> {
+  if old <= edit.position {
+    Some(old)
+  } else if old >= edit.position + edit.deleted {
+    Some(old + edit.inserted - edit.deleted)
+  } else {
+    None
+  }
+}
+
+/// Ranges of the old matches that went away and of the fresh matches that are new,
+/// `mapped` being the keys of `old` after the edit and `kept` the old matches left unscanned.
+fn compare(
+  old: &[Cached],
+  mapped: &[Option<Key>],
+  kept: &[Cached],
+  fresh: &[Cached],
+) -> (Vec<[usize; 4]>, Vec<[usize; 4]>) {
+  let old_keys: HashSet<_> = mapped.iter().flatten().collect();
+  let new_keys: HashSet<_> = kept.iter().chain(fresh).map(|m| m.key).collect();
+  let removed = old
+    .iter()
+    .zip(mapped)
+    .filter(|(_, key)| !key.is_some_and(|key| new_keys.contains(&key)))
+    .map(|(m, _)| m.range)
+    .collect();
+  let added = fresh
+    .iter()
+    .filter(|m| !old_keys.contains(&m.key))
+    .map(|m| m.range)
+    .collect();
+  (removed, added)
+}
+
+/// The node of `root` with `key`, the outermost one if several are.
+fn find_node<'r>(
+  root: Node<'r, ts::Language>,
+  (start, end, kind): Key,
+) -> Option<Node<'r, ts::Language>> {
+  let mut node = root;
+  loop {
+    let range = node.range();
+    if range.start == start && range.end == end && node.kind_id() == kind {
+      return Some(node);
+    }
+    let child = node.children().find(|c| {
+      let range = c.range();
+      range.start <= start && end <= range.end
+    });
+    node = child?;
+  }
+}
+
+/// The innermost node of `root` spanning `start..end`.
+fn spanning<'r>(root: Node<'r, ts::Language>, start: usize, end: usize) -> Node<'r, ts::Language> {
+  let mut node = root;
+  loop {
+    let child = node.children().find(|c| {
+      let range = c.range();
+      range.start <= start && end <= range.end
+    });
+    match child {
+      Some(child) => node = child,
+      None => return node,
+    }
+  }
+}
+
+fn push_ancestors(node: &Node<ts::Language>, out: &mut Vec<Key>, seen: &mut HashSet<Key>) {
+  for ancestor in node.ancestors() {
+    if !seen.insert(key(&ancestor)) {
+      // so are its ancestors
+      break;
+    }
+    out.push(key(&ancestor));
+  }
+}
+
+fn scan_nodes<'r>(
+  rule: &CompiledRule,
+  nodes: impl Iterator<Item = Node<'r, ts::Language>>,
+) -> Result<Vec<Cached>, JsValue> {
+  let mut ret = vec![];
+  for node in nodes {
+    if let Some(m) = rule.match_node(node) {
+      limits::check_matches(ret.len() + 1)?;
+      ret.push(Cached {
+        key: key(&m),
+        range: match_range(&m),
+      });
+    }
+  }
+  Ok(ret)
+}
+
+fn diff_value(diff: &Diff) -> Result<LiveDiff, JsValue> {
+  Ok(serialize::to_value(diff)?.unchecked_into())
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  /// Scan the root with `configs` and keep their matches for `applyEditAndRescan`,
+  /// replacing the rules watched before. Returns `{ changed, added, removed }` like
+  /// `applyEditAndRescan` with every match added. `applyEdit` stops watching.
+  #[wasm_bindgen(js_name = watchRules)]
+  pub fn watch_rules(&mut self, configs: ConfigArray) -> Result<LiveDiff, JsValue> {
+    utils::enter("SgRoot.watchRules", self.inner.source().len());
+    let rules = configs
+      .iter()
+      .map(|config| self.compile(config))
+      .collect::<Result<Vec<_>, _>>()?;
+    let matches = rules
+      .iter()
+      .map(|rule| scan_nodes(rule, self.inner.root().dfs()))
+      .collect::<Result<Vec<_>, _>>()?;
+    let added = matches
+      .iter()
+      .enumerate()
+      .flat_map(|(rule, matches)| {
+        matches.iter().map(move |m| LiveMatch {
+          rule,
+          range: m.range,
+        })
+      })
+      .collect();
+    self.live = Some(LiveRules { rules, matches });
+    diff_value(&Diff {
+      changed: vec![],
+      added,
+      removed: vec![],
+    })
+  }
+
+  /// `applyEdit` followed by rescanning with the rules of `watchRules`, only around the
+  /// changed nodes for rules without `inside`, `precedes` or `follows`. Returns
+  /// `{ changed, added, removed }` where `changed` is the result of `applyEdit` and
+  /// `added` and `removed` are `[{ rule, range }]` of the matches that appeared or went
+  /// away, `rule` being the index of the config. Ranges of `removed` are in the old source,
+  /// the other matches stay and move with the text around them.
+  #[wasm_bindgen(js_name = applyEditAndRescan)]
+  pub fn apply_edit_and_rescan(&mut self, edit: JsValue) -> Result<LiveDiff, JsValue> {
+    utils::enter("SgRoot.applyEditAndRescan", self.inner.source().len());
+    let (old, edit) = self.edit_source(edit)?;
+    let root = self.inner.root();
+    let changed = changed_nodes(&old, root.clone(), self.inner.source(), self.lines(), &edit);
+    // nodes that may match differently, inside or around a change
+    let mut seen = HashSet::new();
+    let mut dirty = vec![];
+    for node in &changed {
+      for inner in node.dfs() {
+        if seen.insert(key(&inner)) {
+          dirty.push(key(&inner));
+        }
+      }
+      push_ancestors(node, &mut dirty, &mut seen);
+    }
+    // text edits leaving the syntax as it is can still change regexes of the nodes around
+    let edited = spanning(root.clone(), edit.position, edit.position + edit.inserted);
+    if seen.insert(key(&edited)) {
+      dirty.push(key(&edited));
+    }
+    push_ancestors(&edited, &mut dirty, &mut seen);
+    let dirty_nodes: Vec<_> = dirty
+      .iter()
+      .filter_map(|key| find_node(root.clone(), *key))
+      .collect();
+    let mut diff = Diff {
+      changed: changed
+        .iter()
+        .map(|node| ChangedRange::new(node, self.lines()))
+        .collect(),
+      added: vec![],
+      removed: vec![],
+    };
+    let live = match &mut self.live {
+      Some(live) => live,
+      None => return diff_value(&diff),
+    };
+    for (i, rule) in live.rules.iter().enumerate() {
+      let old_matches = std::mem::take(&mut live.matches[i]);
+      let mapped: Vec<Option<Key>> = old_matches
+        .iter()
+        .map(|m| {
+          let (start, end, kind) = m.key;
+          Some((map_offset(&edit, start)?, map_offset(&edit, end)?, kind))
+        })
+        .collect();
+      let (kept, fresh) = if rule.local {
+        let kept: Vec<_> = mapped
+          .iter()
+          .flatten()
+          .filter(|key| !seen.contains(*key))
+          .filter_map(|key| find_node(root.clone(), *key))
+          .map(|node| Cached {
+            key: key(&node),
+            range: match_range(&node),
+          })
+          .collect();
+        (kept, scan_nodes(rule, dirty_nodes.iter().cloned())?)
+      } else {
+        (vec![], scan_nodes(rule, root.dfs())?)
+      };
+      let (removed, added) = compare(&old_matches, &mapped, &kept, &fresh);
+      let live_match = |range| LiveMatch { rule: i, range };
+      diff.removed.extend(removed.into_iter().map(live_match));
+      diff.added.extend(added.into_iter().map(live_match));
+      // kept matches are outside every dirty node, so they are not fresh again
+      let mut matches: Vec<_> = kept.into_iter().chain(fresh).collect();
+      // in document order like a traversal, outer nodes first
+      matches.sort_by_key(|m| (m.key.0, std::cmp::Reverse(m.key.1)));
+      live.matches[i] = matches;
+    }
+    diff_value(&diff)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn cached(start: usize, end: usize) -> Cached {
+    Cached {
+      key: (start, end, 1),
+      range: [0, start, 0, end],
+    }
+  }
+
+  #[test]
+  fn test_map_offset() {
+    // `abcdef` to `abXYZef`
+    let edit = RangeEdit {
+      position: 2,
+      deleted: 2,
+      inserted: 3,
+    };
+    assert_eq!(map_offset(&edit, 1), Some(1));
+    assert_eq!(map_offset(&edit, 2), Some(2));
+    assert_eq!(map_offset(&edit, 3), None);
+    assert_eq!(map_offset(&edit, 4), Some(5));
+    assert_eq!(map_offset(&edit, 6), Some(7));
+  }
+
+  #[test]
+  fn test_compare() {
+    let old = [cached(0, 1), cached(2, 3), cached(4, 5)];
+    // the second one was inside the edit, the third moved by one
+    let mapped = [Some((0, 1, 1)), None, Some((5, 6, 1))];
+    let kept = [cached(0, 1)];
+    let fresh = [cached(2, 4), cached(5, 6)];
+    let (removed, added) = compare(&old, &mapped, &kept, &fresh);
+    assert_eq!(removed, [[0, 2, 0, 3]]);
+    assert_eq!(added, [[0, 2, 0, 4]]);
+    // a match that moved and no longer matches
+    let (removed, added) = compare(&old, &mapped, &kept, &fresh[..1]);
+    assert_eq!(removed, [[0, 2, 0, 3], [0, 4, 0, 5]]);
+    assert_eq!(added, [[0, 2, 0, 4]]);
+  }
+}
@@ -0,0 +1,198 @@
+use crate::context::{global, Context};
+use crate::editor::{report_matches, Report};
+use crate::error::{tag, Code};
+use crate::serialize;
+use crate::types::ConfigArray;
+use crate::utils;
+
+use ast_grep_config::Severity;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use std::collections::HashMap;
+
+/// Zero based, `character` in UTF-16 code units, the default position encoding of LSP.
+#[derive(Clone, Copy, Deserialize, PartialEq, PartialOrd, Serialize)]
+struct Position {
+  line: usize,
+  character: usize,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+struct Range {
+  start: Position,
+  end: Position,
+}
+
+impl Range {
+  fn of(report: &Report) -> Self {
+    let position = |offset| {
+      let (line, column) = report.lines.line_column(offset);
+      Position {
+        line: line - 1,
+        character: column - 1,
+      }
+    };
+    let range = report.node.range();
+    Self {
+      start: position(range.start),
+      end: position(range.end),
+    }
+  }
+
+  fn overlaps(&self, other: &Range) -> bool {
+    self.start <= other.end && other.start <= self.end
+  }
+}
+
+#[derive(Serialize)]
+struct Diagnostic {
+  range: Range,
+  severity: u8,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  code: Option<String>,
+  source: &'static str,
+  message: String,
+}
+
+impl Diagnostic {
+  fn of(report: &Report) -> Self {
+    Self {
+      range: Range::of(report),
+      // DiagnosticSeverity of LSP
+      severity: match report.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+        Severity::Hint => 4,
+      },
+      code: report.source.map(String::from),
+      source: "ast-grep",
+      message: report.message.into(),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct PublishDiagnosticsParams<'a> {
+  uri: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  version: Option<i32>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TextEdit {
+  range: Range,
+  new_text: String,
+}
+
+#[derive(Serialize)]
+struct WorkspaceEdit<'a> {
+  changes: HashMap<&'a str, [TextEdit; 1]>,
+}
+
+#[derive(Serialize)]
+struct CodeAction<'a> {
+  title: String,
+  kind: &'static str,
+  diagnostics: [Diagnostic; 1],
+  edit: WorkspaceEdit<'a>,
+}
+
+/// Scan the document `src` at `uri` with many configs into the params of a
+/// `textDocument/publishDiagnostics` notification, `{ uri, version?, diagnostics }`.
+/// Each diagnostic has the `id` of its config as `code` and `ast-grep` as `source`,
+/// `severity` and `message` come from the configs like in `lintDiagnostics`.
+#[wasm_bindgen(js_name = lspDiagnostics)]
+pub fn lsp_diagnostics(
+  uri: String,
+  src: String,
+  configs: ConfigArray,
+  version: Option<i32>,
+) -> Result<JsValue, JsValue> {
+  global().lsp_diagnostics(uri, src, configs.into(), version)
+}
+
+/// The fixes of many configs in the document `src` at `uri` as quick fix `CodeAction`s,
+/// `[{ title, kind, diagnostics, edit }]` with a `WorkspaceEdit` replacing the match,
+/// for the response to `textDocument/codeAction`. Given the `range` of the request,
+/// only fixes of matches overlapping it are returned.
+#[wasm_bindgen(js_name = lspCodeActions)]
+pub fn lsp_code_actions(
+  uri: String,
+  src: String,
+  configs: ConfigArray,
+  range: JsValue,
+) -> Result<JsValue, JsValue> {
+  global().lsp_code_actions(uri, src, configs.into(), range)
+}
+
+impl Context {
+  pub fn lsp_diagnostics(
+    &self,
+    uri: String,
+    src: String,
+    configs: js_sys::Array,
+    version: Option<i32>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("lspDiagnostics", src.len());
+    let rules = self.compile_configs(configs)?;
+    let mut diagnostics = vec![];
+    report_matches(&rules, &src, |report| {
+      diagnostics.push(Diagnostic::of(&report));
+      Ok(())
+    })?;
+    let params = PublishDiagnosticsParams {
+      uri: &uri,
+      version,
+      diagnostics,
+    };
+    Ok(serialize::to_json_value(&params)?)
+  }
+
+  pub fn lsp_code_actions(
+    &self,
+    uri: String,
+    src: String,
+    configs: js_sys::Array,
+    range: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("lspCodeActions", src.len());
+    let range: Option<Range> =
+      serde_wasm_bindgen::from_value(range).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = self.compile_configs(configs)?;
+    let ret = js_sys::Array::new();
+    report_matches(&rules, &src, |report| {
+      let fixer = match &report.rule.fixer {
+        Some(fixer) => fixer,
+        None => return Ok(()),
+      };
+      let diagnostic = Diagnostic::of(&report);
+      if !range.map_or(true, |r| r.overlaps(&diagnostic.range)) {
+        return Ok(());
+      }
+      let new_text = report.node.replace_by(fixer).inserted_text;
+      let edit = TextEdit {
+        range: diagnostic.range,
+        new_text,
+      };
+      let title = match report.source {
+        Some(id) => format!("Fix {}", id),
+        None => "Fix".into(),
+      };
+      let action = CodeAction {
+        title,
+        kind: "quickfix",
+        diagnostics: [diagnostic],
+        edit: WorkspaceEdit {
+          changes: HashMap::from([(uri.as_str(), [edit])]),
+        },
+      };
+      ret.push(&serialize::to_json_value(&action)?);
+      Ok(())
+    })?;
+    Ok(ret.into())
+  }
+}
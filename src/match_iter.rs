@@ -0,0 +1,150 @@
+use crate::cancel::{CancelCheck, CancelSignal};
+use crate::context::{global, Context};
+use crate::rule::CompiledRule;
+use crate::types::Config;
+use crate::utils;
+use crate::{match_range, parse_root, serialize};
+
+use ast_grep_core::{AstGrep, Matcher, Node};
+use std::rc::Rc;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+/// Matches of a rule found lazily, each `next()` walks the tree only up to the next match.
+/// Follows the JS iterator protocol, `next()` returns `{ value, done }`
+/// where `value` has the shape of an item of `findNodes`.
+#[wasm_bindgen]
+pub struct MatchIter {
+  root: AstGrep<ts::Language>,
+  rule: Rc<CompiledRule>,
+  // child indices from the root to the next node to visit, `None` when the walk is done.
+  // the root cannot be borrowed across calls so the position is resumed from the path
+  path: Option<Vec<usize>>,
+}
+
+#[wasm_bindgen(js_name = findNodesIter)]
+pub fn find_nodes_iter(src: String, config: Config) -> Result<MatchIter, JsValue> {
+  global().find_nodes_iter(src, config.into())
+}
+
+impl Context {
+  pub fn find_nodes_iter(&self, src: String, config: JsValue) -> Result<MatchIter, JsValue> {
+    utils::enter("findNodesIter", src.len());
+    let rule = self.compile_config(config)?;
+    let root = parse_root(&src, &rule.lang)?;
+    Ok(MatchIter {
+      root,
+      rule,
+      path: Some(vec![]),
+    })
+  }
+}
+
+/// Call `callback` with each match as it is found, in the shape of an item of `findNodes`.
+/// Returning `false` from the callback stops the scan.
+/// Returns `true` if the whole tree was scanned, `false` if stopped by the callback or `timeoutMs`.
+#[wasm_bindgen(js_name = findNodesEach)]
+pub fn find_nodes_each(
+  src: String,
+  config: Config,
+  callback: js_sys::Function,
+  cancel: Option<CancelSignal>,
+) -> Result<bool, JsValue> {
+  global().find_nodes_each(src, config.into(), callback, cancel)
+}
+
+impl Context {
+  pub fn find_nodes_each(
+    &self,
+    src: String,
+    config: JsValue,
+    callback: js_sys::Function,
+    cancel: Option<CancelSignal>,
+  ) -> Result<bool, JsValue> {
+    utils::enter("findNodesEach", src.len());
+    let rule = self.compile_config(config)?;
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.timeout_ms);
+    let root = parse_root(&src, &rule.lang)?;
+    for node in root.root().dfs() {
+      if !check.visit()? {
+        return Ok(false);
+      }
+      if let Some(n) = rule.match_node(node) {
+        let range = serialize::to_value(&match_range(&n))?;
+        // only an explicit false stops, a callback returning nothing keeps going
+        if callback.call1(&JsValue::NULL, &range)? == JsValue::FALSE {
+          return Ok(false);
+        }
+      }
+    }
+    Ok(true)
+  }
+}
+
+fn resolve<'r>(root: &'r AstGrep<ts::Language>, path: &[usize]) -> Option<Node<'r, ts::Language>> {
+  let mut node = root.root();
+  for &nth in path {
+    node = node.child(nth)?;
+  }
+  Some(node)
+}
+
+/// Step to the next node in pre-order, the same order `dfs` visits.
+fn advance<'r>(
+  mut node: Node<'r, ts::Language>,
+  path: &mut Vec<usize>,
+) -> Option<Node<'r, ts::Language>> {
+  if let Some(child) = node.child(0) {
+    path.push(0);
+    return Some(child);
+  }
+  loop {
+    if let Some(sibling) = node.next() {
+      *path.last_mut()? += 1;
+      return Some(sibling);
+    }
+    node = node.parent()?;
+    path.pop();
+  }
+}
+
+#[wasm_bindgen]
+impl MatchIter {
+  // named after the JS protocol, in Rust `next` would read like Iterator::next
+  #[wasm_bindgen(js_name = next)]
+  pub fn next_result(&mut self) -> Result<JsValue, JsValue> {
+    utils::enter("MatchIter.next", self.root.source().len());
+    let range = self.next_range();
+    let done = JsValue::from_bool(range.is_none());
+    let value = match range {
+      Some(range) => serialize::to_value(&range)?,
+      None => JsValue::UNDEFINED,
+    };
+    let ret = js_sys::Object::new();
+    js_sys::Reflect::set(&ret, &"value".into(), &value)?;
+    js_sys::Reflect::set(&ret, &"done".into(), &done)?;
+    Ok(ret.into())
+  }
+
+  /// Free the parsed tree now instead of when JS garbage collects the iterator.
+  pub fn dispose(self) {}
+}
+
+impl MatchIter {
+  fn next_range(&mut self) -> Option<[usize; 4]> {
+    let path = self.path.as_mut()?;
+    let mut next = resolve(&self.root, path);
+    while let Some(node) = next {
+      let found = self.rule.match_node(node.clone()).map(|n| match_range(&n));
+      next = advance(node, path);
+      if found.is_some() {
+        if next.is_none() {
+          self.path = None;
+        }
+        return found;
+      }
+    }
+    self.path = None;
+    None
+  }
+}
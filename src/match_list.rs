@@ -0,0 +1,165 @@
+use crate::cancel::{CancelCheck, CancelSignal};
+use crate::context::{global, Context};
+use crate::error::{error_with, Code};
+use crate::limits;
+use crate::rule::CompiledRule;
+use crate::stats;
+use crate::types::Config;
+use crate::utils;
+use crate::{parse_root, SgRoot};
+
+use ast_grep_core::{AstGrep, Matcher};
+use std::collections::HashMap;
+use std::ops::Range;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+struct Match {
+  range: Range<usize>,
+  start: (usize, usize),
+  end: (usize, usize),
+  env: HashMap<String, String>,
+}
+
+/// Matches kept in wasm memory, each converted to JS values only when accessed,
+/// e.g. by a virtualized list rendering the visible rows.
+#[wasm_bindgen]
+pub struct MatchList {
+  src: String,
+  matches: Vec<Match>,
+  timed_out: bool,
+}
+
+impl MatchList {
+  fn new(src: String, matches: Vec<Match>, timed_out: bool) -> Self {
+    stats::track(|live| live.match_lists += 1);
+    Self {
+      src,
+      matches,
+      timed_out,
+    }
+  }
+}
+
+impl Drop for MatchList {
+  fn drop(&mut self) {
+    stats::track(|live| live.match_lists -= 1);
+  }
+}
+
+fn collect_matches(
+  root: &AstGrep<ts::Language>,
+  rule: &CompiledRule,
+  check: &mut CancelCheck,
+) -> Result<Vec<Match>, JsValue> {
+  let mut ret = vec![];
+  for node in root.root().dfs() {
+    if !check.visit()? {
+      break;
+    }
+    // match with an env we own, NodeMatch only lends its env
+    let mut env = rule.get_meta_var_env();
+    let n = match rule.match_node_with_env(node, &mut env) {
+      Some(n) if env.match_constraints() => n,
+      _ => continue,
+    };
+    limits::check_matches(ret.len() + 1)?;
+    ret.push(Match {
+      range: n.range(),
+      start: n.start_pos(),
+      end: n.end_pos(),
+      env: env.into(),
+    });
+  }
+  Ok(ret)
+}
+
+#[wasm_bindgen(js_name = findMatches)]
+pub fn find_matches(
+  src: String,
+  config: Config,
+  cancel: Option<CancelSignal>,
+) -> Result<MatchList, JsValue> {
+  global().find_matches(src, config.into(), cancel)
+}
+
+impl Context {
+  pub fn find_matches(
+    &self,
+    src: String,
+    config: JsValue,
+    cancel: Option<CancelSignal>,
+  ) -> Result<MatchList, JsValue> {
+    utils::enter("findMatches", src.len());
+    let rule = self.compile_config(config)?;
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.timeout_ms);
+    let root = parse_root(&src, &rule.lang)?;
+    let matches = collect_matches(&root, &rule, &mut check)?;
+    Ok(MatchList::new(src, matches, check.timed_out))
+  }
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  #[wasm_bindgen(js_name = findMatches)]
+  pub fn find_matches(
+    &self,
+    config: Config,
+    cancel: Option<CancelSignal>,
+  ) -> Result<MatchList, JsValue> {
+    utils::enter("SgRoot.findMatches", self.inner.source().len());
+    let rule = self.compile(config.into())?;
+    let mut check = CancelCheck::new(cancel.as_ref(), rule.timeout_ms);
+    let matches = collect_matches(&self.inner, &rule, &mut check)?;
+    let src = self.inner.source().to_string();
+    Ok(MatchList::new(src, matches, check.timed_out))
+  }
+}
+
+#[wasm_bindgen]
+impl MatchList {
+  /// Free the matches and their source now instead of when JS garbage collects the list.
+  pub fn dispose(self) {}
+
+  #[wasm_bindgen(getter)]
+  pub fn length(&self) -> usize {
+    self.matches.len()
+  }
+
+  #[wasm_bindgen(getter, js_name = timedOut)]
+  pub fn timed_out(&self) -> bool {
+    self.timed_out
+  }
+
+  /// `[startRow, startColumn, endRow, endColumn]` like the entries of findNodes.
+  #[wasm_bindgen(js_name = getRange)]
+  pub fn get_range(&self, i: usize) -> Result<Vec<usize>, JsValue> {
+    let m = self.get(i)?;
+    Ok(vec![m.start.0, m.start.1, m.end.0, m.end.1])
+  }
+
+  #[wasm_bindgen(js_name = getText)]
+  pub fn get_text(&self, i: usize) -> Result<String, JsValue> {
+    let m = self.get(i)?;
+    Ok(self.src[m.range.clone()].to_string())
+  }
+
+  /// Text of each meta variable captured by the match, keyed by name without `$`.
+  #[wasm_bindgen(js_name = getEnv)]
+  pub fn get_env(&self, i: usize) -> Result<js_sys::Object, JsValue> {
+    let m = self.get(i)?;
+    let env = js_sys::Object::new();
+    for (name, text) in &m.env {
+      js_sys::Reflect::set(&env, &name.into(), &text.into())?;
+    }
+    Ok(env)
+  }
+
+  fn get(&self, i: usize) -> Result<&Match, JsValue> {
+    self.matches.get(i).ok_or_else(|| {
+      let msg = format!("match index {} out of range 0..{}", i, self.matches.len());
+      let params = [("index", i.into()), ("length", self.matches.len().into())];
+      error_with(Code::InvalidArgument, &msg, &params)
+    })
+  }
+}
@@ -0,0 +1,38 @@
+use crate::limits::{self, Limits};
+use crate::rule::CompiledRule;
+
+use std::rc::Rc;
+
+/// Result of the last call, returned again when the same source is scanned with the same rule
+/// under the same limits. Editors calling on every render often repeat the previous call unchanged.
+pub struct Memo<T> {
+  // keeping the compiled rule alive makes its address a stable cache key
+  last: Option<(String, Rc<CompiledRule>, Limits, T)>,
+}
+
+impl<T> Default for Memo<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T> Memo<T> {
+  pub const fn new() -> Self {
+    Self { last: None }
+  }
+
+  pub fn get(&self, src: &str, rule: &Rc<CompiledRule>) -> Option<&T> {
+    let (last_src, last_rule, last_limits, value) = self.last.as_ref()?;
+    // a result computed under other limits may have to throw now
+    let hit = Rc::ptr_eq(last_rule, rule) && *last_limits == limits::current() && last_src == src;
+    hit.then_some(value)
+  }
+
+  pub fn clear(&mut self) {
+    self.last = None;
+  }
+
+  pub fn set(&mut self, src: String, rule: Rc<CompiledRule>, value: T) {
+    self.last = Some((src, rule, limits::current(), value));
+  }
+}
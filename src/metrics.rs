@@ -0,0 +1,135 @@
+use crate::cancel::CancelCheck;
+use crate::context::global;
+use crate::error::{error, Code};
+use crate::parse_root;
+use crate::serialize;
+use crate::types::Config;
+use crate::utils;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+  #[wasm_bindgen(js_namespace = performance, js_name = now)]
+  fn performance_now() -> f64;
+}
+
+/// Milliseconds from `performance.now()`, finer grained than `Date.now()` for small sources.
+pub fn now() -> f64 {
+  performance_now()
+}
+
+/// Timings of one call, attached as `metrics` to the results of configs with `metrics: true`.
+/// `compileMs` is near zero when the compiled rule is reused from the cache
+/// and `parseMs` is zero for roots parsed before the call.
+/// Scans with many configs add `rules`, the share of each config in the same order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+  #[serde(skip)]
+  lap_start: f64,
+  compile_ms: f64,
+  parse_ms: f64,
+  match_ms: f64,
+  nodes_visited: usize,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  rules: Vec<RuleMetrics>,
+}
+
+/// The share of one config in a scan with many.
+/// `matchMs` sums a timing per node, coarse where the browser clamps `performance.now()`.
+/// `nodesVisited` counts the nodes tried against the config, fewer than all for rules of known kinds.
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleMetrics {
+  pub match_ms: f64,
+  pub nodes_visited: usize,
+}
+
+impl Metrics {
+  pub fn start() -> Self {
+    Self {
+      lap_start: now(),
+      compile_ms: 0.0,
+      parse_ms: 0.0,
+      match_ms: 0.0,
+      nodes_visited: 0,
+      rules: vec![],
+    }
+  }
+
+  fn lap(&mut self) -> f64 {
+    let end = now();
+    let elapsed = end - self.lap_start;
+    self.lap_start = end;
+    elapsed
+  }
+
+  pub fn compiled(&mut self) {
+    self.compile_ms = self.lap();
+  }
+
+  pub fn parsed(&mut self) {
+    self.parse_ms = self.lap();
+  }
+
+  pub fn per_rule(&mut self, rules: Vec<RuleMetrics>) {
+    self.rules = rules;
+  }
+
+  pub fn attach(mut self, ret: &JsValue, check: &CancelCheck) -> Result<(), JsValue> {
+    self.match_ms = self.lap();
+    self.nodes_visited = check.visited();
+    let metrics = serialize::to_value(&self)?;
+    js_sys::Reflect::set(ret, &"metrics".into(), &metrics)?;
+    Ok(())
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Benchmark {
+  iterations: u32,
+  matches: usize,
+  min_ms: f64,
+  median_ms: f64,
+  p95_ms: f64,
+}
+
+/// Match `config` against `src` `iterations` times, timing only the matching.
+/// The source is parsed and the rule compiled once before the first run.
+#[wasm_bindgen(js_name = benchmarkRule)]
+pub fn benchmark_rule(src: String, config: Config, iterations: u32) -> Result<JsValue, JsValue> {
+  utils::enter("benchmarkRule", src.len());
+  if iterations == 0 {
+    return Err(error(
+      Code::InvalidArgument,
+      "iterations must be at least 1",
+    ));
+  }
+  let rule = global().compile_config(config.into())?;
+  let root = parse_root(&src, &rule.lang)?;
+  let mut matches = 0;
+  let mut timings: Vec<f64> = (0..iterations)
+    .map(|_| {
+      let start = now();
+      matches = root.root().find_all(&*rule).count();
+      now() - start
+    })
+    .collect();
+  timings.sort_by(f64::total_cmp);
+  // nearest rank percentile
+  let percentile = |p: f64| {
+    let rank = (p * timings.len() as f64).ceil() as usize;
+    timings[rank.max(1) - 1]
+  };
+  let ret = Benchmark {
+    iterations,
+    matches,
+    min_ms: timings[0],
+    median_ms: percentile(0.5),
+    p95_ms: percentile(0.95),
+  };
+  Ok(serialize::to_value(&ret)?)
+}
@@ -0,0 +1,383 @@
+use crate::context::Context;
+use crate::error::{error, error_with, tag, Code};
+use crate::limits;
+use crate::lines::{LineIndex, Lines};
+use crate::rule::CompiledRule;
+use crate::stats;
+use crate::types::{MatchRange, NodeEdit, NodeMatcher, NodeRange};
+use crate::utils;
+use crate::{compile_for_root, serialize, SgRoot};
+
+use ast_grep_core::{AstGrep, Matcher, Node};
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::rc::Rc;
+
+/// A node of a parsed root in the shape of `SgNode` of `@ast-grep/napi`,
+/// so code written against the native binding runs on the WASM build unmodified.
+/// Matchers are a pattern string or a config, which defaults to the root's language.
+#[wasm_bindgen]
+pub struct SgNode {
+  root: Rc<AstGrep<ts::Language>>,
+  // the line index of the root when the node was created, for the same source
+  lines: Lines,
+  ctx: Rc<Context>,
+  // child indices from the root, nodes borrow their tree so they are located again on use
+  path: Vec<usize>,
+  // the rule that found the node, matched again to read its metavariables
+  rule: Option<Rc<CompiledRule>>,
+}
+
+impl SgNode {
+  fn new(
+    root: Rc<AstGrep<ts::Language>>,
+    lines: Lines,
+    ctx: Rc<Context>,
+    path: Vec<usize>,
+    rule: Option<Rc<CompiledRule>>,
+  ) -> Self {
+    stats::track(|live| live.nodes += 1);
+    Self {
+      root,
+      lines,
+      ctx,
+      path,
+      rule,
+    }
+  }
+
+  fn node(&self) -> Node<'_, ts::Language> {
+    let mut node = self.root.root();
+    for &i in &self.path {
+      node = node.child(i).expect("the path of a node of this tree");
+    }
+    node
+  }
+
+  fn wrap(&self, node: &Node<ts::Language>, rule: Option<Rc<CompiledRule>>) -> SgNode {
+    let (root, lines) = (self.root.clone(), self.lines.clone());
+    SgNode::new(root, lines, self.ctx.clone(), path_of(node), rule)
+  }
+
+  fn wrap_all<'r>(&self, nodes: impl Iterator<Item = Node<'r, ts::Language>>) -> js_sys::Array {
+    nodes.map(|n| JsValue::from(self.wrap(&n, None))).collect()
+  }
+
+  fn compile(&self, matcher: NodeMatcher) -> Result<Rc<CompiledRule>, JsValue> {
+    let matcher: JsValue = matcher.into();
+    let config = match matcher.as_string() {
+      Some(pattern) => {
+        let rule = js_sys::Object::new();
+        js_sys::Reflect::set(&rule, &"pattern".into(), &pattern.into())?;
+        let config = js_sys::Object::new();
+        js_sys::Reflect::set(&config, &"rule".into(), &rule)?;
+        config.into()
+      }
+      None => matcher,
+    };
+    compile_for_root(&self.ctx, &self.root, config)
+  }
+
+  fn line_index(&self) -> &LineIndex {
+    self.lines.get(self.root.source())
+  }
+
+  fn position(&self, (line, column): (usize, usize), offset: usize) -> Position {
+    Position {
+      line,
+      column,
+      index: self.line_index().js_index(offset),
+    }
+  }
+}
+
+fn path_of(node: &Node<ts::Language>) -> Vec<usize> {
+  let mut path = vec![];
+  let mut current = node.clone();
+  while let Some(parent) = current.parent() {
+    path.push(current.prev_all().count());
+    current = parent;
+  }
+  path.reverse();
+  path
+}
+
+impl Drop for SgNode {
+  fn drop(&mut self) {
+    stats::track(|live| live.nodes -= 1);
+  }
+}
+
+#[derive(Serialize)]
+struct Position {
+  /// zero based, like the ranges of `findNodes`
+  line: usize,
+  column: usize,
+  /// JS string index into the source
+  index: usize,
+}
+
+#[derive(Serialize)]
+struct Range {
+  start: Position,
+  end: Position,
+}
+
+/// Offsets are JS string indices into the source of the root.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Edit {
+  start_pos: usize,
+  end_pos: usize,
+  inserted_text: String,
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  /// The root node, in the shape of `SgRoot.root` of `@ast-grep/napi`.
+  pub fn root(&self) -> SgNode {
+    let (root, lines) = (self.inner.clone(), self.lines.clone());
+    SgNode::new(root, lines, self.ctx.clone(), vec![], None)
+  }
+
+  /// The node spanning `range`, in the shape of a range of `findNodes`, to walk on from a match.
+  /// Nodes sharing a range, e.g. a call and its expression statement, are told apart by `kind`,
+  /// without it the outermost is returned.
+  #[wasm_bindgen(js_name = nodeAt)]
+  pub fn node_at(
+    &self,
+    range: MatchRange,
+    kind: Option<String>,
+  ) -> Result<Option<SgNode>, JsValue> {
+    let range: [usize; 4] =
+      serde_wasm_bindgen::from_value(range.into()).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let (start, end) = ((range[0], range[1]), (range[2], range[3]));
+    let mut node = self.inner.root();
+    loop {
+      let same_kind = kind.as_deref().map_or(true, |k| node.kind() == k);
+      if node.start_pos() == start && node.end_pos() == end && same_kind {
+        let path = path_of(&node);
+        return Ok(Some(SgNode::new(
+          self.inner.clone(),
+          self.lines.clone(),
+          self.ctx.clone(),
+          path,
+          None,
+        )));
+      }
+      let inner = node
+        .children()
+        .find(|c| c.start_pos() <= start && c.end_pos() >= end);
+      match inner {
+        Some(c) => node = c,
+        None => return Ok(None),
+      }
+    }
+  }
+
+  /// Always `anonymous`, roots are parsed from strings like `parse` of `@ast-grep/napi`.
+  pub fn filename(&self) -> String {
+    "anonymous".into()
+  }
+}
+
+#[wasm_bindgen]
+impl SgNode {
+  /// Free the node now instead of when JS garbage collects it.
+  /// A node keeps its tree alive, also after its root was edited or disposed.
+  pub fn dispose(self) {}
+
+  pub fn range(&self) -> Result<NodeRange, JsValue> {
+    let node = self.node();
+    let offsets = node.range();
+    let ret = Range {
+      start: self.position(node.start_pos(), offsets.start),
+      end: self.position(node.end_pos(), offsets.end),
+    };
+    Ok(serialize::to_value(&ret)?.unchecked_into())
+  }
+
+  #[wasm_bindgen(js_name = isLeaf)]
+  pub fn is_leaf(&self) -> bool {
+    self.node().is_leaf()
+  }
+
+  #[wasm_bindgen(js_name = isNamed)]
+  pub fn is_named(&self) -> bool {
+    self.node().is_named()
+  }
+
+  /// Whether the node has no named children, e.g. a string with only its quotes below it.
+  #[wasm_bindgen(js_name = isNamedLeaf)]
+  pub fn is_named_leaf(&self) -> bool {
+    self.node().children().all(|c| !c.is_named())
+  }
+
+  pub fn kind(&self) -> String {
+    self.node().kind().into_owned()
+  }
+
+  /// The numeric id of `kind`, see `getKindTable`.
+  #[wasm_bindgen(js_name = kindId)]
+  pub fn kind_id(&self) -> u16 {
+    self.node().kind_id()
+  }
+
+  pub fn text(&self) -> String {
+    self.node().text().into_owned()
+  }
+
+  pub fn matches(&self, matcher: NodeMatcher) -> Result<bool, JsValue> {
+    let rule = self.compile(matcher)?;
+    Ok(self.node().matches(&*rule))
+  }
+
+  pub fn inside(&self, matcher: NodeMatcher) -> Result<bool, JsValue> {
+    let rule = self.compile(matcher)?;
+    Ok(self.node().inside(&*rule))
+  }
+
+  pub fn has(&self, matcher: NodeMatcher) -> Result<bool, JsValue> {
+    let rule = self.compile(matcher)?;
+    Ok(self.node().has(&*rule))
+  }
+
+  pub fn precedes(&self, matcher: NodeMatcher) -> Result<bool, JsValue> {
+    let rule = self.compile(matcher)?;
+    Ok(self.node().precedes(&*rule))
+  }
+
+  pub fn follows(&self, matcher: NodeMatcher) -> Result<bool, JsValue> {
+    let rule = self.compile(matcher)?;
+    Ok(self.node().follows(&*rule))
+  }
+
+  /// The node captured as `$NAME` by the matcher that found this node, given `NAME`.
+  #[wasm_bindgen(js_name = getMatch)]
+  pub fn get_match(&self, name: &str) -> Option<SgNode> {
+    let rule = self.rule.as_ref()?;
+    let matched = rule.match_node(self.node())?;
+    let node = matched.get_env().get_match(name)?;
+    Some(self.wrap(node, None))
+  }
+
+  /// The nodes captured as `$$$NAME` by the matcher that found this node, given `NAME`.
+  #[wasm_bindgen(js_name = getMultipleMatches)]
+  pub fn get_multiple_matches(&self, name: &str) -> js_sys::Array {
+    let matched = self.rule.as_ref().and_then(|r| r.match_node(self.node()));
+    match matched {
+      Some(m) => self.wrap_all(m.get_env().get_multiple_matches(name).into_iter()),
+      None => js_sys::Array::new(),
+    }
+  }
+
+  pub fn find(&self, matcher: NodeMatcher) -> Result<Option<SgNode>, JsValue> {
+    utils::enter("SgNode.find", self.root.source().len());
+    let rule = self.compile(matcher)?;
+    let found = self.node().find(&*rule);
+    Ok(found.map(|n| self.wrap(&n, Some(rule.clone()))))
+  }
+
+  #[wasm_bindgen(js_name = findAll)]
+  pub fn find_all(&self, matcher: NodeMatcher) -> Result<js_sys::Array, JsValue> {
+    utils::enter("SgNode.findAll", self.root.source().len());
+    let rule = self.compile(matcher)?;
+    let ret = js_sys::Array::new();
+    for n in self.node().find_all(&*rule) {
+      limits::check_matches(ret.length() as usize + 1)?;
+      ret.push(&self.wrap(&n, Some(rule.clone())).into());
+    }
+    Ok(ret)
+  }
+
+  pub fn children(&self) -> js_sys::Array {
+    self.wrap_all(self.node().children())
+  }
+
+  pub fn field(&self, name: &str) -> Option<SgNode> {
+    self.node().field(name).map(|n| self.wrap(&n, None))
+  }
+
+  /// All children in field `name`, `field` returns only the first.
+  #[wasm_bindgen(js_name = fieldChildren)]
+  pub fn field_children(&self, name: &str) -> js_sys::Array {
+    self.wrap_all(self.node().field_children(name))
+  }
+
+  pub fn parent(&self) -> Option<SgNode> {
+    self.node().parent().map(|n| self.wrap(&n, None))
+  }
+
+  pub fn child(&self, nth: usize) -> Option<SgNode> {
+    self.node().child(nth).map(|n| self.wrap(&n, None))
+  }
+
+  pub fn ancestors(&self) -> js_sys::Array {
+    self.wrap_all(self.node().ancestors())
+  }
+
+  pub fn next(&self) -> Option<SgNode> {
+    self.node().next().map(|n| self.wrap(&n, None))
+  }
+
+  #[wasm_bindgen(js_name = nextAll)]
+  pub fn next_all(&self) -> js_sys::Array {
+    self.wrap_all(self.node().next_all())
+  }
+
+  pub fn prev(&self) -> Option<SgNode> {
+    self.node().prev().map(|n| self.wrap(&n, None))
+  }
+
+  #[wasm_bindgen(js_name = prevAll)]
+  pub fn prev_all(&self) -> js_sys::Array {
+    self.wrap_all(self.node().prev_all())
+  }
+
+  /// An edit replacing this node with `text`, applied by `commitEdits`.
+  pub fn replace(&self, text: String) -> Result<NodeEdit, JsValue> {
+    let range = self.node().range();
+    let lines = self.line_index();
+    let edit = Edit {
+      start_pos: lines.js_index(range.start),
+      end_pos: lines.js_index(range.end),
+      inserted_text: text,
+    };
+    Ok(serialize::to_value(&edit)?.unchecked_into())
+  }
+
+  /// The text of this node with `edits` applied, edits must lie inside the node and not overlap.
+  #[wasm_bindgen(js_name = commitEdits)]
+  pub fn commit_edits(&self, edits: JsValue) -> Result<String, JsValue> {
+    let mut edits: Vec<Edit> =
+      serde_wasm_bindgen::from_value(edits).map_err(|e| tag(Code::InvalidArgument, e))?;
+    edits.sort_by_key(|e| e.start_pos);
+    let src = self.root.source();
+    let lines = self.line_index();
+    let range = self.node().range();
+    let range = lines.byte_of(range.start)..lines.byte_of(range.end);
+    let to_byte = |offset| {
+      lines.checked_byte(src, offset).ok_or_else(|| {
+        let msg = format!("offset {} is out of the source", offset);
+        error_with(Code::InvalidEdit, &msg, &[("offset", offset.into())])
+      })
+    };
+    let mut ret = String::new();
+    let mut start = range.start;
+    for edit in edits {
+      let (from, to) = (to_byte(edit.start_pos)?, to_byte(edit.end_pos)?);
+      if from < start || to < from || to > range.end {
+        let msg = "edits must lie inside the node and not overlap";
+        return Err(error(Code::InvalidEdit, msg));
+      }
+      ret.push_str(&src[start..from]);
+      ret.push_str(&edit.inserted_text);
+      start = to;
+    }
+    ret.push_str(&src[start..range.end]);
+    Ok(ret)
+  }
+}
@@ -0,0 +1,331 @@
+use crate::cancel::CancelCheck;
+use crate::context::{global, Context};
+use crate::error::{error, error_with, tag, Code};
+use crate::lines::LineIndex;
+use crate::types::{IdentifierMatches, KindMatches, Matches, PatternMatches, StringArray};
+use crate::{limits, match_range, parse_root, scan_ranges, serialize, utils};
+
+use ast_grep_core::language::Language;
+use ast_grep_core::{KindMatcher, Matcher, Node};
+use serde::Serialize;
+use serde_json::json;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::HashSet;
+
+/// The config `{ language?, rule: { pattern }, fix? }` of the shortcuts.
+fn pattern_config(
+  pattern: String,
+  fix: Option<String>,
+  language: Option<String>,
+) -> Result<JsValue, JsValue> {
+  let mut config = json!({ "rule": { "pattern": pattern } });
+  if let Some(fix) = fix {
+    config["fix"] = fix.into();
+  }
+  if let Some(language) = language {
+    config["language"] = language.into();
+  }
+  Ok(serialize::to_json_value(&config)?)
+}
+
+/// `findNodes` with the config `{ rule: { pattern } }`, for examples and quick experiments.
+#[wasm_bindgen(js_name = findByPattern)]
+pub fn find_by_pattern(
+  src: String,
+  pattern: String,
+  language: Option<String>,
+) -> Result<Matches, JsValue> {
+  global()
+    .find_by_pattern(src, pattern, language)
+    .map(JsCast::unchecked_into)
+}
+
+/// `fixErrors` with the config `{ rule: { pattern }, fix }`, returning the rewritten source.
+#[wasm_bindgen(js_name = replaceByPattern)]
+pub fn replace_by_pattern(
+  src: String,
+  pattern: String,
+  fix: String,
+  language: Option<String>,
+) -> Result<String, JsValue> {
+  global().replace_by_pattern(src, pattern, fix, language)
+}
+
+#[derive(Serialize)]
+struct PatternMatch {
+  /// index of the pattern that matched
+  pattern: usize,
+  range: [usize; 4],
+}
+
+/// Search `src` for any of `patterns` in one parse and traversal, returning `[{ pattern, range }]`
+/// in document order where `pattern` is the index of the pattern that matched,
+/// the earlier pattern first for a node matched by several.
+#[wasm_bindgen(js_name = findByPatterns)]
+pub fn find_by_patterns(
+  src: String,
+  patterns: StringArray,
+  language: Option<String>,
+) -> Result<PatternMatches, JsValue> {
+  global()
+    .find_by_patterns(src, patterns.into(), language)
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Serialize)]
+struct KindMatch {
+  range: [usize; 4],
+  text: String,
+}
+
+/// Every node of the kind `kind` in `src`, `[{ range, text }]` in document order,
+/// e.g. to explore a new grammar. Throws `INVALID_RULE` for kinds the grammar does not have.
+#[wasm_bindgen(js_name = findByKind)]
+pub fn find_by_kind(
+  src: String,
+  kind: String,
+  language: Option<String>,
+) -> Result<KindMatches, JsValue> {
+  global()
+    .find_by_kind(src, kind, language)
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Serialize)]
+struct IdentifierMatch {
+  range: [usize; 4],
+  kind: String,
+}
+
+/// Every identifier of `src` spelled `name`, `[{ range, kind }]` in document order, a grep
+/// for whole names that skips strings and comments. Identifiers are the named leaves whose
+/// kind ends with `identifier`, like `property_identifier` or `type_identifier`.
+#[wasm_bindgen(js_name = findIdentifier)]
+pub fn find_identifier(
+  src: String,
+  name: String,
+  language: Option<String>,
+) -> Result<IdentifierMatches, JsValue> {
+  global()
+    .find_identifier(src, name, language)
+    .map(JsCast::unchecked_into)
+}
+
+/// `findByPattern` for rare patterns in large sources. The longest word of the pattern that
+/// is not a metavariable is searched as text first, and only the nodes around its
+/// occurrences are matched, so a source without it is not even parsed. Patterns made of
+/// metavariables and punctuation alone match every node like `findByPattern`.
+#[wasm_bindgen(js_name = findByPatternPrefiltered)]
+pub fn find_by_pattern_prefiltered(
+  src: String,
+  pattern: String,
+  language: Option<String>,
+) -> Result<Matches, JsValue> {
+  global()
+    .find_by_pattern_prefiltered(src, pattern, language)
+    .map(JsCast::unchecked_into)
+}
+
+/// The longest leaf of `pattern` made of word characters only, which every match contains
+/// as a whole word, `None` if there is none.
+fn prefilter_word(pattern: &str, lang: &ts::Language) -> Option<String> {
+  let processed = lang.pre_process_pattern(pattern);
+  let root = lang.ast_grep(&processed);
+  let is_word = |text: &str| text.chars().all(|c| c.is_alphanumeric() || c == '_');
+  let word = root
+    .root()
+    .dfs()
+    .filter(|n| n.is_leaf())
+    .map(|n| n.text().into_owned())
+    .filter(|text| !text.is_empty() && is_word(text))
+    .filter(|text| lang.extract_meta_var(text).is_none())
+    .max_by_key(|text| text.len());
+  word
+}
+
+/// The leaf of `root` at node offset `offset`, or the innermost node there without children.
+fn node_at<'r>(root: Node<'r, ts::Language>, offset: usize) -> Node<'r, ts::Language> {
+  let mut node = root;
+  loop {
+    let child = node.children().find(|c| {
+      let range = c.range();
+      range.start <= offset && offset < range.end
+    });
+    match child {
+      Some(child) => node = child,
+      None => return node,
+    }
+  }
+}
+
+impl Context {
+  pub fn find_by_pattern(
+    &self,
+    src: String,
+    pattern: String,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    let config = pattern_config(pattern, None, language)?;
+    self.find_nodes(src, config, None)
+  }
+
+  pub fn replace_by_pattern(
+    &self,
+    src: String,
+    pattern: String,
+    fix: String,
+    language: Option<String>,
+  ) -> Result<String, JsValue> {
+    let config = pattern_config(pattern, Some(fix), language)?;
+    self.fix_errors(src, config)
+  }
+
+  pub fn find_by_patterns(
+    &self,
+    src: String,
+    patterns: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findByPatterns", src.len());
+    let patterns: Vec<String> =
+      serde_wasm_bindgen::from_value(patterns).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let rules = patterns
+      .into_iter()
+      .map(|pattern| self.compile_config(pattern_config(pattern, None, language.clone())?))
+      .collect::<Result<Vec<_>, _>>()?;
+    let lang = match rules.first() {
+      Some(rule) => rule.lang.clone(),
+      None => return Ok(js_sys::Array::new().into()),
+    };
+    let root = parse_root(&src, &lang)?;
+    let ranges = scan_ranges(&root, &rules, &mut CancelCheck::new(None, None), None)?;
+    let mut ret: Vec<_> = ranges
+      .into_iter()
+      .enumerate()
+      .flat_map(|(pattern, ranges)| {
+        ranges
+          .into_iter()
+          .map(move |range| PatternMatch { pattern, range })
+      })
+      .collect();
+    // stable, so patterns stay in order at the same node
+    ret.sort_by_key(|m| (m.range[0], m.range[1]));
+    Ok(serialize::to_value(&ret)?)
+  }
+
+  pub fn find_by_kind(
+    &self,
+    src: String,
+    kind: String,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findByKind", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let matcher = KindMatcher::new(&kind, (*lang).clone());
+    if matcher.is_invalid() {
+      let message = format!("unknown kind `{}`", kind);
+      return Err(error_with(
+        Code::InvalidRule,
+        &message,
+        &[("kind", kind.into())],
+      ));
+    }
+    let root = parse_root(&src, &lang)?;
+    let mut ret = vec![];
+    for node in root.root().find_all(matcher) {
+      limits::check_matches(ret.len() + 1)?;
+      ret.push(KindMatch {
+        range: match_range(&node),
+        text: node.text().into_owned(),
+      });
+    }
+    Ok(serialize::to_value(&ret)?)
+  }
+
+  pub fn find_by_pattern_prefiltered(
+    &self,
+    src: String,
+    pattern: String,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findByPatternPrefiltered", src.len());
+    let config = pattern_config(pattern.clone(), None, language)?;
+    let rule = self.compile_config(config)?;
+    let word = match prefilter_word(&pattern, &rule.lang) {
+      Some(word) => word,
+      None => {
+        let root = parse_root(&src, &rule.lang)?;
+        let mut ret = vec![];
+        for m in root.root().find_all(&*rule) {
+          limits::check_matches(ret.len() + 1)?;
+          ret.push(match_range(&m));
+        }
+        return Ok(serialize::to_value(&ret)?);
+      }
+    };
+    let regex = regex::Regex::new(&format!(r"\b{}\b", regex::escape(&word)))
+      .map_err(|e| error(Code::InvalidArgument, &format!("{:?}", e)))?;
+    let lines = LineIndex::new(&src);
+    let hits: Vec<usize> = regex
+      .find_iter(&src)
+      .map(|m| lines.node_offset(m.start()))
+      .collect();
+    if hits.is_empty() {
+      return Ok(serialize::to_value(&Vec::<[usize; 4]>::new())?);
+    }
+    let root = parse_root(&src, &rule.lang)?;
+    // every match contains a hit, so only the nodes from a hit up to the root can match
+    let mut visited = HashSet::new();
+    let mut found = vec![];
+    for hit in hits {
+      let leaf = node_at(root.root(), hit);
+      for node in std::iter::once(leaf.clone()).chain(leaf.ancestors()) {
+        let range = node.range();
+        if !visited.insert((range.start, range.end, node.kind_id())) {
+          // so are its ancestors
+          break;
+        }
+        if let Some(m) = rule.match_node(node) {
+          limits::check_matches(found.len() + 1)?;
+          let depth = m.ancestors().count();
+          found.push((
+            (range.start, std::cmp::Reverse(range.end), depth),
+            match_range(&m),
+          ));
+        }
+      }
+    }
+    // in document order like a traversal, outer nodes first
+    found.sort_by_key(|(key, _)| *key);
+    let ret: Vec<_> = found.into_iter().map(|(_, range)| range).collect();
+    Ok(serialize::to_value(&ret)?)
+  }
+
+  pub fn find_identifier(
+    &self,
+    src: String,
+    name: String,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findIdentifier", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    let mut ret = vec![];
+    for node in root.root().dfs() {
+      if !node.is_leaf() || !node.is_named() || !node.kind().ends_with("identifier") {
+        continue;
+      }
+      if node.text() == name {
+        limits::check_matches(ret.len() + 1)?;
+        ret.push(IdentifierMatch {
+          range: match_range(&node),
+          kind: node.kind().into_owned(),
+        });
+      }
+    }
+    Ok(serialize::to_value(&ret)?)
+  }
+}
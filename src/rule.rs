@@ -0,0 +1,495 @@
+use crate::cancel::{CancelCheck, CancelSignal};
+use crate::context::{global, Context};
+use crate::error::{error, Code};
+use crate::lines::LineEndings;
+use crate::metrics::Metrics;
+use crate::shape;
+use crate::stats;
+use crate::trace::TraceRule;
+use crate::types::{Config, Diagnostics, Matches};
+use crate::utils;
+use crate::validate::{check_compilable, collect_captures, load_warnings, Diagnostic};
+use crate::{find_in_root, fix_in_root, parse_root, serialize, WASMConfig};
+
+use ast_grep_config::{
+  deserialize_rule, try_deserialize_matchers, PatternStyle, RuleWithConstraint,
+  SerializableMetaVarMatcher, SerializableRule, Severity,
+};
+use ast_grep_core::language::Language;
+use ast_grep_core::meta_var::{MetaVarEnv, MetaVarMatcher, MetaVarMatchers};
+use ast_grep_core::{Matcher, Node, NodeMatch, Pattern};
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// A config with its rule, constraints and fix deserialized and patterns parsed.
+pub struct CompiledRule {
+  pub lang: Rc<ts::Language>,
+  pub matcher: RuleWithConstraint<ts::Language>,
+  pub fixer: Option<Pattern<ts::Language>>,
+  pub timeout_ms: Option<f64>,
+  pub metrics: bool,
+  pub coverage: bool,
+  pub id: Option<String>,
+  pub message: Option<String>,
+  pub severity: Option<Severity>,
+  pub line_endings: LineEndings,
+  /// sorted names of the metavariables the patterns of the rule capture
+  pub captures: Vec<String>,
+  /// warnings of `validateRule` about a config that compiles
+  pub warnings: Vec<Diagnostic>,
+  /// sub-rules compiled one by one for `trace: true` configs
+  pub trace: Option<TraceRule>,
+  /// whether a match only depends on the node and its descendants, no `inside`, `precedes`
+  /// or `follows` anywhere in the rule
+  pub local: bool,
+  /// sorted kind ids the rule can match, `None` if it can match any node
+  kinds: Option<Vec<u16>>,
+}
+
+impl CompiledRule {
+  pub fn try_new(config: WASMConfig, lang: Rc<ts::Language>) -> Result<Self, JsValue> {
+    // ast-grep-core panics on patterns it cannot use, which aborts the whole module
+    let regexes = check_compilable(&config, &lang)?;
+    let kinds = potential_kinds(&config.rule, &lang);
+    let local = is_local(&config.rule);
+    let warnings = load_warnings(&config, &lang);
+    let mut captures = vec![];
+    collect_captures(&config.rule, &lang, &mut captures);
+    captures.sort();
+    captures.dedup();
+    let trace = match config.trace {
+      true => Some(TraceRule::try_new(&config.rule, &lang)?),
+      false => None,
+    };
+    let matcher = get_matcher(config.rule, config.constraints, &regexes, &lang)?;
+    let fixer = config.fix.map(|fix| Pattern::new(&fix, (*lang).clone()));
+    Ok(Self {
+      lang,
+      matcher,
+      fixer,
+      timeout_ms: config.timeout_ms,
+      metrics: config.metrics,
+      coverage: config.coverage,
+      id: config.id,
+      message: config.message,
+      severity: config.severity,
+      line_endings: config.line_endings,
+      captures,
+      warnings,
+      trace,
+      local,
+      kinds,
+    })
+  }
+
+  /// sorted kind ids the rule can match, `None` if it can match any node
+  pub fn kinds(&self) -> Option<&[u16]> {
+    self.kinds.as_deref()
+  }
+
+  pub fn may_match(&self, node: &Node<ts::Language>) -> bool {
+    match &self.kinds {
+      Some(kinds) => kinds.binary_search(&node.kind_id()).is_ok(),
+      None => true,
+    }
+  }
+}
+
+// skip nodes of other kinds before cloning constraints into an env and matching the whole rule
+impl Matcher<ts::Language> for CompiledRule {
+  fn match_node_with_env<'tree>(
+    &self,
+    node: Node<'tree, ts::Language>,
+    env: &mut MetaVarEnv<'tree, ts::Language>,
+  ) -> Option<Node<'tree, ts::Language>> {
+    if !self.may_match(&node) {
+      return None;
+    }
+    self.matcher.match_node_with_env(node, env)
+  }
+
+  fn match_node<'tree>(
+    &self,
+    node: Node<'tree, ts::Language>,
+  ) -> Option<NodeMatch<'tree, ts::Language>> {
+    if !self.may_match(&node) {
+      return None;
+    }
+    self.matcher.match_node(node)
+  }
+
+  fn get_meta_var_env<'tree>(&self) -> MetaVarEnv<'tree, ts::Language> {
+    self.matcher.get_meta_var_env()
+  }
+}
+
+pub fn potential_kinds(rule: &SerializableRule, lang: &ts::Language) -> Option<Vec<u16>> {
+  use SerializableRule as S;
+  bounded_kinds(rule, &|leaf| match leaf {
+    S::Kind(kind) => Some(lang.get_ts_language().id_for_node_kind(kind, true)),
+    S::Pattern(PatternStyle::Str(pattern)) => pattern_kind(pattern, lang),
+    _ => None,
+  })
+}
+
+/// The kinds `rule` can match, with `leaf` the kind of a `kind` or string `pattern` rule.
+fn bounded_kinds(
+  rule: &SerializableRule,
+  leaf: &dyn Fn(&SerializableRule) -> Option<u16>,
+) -> Option<Vec<u16>> {
+  use SerializableRule as S;
+  let mut kinds = match rule {
+    S::Kind(_) | S::Pattern(PatternStyle::Str(_)) => vec![leaf(rule)?],
+    // every sub rule must match, so any of them bounds the kinds
+    S::All(rules) => rules
+      .iter()
+      .filter_map(|r| bounded_kinds(r, leaf))
+      .reduce(|a, b| a.into_iter().filter(|k| b.contains(k)).collect())?,
+    S::Any(rules) => {
+      let kinds = rules.iter().map(|r| bounded_kinds(r, leaf));
+      kinds.collect::<Option<Vec<_>>>()?.concat()
+    }
+    // relational and contextual rules can match nodes of any kind
+    _ => return None,
+  };
+  kinds.sort_unstable();
+  kinds.dedup();
+  Some(kinds)
+}
+
+fn is_local(rule: &SerializableRule) -> bool {
+  // the fields of relational rules are private, look for the keys in the serialized rule
+  fn walk(value: &serde_json::Value) -> bool {
+    match value {
+      serde_json::Value::Object(map) => map.iter().all(|(key, value)| {
+        !matches!(key.as_str(), "inside" | "precedes" | "follows") && walk(value)
+      }),
+      serde_json::Value::Array(values) => values.iter().all(walk),
+      _ => true,
+    }
+  }
+  serde_json::to_value(rule).is_ok_and(|value| walk(&value))
+}
+
+/// Kind of the node a pattern matches, `None` for a lone meta variable matching anything.
+fn pattern_kind(pattern: &str, lang: &ts::Language) -> Option<u16> {
+  let pattern = Pattern::new(pattern, lang.clone());
+  let node = pattern_node(pattern.root.root());
+  if node.is_leaf() && lang.extract_meta_var(&node.text()).is_some() {
+    return None;
+  }
+  Some(node.kind_id())
+}
+
+/// The node a pattern parsed to `root` matches with.
+pub fn pattern_node(mut node: Node<ts::Language>) -> Node<ts::Language> {
+  // descend like the pattern matcher does, zero width children are missing nodes
+  loop {
+    let children: Vec<_> = node.children().collect();
+    node = match children.as_slice() {
+      [only] => only.clone(),
+      [first, second] if second.range().is_empty() => first.clone(),
+      _ => return node,
+    };
+  }
+}
+
+/// Mirror of `RelationalRule`, whose fields are private in ast-grep-config.
+#[derive(Deserialize)]
+pub struct Relation {
+  #[serde(flatten)]
+  pub rule: SerializableRule,
+  #[serde(default)]
+  pub until: Option<SerializableRule>,
+  #[serde(default)]
+  pub immediate: bool,
+}
+
+/// Read the fields of a relational rule, which it only exposes through serde.
+pub fn split_relation<T: Serialize>(relation: &T) -> Result<Relation, serde_wasm_bindgen::Error> {
+  let value = serde_wasm_bindgen::to_value(relation)?;
+  serde_wasm_bindgen::from_value(value)
+}
+
+/// The key of `rule` in a config, e.g. `inside`.
+pub fn rule_key(rule: &SerializableRule) -> &'static str {
+  use SerializableRule as S;
+  match rule {
+    S::All(_) => "all",
+    S::Any(_) => "any",
+    S::Not(_) => "not",
+    S::Inside(_) => "inside",
+    S::Has(_) => "has",
+    S::Precedes(_) => "precedes",
+    S::Follows(_) => "follows",
+    S::Pattern(_) => "pattern",
+    S::Kind(_) => "kind",
+  }
+}
+
+/// Call `visit` with `rule` and each of its sub-rules, parents first, and their paths in the
+/// config, e.g. `rule.all[1]`. The rule of a relation is at the path of the relation, like
+/// `rule.inside`, and `until` under it. A relation whose fields cannot be read is passed as
+/// the error at the path of its rule.
+pub fn walk_rule<F>(rule: &SerializableRule, path: &str, visit: &mut F)
+where
+  F: FnMut(&str, Result<&SerializableRule, serde_wasm_bindgen::Error>),
+{
+  use SerializableRule as S;
+  visit(path, Ok(rule));
+  let path = format!("{}.{}", path, rule_key(rule));
+  match rule {
+    S::All(rules) | S::Any(rules) => {
+      for (i, rule) in rules.iter().enumerate() {
+        walk_rule(rule, &format!("{}[{}]", path, i), visit);
+      }
+    }
+    S::Not(rule) => walk_rule(rule, &path, visit),
+    S::Inside(r) | S::Has(r) | S::Precedes(r) | S::Follows(r) => match split_relation(r) {
+      Ok(relation) => {
+        walk_rule(&relation.rule, &path, visit);
+        if let Some(until) = &relation.until {
+          walk_rule(until, &format!("{}.until", path), visit);
+        }
+      }
+      Err(e) => visit(&path, Err(e)),
+    },
+    S::Pattern(_) | S::Kind(_) => (),
+  }
+}
+
+fn get_matcher(
+  rule: SerializableRule,
+  constraints: Option<HashMap<String, SerializableMetaVarMatcher>>,
+  regexes: &HashMap<String, regex::Regex>,
+  lang: &ts::Language,
+) -> Result<RuleWithConstraint<ts::Language>, JsValue> {
+  let rule =
+    deserialize_rule(rule, lang.clone()).map_err(|e| error(Code::InvalidRule, &e.to_string()))?;
+  let matchers = constraint_matchers(constraints.unwrap_or_default(), regexes, lang)?;
+  Ok(RuleWithConstraint { rule, matchers })
+}
+
+/// Matchers of `constraints`, taking the regexes from those `check_compilable` compiled.
+pub fn constraint_matchers(
+  constraints: HashMap<String, SerializableMetaVarMatcher>,
+  regexes: &HashMap<String, regex::Regex>,
+  lang: &ts::Language,
+) -> Result<MetaVarMatchers<ts::Language>, JsValue> {
+  let (compiled, rest): (HashMap<_, _>, HashMap<_, _>) = constraints
+    .into_iter()
+    .partition(|(key, _)| regexes.contains_key(key));
+  let mut matchers = try_deserialize_matchers(rest, lang.clone()).map_err(|e| {
+    error(
+      Code::InvalidConstraint,
+      &format!("invalid constraint: {:?}", e),
+    )
+  })?;
+  for key in compiled.into_keys() {
+    let regex = regexes[&key].clone();
+    matchers.insert(key, MetaVarMatcher::Regex(regex));
+  }
+  Ok(matchers)
+}
+
+const CACHE_SIZE: usize = 32;
+
+/// Recently compiled rules, the least recently used are at the back,
+/// keyed by the JSON text of the config.
+#[derive(Default)]
+pub struct RuleCache(VecDeque<(String, Rc<CompiledRule>)>);
+
+impl RuleCache {
+  fn get(&mut self, key: &str, lang: &ts::Language) -> Option<Rc<CompiledRule>> {
+    // the same config compiles differently after setupParser changes the default language
+    let index = self
+      .0
+      .iter()
+      .position(|(k, rule)| k == key && *rule.lang == *lang)?;
+    let entry = self.0.remove(index)?;
+    let rule = entry.1.clone();
+    self.0.push_front(entry);
+    Some(rule)
+  }
+
+  fn put(&mut self, key: String, rule: Rc<CompiledRule>) {
+    self.0.push_front((key, rule));
+    self.0.truncate(CACHE_SIZE);
+  }
+
+  pub fn clear(&mut self) {
+    self.0.clear();
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+}
+
+impl Context {
+  /// Compile a JS config, reusing the result of an identical config compiled recently.
+  pub fn compile_config(&self, config: JsValue) -> Result<Rc<CompiledRule>, JsValue> {
+    let language = js_sys::Reflect::get(&config, &"language".into())
+      .ok()
+      .and_then(|l| l.as_string());
+    let lang = self.get_language(language.as_deref())?;
+    self.compile_config_with(config, lang)
+  }
+
+  /// Same as `compile_config` but with the language already resolved.
+  pub fn compile_config_with(
+    &self,
+    config: JsValue,
+    lang: Rc<ts::Language>,
+  ) -> Result<Rc<CompiledRule>, JsValue> {
+    let key = js_sys::JSON::stringify(&config)
+      .ok()
+      .and_then(|s| s.as_string());
+    if let Some(key) = &key {
+      if let Some(rule) = self.rules.borrow_mut().get(key, &lang) {
+        return Ok(rule);
+      }
+    }
+    let config = shape::read_config(config)?;
+    let rule = Rc::new(CompiledRule::try_new(config, lang)?);
+    if let Some(key) = key {
+      self.rules.borrow_mut().put(key, rule.clone());
+    }
+    Ok(rule)
+  }
+
+  /// Compile configs scanned together, they must all use the same language.
+  pub fn compile_configs(&self, configs: js_sys::Array) -> Result<Vec<Rc<CompiledRule>>, JsValue> {
+    let rules = configs
+      .iter()
+      .map(|config| self.compile_config(config))
+      .collect::<Result<Vec<_>, _>>()?;
+    if rules.windows(2).any(|w| *w[0].lang != *w[1].lang) {
+      let msg = "all configs scanned together must use the same language";
+      return Err(error(Code::MixedLanguages, msg));
+    }
+    Ok(rules)
+  }
+}
+
+/// A compiled config that can scan many sources without deserializing the rule again.
+#[wasm_bindgen]
+pub struct RuleHandle {
+  pub(crate) inner: Rc<CompiledRule>,
+}
+
+impl RuleHandle {
+  pub(crate) fn new(inner: Rc<CompiledRule>) -> Self {
+    stats::track(|live| live.rules += 1);
+    Self { inner }
+  }
+}
+
+#[wasm_bindgen(js_name = compileRule)]
+pub fn compile_rule(config: Config) -> Result<RuleHandle, JsValue> {
+  Ok(RuleHandle::new(global().compile_config(config.into())?))
+}
+
+impl Drop for RuleHandle {
+  fn drop(&mut self) {
+    stats::track(|live| live.rules -= 1);
+  }
+}
+
+#[wasm_bindgen]
+impl RuleHandle {
+  /// Free the compiled rule now instead of when JS garbage collects the handle.
+  pub fn dispose(self) {}
+
+  /// Problems of a config that compiles, in the shape of `validateRule`,
+  /// e.g. constraints on metavariables no pattern captures.
+  #[wasm_bindgen(getter)]
+  pub fn warnings(&self) -> Result<Diagnostics, JsValue> {
+    Ok(serialize::to_value(&self.inner.warnings)?.unchecked_into())
+  }
+
+  #[wasm_bindgen(js_name = findNodes)]
+  pub fn find_nodes(&self, src: String, cancel: Option<CancelSignal>) -> Result<Matches, JsValue> {
+    utils::enter("RuleHandle.findNodes", src.len());
+    let mut metrics = Metrics::start();
+    let mut check = CancelCheck::new(cancel.as_ref(), self.inner.timeout_ms);
+    let root = parse_root(&src, &self.inner.lang)?;
+    metrics.parsed();
+    find_in_root(&root, &self.inner, &mut check, metrics).map(JsCast::unchecked_into)
+  }
+
+  #[wasm_bindgen(js_name = fixErrors)]
+  pub fn fix_errors(&self, src: String) -> Result<String, JsValue> {
+    utils::enter("RuleHandle.fixErrors", src.len());
+    let root = parse_root(&src, &self.inner.lang)?;
+    fix_in_root(&root, &self.inner)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_walk_rule() {
+    let yaml = "all: [{ kind: a }, { not: { any: [{ pattern: x }] } }]";
+    let rule: SerializableRule = ast_grep_config::from_str(yaml).expect("should parse");
+    let mut visited = vec![];
+    walk_rule(&rule, "rule", &mut |path, rule| {
+      visited.push(format!("{} {}", path, rule_key(rule.expect("no relation"))));
+    });
+    let expected = [
+      "rule all",
+      "rule.all[0] kind",
+      "rule.all[1] not",
+      "rule.all[1].not any",
+      "rule.all[1].not.any[0] pattern",
+    ];
+    assert_eq!(visited, expected);
+  }
+
+  fn kinds(yaml: &str) -> Option<Vec<u16>> {
+    let rule: SerializableRule = ast_grep_config::from_str(yaml).expect("should parse");
+    // kinds are numbered by their name, a pattern of a lone metavariable matches anything
+    bounded_kinds(&rule, &|leaf| match leaf {
+      SerializableRule::Kind(kind) => kind.parse().ok(),
+      SerializableRule::Pattern(PatternStyle::Str(p)) if p.starts_with('$') => None,
+      SerializableRule::Pattern(PatternStyle::Str(p)) => p.parse().ok(),
+      _ => None,
+    })
+  }
+
+  #[test]
+  fn test_potential_kinds() {
+    assert_eq!(kinds("kind: '3'"), Some(vec![3]));
+    assert_eq!(kinds("pattern: $A"), None);
+    assert_eq!(
+      kinds("any: [{ kind: '3' }, { pattern: '1' }, { kind: '3' }]"),
+      Some(vec![1, 3])
+    );
+    // one unbounded alternative unbounds the whole any
+    assert_eq!(kinds("any: [{ kind: '3' }, { pattern: $A }]"), None);
+    assert_eq!(kinds("any: [{ kind: '3' }, { not: { kind: '1' } }]"), None);
+    assert_eq!(kinds("not: { kind: '1' }"), None);
+    assert_eq!(
+      kinds("all: [{ kind: '3' }, { not: { kind: '3' } }]"),
+      Some(vec![3])
+    );
+    assert_eq!(
+      kinds("all: [{ any: [{ kind: '1' }, { kind: '2' }] }, { kind: '2' }]"),
+      Some(vec![2])
+    );
+    assert_eq!(kinds("all: [{ kind: '1' }, { kind: '2' }]"), Some(vec![]));
+    assert_eq!(
+      kinds("all: [{ has: { kind: '1' } }, { pattern: $A }]"),
+      None
+    );
+    assert_eq!(kinds("pattern: { context: a, selector: b }"), None);
+    assert_eq!(kinds("inside: { kind: '1' }"), None);
+  }
+}
@@ -0,0 +1,214 @@
+use crate::error::{tag, Code};
+use crate::serialize;
+
+use ast_grep_config::Severity;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// The results of `scanRules`, or of `scanMany` with the name of each source as its URI.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scan {
+  One(Vec<Vec<[usize; 4]>>),
+  Many(Vec<Source>),
+}
+
+#[derive(Deserialize)]
+struct Source {
+  name: String,
+  matches: Vec<Vec<[usize; 4]>>,
+}
+
+/// Describes the scanned configs in order, the configs themselves can be passed as `rules`.
+#[derive(Deserialize)]
+struct Metadata {
+  #[serde(default)]
+  rules: Vec<RuleMetadata>,
+  /// URI of the source scanned by `scanRules`
+  uri: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct RuleMetadata {
+  id: Option<String>,
+  message: Option<String>,
+  severity: Option<Severity>,
+}
+
+#[derive(Serialize)]
+struct Log {
+  #[serde(rename = "$schema")]
+  schema: &'static str,
+  version: &'static str,
+  runs: [Run; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+  tool: Tool,
+  results: Vec<SarifResult>,
+  /// columns of the ranges are JS string indices, SARIF assumes code points otherwise
+  column_kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct Tool {
+  driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+  name: &'static str,
+  version: &'static str,
+  information_uri: &'static str,
+  rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportingDescriptor {
+  id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  short_description: Option<Message>,
+  default_configuration: Configuration,
+}
+
+#[derive(Serialize)]
+struct Configuration {
+  level: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct Message {
+  text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+  rule_id: String,
+  rule_index: usize,
+  level: &'static str,
+  message: Message,
+  locations: [Location; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+  physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  artifact_location: Option<ArtifactLocation>,
+  region: Region,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+  uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+  start_line: usize,
+  start_column: usize,
+  end_line: usize,
+  end_column: usize,
+}
+
+fn level(severity: Option<&Severity>) -> &'static str {
+  match severity {
+    Some(Severity::Error) => "error",
+    Some(Severity::Hint | Severity::Info) => "note",
+    Some(Severity::Warning) | None => "warning",
+  }
+}
+
+/// Convert the results of `scanRules` or `scanMany` to a SARIF 2.1.0 log for code scanning dashboards.
+/// `metadata` is `{ rules, uri? }` where `rules` describes each scanned config in order
+/// with its `id`, `message` and `severity`, so the configs themselves can be passed.
+/// `uri` names the source of `scanRules`, `scanMany` sources are named by their `name`.
+/// Rules without `id` are named by their index, lines and columns are made one based,
+/// columns are UTF-16 code units as `columnKind` of the run declares.
+#[wasm_bindgen(js_name = toSarif)]
+pub fn to_sarif(results: JsValue, metadata: JsValue) -> Result<JsValue, JsValue> {
+  let scan: Scan =
+    serde_wasm_bindgen::from_value(results).map_err(|e| tag(Code::InvalidArgument, e))?;
+  let metadata: Metadata =
+    serde_wasm_bindgen::from_value(metadata).map_err(|e| tag(Code::InvalidArgument, e))?;
+  let sources = match scan {
+    Scan::One(matches) => vec![(metadata.uri.clone(), matches)],
+    Scan::Many(sources) => sources
+      .into_iter()
+      .map(|s| (Some(s.name), s.matches))
+      .collect(),
+  };
+  let count = sources.iter().map(|(_, m)| m.len()).max().unwrap_or(0);
+  let mut rules = metadata.rules;
+  rules.resize_with(rules.len().max(count), RuleMetadata::default);
+  let ids: Vec<_> = rules
+    .iter()
+    .enumerate()
+    .map(|(i, r)| r.id.clone().unwrap_or_else(|| format!("rule-{}", i)))
+    .collect();
+  let mut results = vec![];
+  for (uri, matches) in sources {
+    for (i, ranges) in matches.into_iter().enumerate() {
+      let rule = &rules[i];
+      let text = rule.message.clone().unwrap_or_else(|| ids[i].clone());
+      for [start_line, start_column, end_line, end_column] in ranges {
+        let physical_location = PhysicalLocation {
+          artifact_location: uri.clone().map(|uri| ArtifactLocation { uri }),
+          region: Region {
+            start_line: start_line + 1,
+            start_column: start_column + 1,
+            end_line: end_line + 1,
+            end_column: end_column + 1,
+          },
+        };
+        results.push(SarifResult {
+          rule_id: ids[i].clone(),
+          rule_index: i,
+          level: level(rule.severity.as_ref()),
+          message: Message { text: text.clone() },
+          locations: [Location { physical_location }],
+        });
+      }
+    }
+  }
+  let descriptors = rules
+    .iter()
+    .zip(ids)
+    .map(|(rule, id)| ReportingDescriptor {
+      id,
+      short_description: rule.message.clone().map(|text| Message { text }),
+      default_configuration: Configuration {
+        level: level(rule.severity.as_ref()),
+      },
+    })
+    .collect();
+  let log = Log {
+    schema: "https://json.schemastore.org/sarif-2.1.0.json",
+    version: "2.1.0",
+    runs: [Run {
+      tool: Tool {
+        driver: Driver {
+          name: "ast-grep-wasm",
+          version: env!("CARGO_PKG_VERSION"),
+          information_uri: "https://ast-grep.github.io",
+          rules: descriptors,
+        },
+      },
+      results,
+      column_kind: "utf16CodeUnits",
+    }],
+  };
+  Ok(serialize::to_json_value(&log)?)
+}
@@ -0,0 +1,60 @@
+use crate::error::{tag, Code};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use std::cell::Cell;
+
+/// Options of the serde_wasm_bindgen serializer producing every result object.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SerializeOptions {
+  /// plain objects instead of `Map` for maps
+  maps_as_objects: bool,
+  /// `BigInt` instead of lossy numbers for 64-bit integers
+  large_numbers_as_bigints: bool,
+  /// `null` instead of `undefined` for `None`
+  missing_as_null: bool,
+  /// numeric `kindId` instead of `kind` names in dumps, see `getKindTable`
+  kind_ids: bool,
+}
+
+thread_local! {
+  static OPTIONS: Cell<SerializeOptions> = Cell::new(SerializeOptions::default());
+}
+
+/// Change how results are converted to JS, e.g. `{ mapsAsObjects: true }` for JSON-shaped output.
+/// Omitted options fall back to serde_wasm_bindgen's defaults.
+#[wasm_bindgen(js_name = setSerializeOptions)]
+pub fn set_serialize_options(options: JsValue) -> Result<(), JsValue> {
+  let options: SerializeOptions = if options.is_undefined() || options.is_null() {
+    SerializeOptions::default()
+  } else {
+    serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+  };
+  OPTIONS.with(|o| o.set(options));
+  Ok(())
+}
+
+/// Whether nodes in dumps carry kind ids instead of kind names.
+pub fn kind_ids() -> bool {
+  OPTIONS.with(|o| o.get().kind_ids)
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, serde_wasm_bindgen::Error> {
+  let options = OPTIONS.with(Cell::get);
+  let serializer = serde_wasm_bindgen::Serializer::new()
+    .serialize_maps_as_objects(options.maps_as_objects)
+    .serialize_large_number_types_as_bigints(options.large_numbers_as_bigints)
+    .serialize_missing_as_null(options.missing_as_null);
+  value.serialize(&serializer)
+}
+
+/// Like `to_value` but JSON compatible whatever the options, with plain objects for maps,
+/// `null` for `None` and numbers for 64-bit integers, for results in the shape of another
+/// tool's JSON output.
+pub fn to_json_value<T: Serialize + ?Sized>(
+  value: &T,
+) -> Result<JsValue, serde_wasm_bindgen::Error> {
+  value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+}
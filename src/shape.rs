@@ -0,0 +1,289 @@
+use crate::error::{error, error_with, Code};
+use crate::WASMConfig;
+
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+
+/// Longest `received` text, longer values are cut with `...`.
+const RECEIVED_CHARS: usize = 60;
+
+const RULE_KEYS: &str = "one of all, any, not, inside, has, precedes, follows, pattern or kind";
+
+/// Where a config deviates from the shape of `WASMConfig`.
+/// Serde only tells which type failed, e.g. `unknown variant`, not where in the config.
+pub struct Mistake {
+  /// e.g. `rule.has.kind`
+  pub path: String,
+  pub expected: &'static str,
+  /// the offending value as JSON
+  pub received: String,
+}
+
+impl Mistake {
+  fn new(path: &str, expected: &'static str, received: &Value) -> Self {
+    let mut received = received.to_string();
+    if received.chars().count() > RECEIVED_CHARS {
+      received = received.chars().take(RECEIVED_CHARS).collect::<String>() + "...";
+    }
+    Self {
+      path: path.into(),
+      expected,
+      received,
+    }
+  }
+
+  pub fn message(&self) -> String {
+    format!("expected {}, got {}", self.expected, self.received)
+  }
+}
+
+impl From<Mistake> for JsValue {
+  fn from(m: Mistake) -> Self {
+    let message = format!("{}: {}", m.path, m.message());
+    let params = [
+      ("path", JsValue::from(m.path)),
+      ("received", JsValue::from(m.received)),
+    ];
+    error_with(Code::InvalidConfig, &message, &params)
+  }
+}
+
+/// Deserialize a config object, erring with the `path` and `received` value of the mistake.
+pub fn read_config(config: JsValue) -> Result<WASMConfig, JsValue> {
+  serde_wasm_bindgen::from_value(config.clone()).map_err(|e| {
+    let value = serde_wasm_bindgen::from_value(config).ok();
+    match value.map(|v| check_config(&v)) {
+      Some(Err(mistake)) => mistake.into(),
+      _ => error(Code::InvalidConfig, &e.to_string()),
+    }
+  })
+}
+
+/// Err with the first mistake in `config`.
+pub fn check_config(config: &Value) -> Result<(), Mistake> {
+  let object = match config {
+    Value::Object(object) => object,
+    other => return Err(Mistake::new("", "a config object", other)),
+  };
+  match object.get("rule") {
+    Some(rule) => check_rule(rule, "rule")?,
+    None => return Err(Mistake::new("rule", "a rule", &Value::Null)),
+  }
+  for key in ["language", "fix", "id", "message"] {
+    check_optional(object, key, "a string", Value::is_string)?;
+  }
+  check_optional(object, "timeoutMs", "a number", Value::is_number)?;
+  for key in ["metrics", "trace", "coverage"] {
+    check_optional(object, key, "a boolean", Value::is_boolean)?;
+  }
+  let expected = "one of hint, info, warning or error";
+  check_optional(object, "severity", expected, |v| {
+    matches!(v.as_str(), Some("hint" | "info" | "warning" | "error"))
+  })?;
+  let expected = "one of preserve, auto, lf or crlf";
+  check_optional(object, "lineEndings", expected, |v| {
+    matches!(v.as_str(), Some("preserve" | "auto" | "lf" | "crlf"))
+  })?;
+  match object.get("constraints") {
+    None | Some(Value::Null) => Ok(()),
+    Some(Value::Object(constraints)) => constraints
+      .iter()
+      .try_for_each(|(name, m)| check_matcher(m, &format!("constraints.{}", name))),
+    Some(other) => Err(Mistake::new("constraints", "an object", other)),
+  }
+}
+
+fn check_optional(
+  object: &Map<String, Value>,
+  key: &str,
+  expected: &'static str,
+  is: fn(&Value) -> bool,
+) -> Result<(), Mistake> {
+  match object.get(key) {
+    Some(value) if !value.is_null() && !is(value) => Err(Mistake::new(key, expected, value)),
+    _ => Ok(()),
+  }
+}
+
+fn check_string(value: &Value, path: &str) -> Result<(), Mistake> {
+  match value {
+    Value::String(_) => Ok(()),
+    other => Err(Mistake::new(path, "a string", other)),
+  }
+}
+
+/// The single key of a rule or matcher object, which names its variant.
+fn variant<'v>(
+  value: &'v Value,
+  path: &str,
+  expected: &'static str,
+) -> Result<(&'v str, &'v Value), Mistake> {
+  match value {
+    Value::Object(object) if object.len() == 1 => {
+      let (key, value) = object.iter().next().unwrap();
+      Ok((key, value))
+    }
+    other => Err(Mistake::new(path, expected, other)),
+  }
+}
+
+fn check_rule(rule: &Value, path: &str) -> Result<(), Mistake> {
+  let (key, value) = variant(rule, path, RULE_KEYS)?;
+  let path = format!("{}.{}", path, key);
+  match key {
+    "all" | "any" => match value {
+      Value::Array(rules) => rules
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, r)| check_rule(r, &format!("{}[{}]", path, i))),
+      other => Err(Mistake::new(&path, "an array of rules", other)),
+    },
+    "not" => check_rule(value, &path),
+    "inside" | "has" | "precedes" | "follows" => check_relation(value, &path),
+    "pattern" => match value {
+      Value::Object(contextual) => {
+        for key in ["context", "selector"] {
+          let part = contextual.get(key).unwrap_or(&Value::Null);
+          check_string(part, &format!("{}.{}", path, key))?;
+        }
+        Ok(())
+      }
+      other => check_string(other, &path),
+    },
+    "kind" => check_string(value, &path),
+    // report the key, its value is fine for all we know
+    _ => Err(Mistake::new(&path, RULE_KEYS, &Value::String(key.into()))),
+  }
+}
+
+fn check_relation(relation: &Value, path: &str) -> Result<(), Mistake> {
+  let object = match relation {
+    Value::Object(object) => object,
+    other => return Err(Mistake::new(path, "a relational rule", other)),
+  };
+  // the rule of a relation is flattened next to `until` and `immediate`
+  let mut rule = object.clone();
+  if let Some(until) = rule.remove("until") {
+    if !until.is_null() {
+      check_rule(&until, &format!("{}.until", path))?;
+    }
+  }
+  if let Some(immediate) = rule.remove("immediate") {
+    if !immediate.is_boolean() {
+      return Err(Mistake::new(
+        &format!("{}.immediate", path),
+        "a boolean",
+        &immediate,
+      ));
+    }
+  }
+  check_rule(&Value::Object(rule), path)
+}
+
+fn check_matcher(matcher: &Value, path: &str) -> Result<(), Mistake> {
+  let (key, value) = variant(matcher, path, "one of regex, pattern or kind")?;
+  let path = format!("{}.{}", path, key);
+  match key {
+    "regex" | "pattern" | "kind" => check_string(value, &path),
+    _ => Err(Mistake::new(
+      &path,
+      "one of regex, pattern or kind",
+      &Value::String(key.into()),
+    )),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use serde_json::json;
+
+  fn mistake(config: Value) -> (String, &'static str, String) {
+    match check_config(&config) {
+      Ok(()) => panic!("{} should be a mistake", config),
+      Err(m) => (m.path, m.expected, m.received),
+    }
+  }
+
+  #[test]
+  fn test_valid_config() {
+    let config = json!({
+      "rule": {
+        "all": [
+          { "pattern": { "context": "class A { $F = 1 }", "selector": "field_definition" } },
+          { "inside": { "kind": "class_body", "until": { "kind": "program" }, "immediate": false } },
+          { "not": { "has": { "pattern": "$A" } } },
+        ]
+      },
+      "constraints": { "F": { "regex": "^a" } },
+      "severity": "hint",
+      "language": "javascript",
+    });
+    assert!(check_config(&config).is_ok());
+  }
+
+  #[test]
+  fn test_rule_mistakes() {
+    let (path, expected, received) = mistake(json!({ "language": "js" }));
+    assert_eq!(
+      (path.as_str(), expected, received.as_str()),
+      ("rule", "a rule", "null")
+    );
+    let (path, expected, _) = mistake(json!({ "rule": { "kind": "a", "pattern": "b" } }));
+    assert_eq!((path.as_str(), expected), ("rule", RULE_KEYS));
+    let (path, _, received) =
+      mistake(json!({ "rule": { "all": [{ "kind": "a" }, { "knd": "b" }] } }));
+    assert_eq!(
+      (path.as_str(), received.as_str()),
+      ("rule.all[1].knd", "\"knd\"")
+    );
+    let (path, expected, _) = mistake(json!({ "rule": { "any": { "kind": "a" } } }));
+    assert_eq!((path.as_str(), expected), ("rule.any", "an array of rules"));
+    let (path, expected, _) = mistake(json!({ "rule": { "pattern": { "context": "a" } } }));
+    assert_eq!(
+      (path.as_str(), expected),
+      ("rule.pattern.selector", "a string")
+    );
+  }
+
+  #[test]
+  fn test_relation_mistakes() {
+    let rule = json!({ "rule": { "has": { "kind": "a", "immediate": "yes" } } });
+    let (path, expected, _) = mistake(rule);
+    assert_eq!(
+      (path.as_str(), expected),
+      ("rule.has.immediate", "a boolean")
+    );
+    let rule = json!({ "rule": { "inside": { "kind": "a", "until": { "kind": 1 } } } });
+    let (path, expected, received) = mistake(rule);
+    assert_eq!(
+      (path.as_str(), expected),
+      ("rule.inside.until.kind", "a string")
+    );
+    assert_eq!(received, "1");
+    // `until` does not count as the rule of the relation
+    let (path, _, _) = mistake(json!({ "rule": { "follows": { "until": { "kind": "a" } } } }));
+    assert_eq!(path, "rule.follows");
+  }
+
+  #[test]
+  fn test_field_mistakes() {
+    let (path, expected, _) = mistake(json!({ "rule": { "kind": "a" }, "severity": "fatal" }));
+    assert_eq!(
+      (path.as_str(), expected),
+      ("severity", "one of hint, info, warning or error")
+    );
+    let (path, expected, _) = mistake(json!({ "rule": { "kind": "a" }, "fix": 1 }));
+    assert_eq!((path.as_str(), expected), ("fix", "a string"));
+    let config = json!({ "rule": { "kind": "a" }, "constraints": { "A": { "regx": "a" } } });
+    let (path, expected, _) = mistake(config);
+    assert_eq!(
+      (path.as_str(), expected),
+      ("constraints.A.regx", "one of regex, pattern or kind")
+    );
+    let config = json!({ "rule": { "kind": "a" }, "message": vec![1; 100] });
+    let (path, _, received) = mistake(config);
+    assert_eq!(path, "message");
+    assert_eq!(received.chars().count(), RECEIVED_CHARS + 3);
+  }
+}
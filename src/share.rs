@@ -0,0 +1,154 @@
+use crate::context::{global, Context};
+use crate::error::{error, tag, Code};
+use crate::serialize;
+use crate::utils;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+// raw deflate of the host, CompressionStream is in browsers, Deno and Node.js 18
+#[wasm_bindgen(inline_js = "
+function pipe(bytes, transform) {
+  return new Response(new Blob([bytes]).stream().pipeThrough(transform)).arrayBuffer()
+}
+export function deflate(bytes) { return pipe(bytes, new CompressionStream('deflate-raw')) }
+export function inflate(bytes) { return pipe(bytes, new DecompressionStream('deflate-raw')) }
+")]
+extern "C" {
+  #[wasm_bindgen(catch)]
+  fn deflate(bytes: &[u8]) -> Result<js_sys::Promise, JsValue>;
+  #[wasm_bindgen(catch)]
+  fn inflate(bytes: &[u8]) -> Result<js_sys::Promise, JsValue>;
+}
+
+/// First byte of an encoded state, bumped when the layout changes incompatibly.
+/// New fields do not need a bump, decoding ignores unknown fields and defaults missing ones.
+const VERSION: u8 = 1;
+
+/// What a playground share link restores.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+struct State {
+  source: String,
+  config_yaml: String,
+  lang: String,
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// base64url without padding, safe in a URL fragment as is
+fn to_base64url(bytes: &[u8]) -> String {
+  let mut ret = String::with_capacity((bytes.len() * 4).div_ceil(3));
+  for chunk in bytes.chunks(3) {
+    let n = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+    for i in 0..=chunk.len() {
+      ret.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+    }
+  }
+  ret
+}
+
+fn from_base64url(text: &str) -> Option<Vec<u8>> {
+  let digits = text
+    .bytes()
+    .map(|c| ALPHABET.iter().position(|a| *a == c).map(|d| d as u32))
+    .collect::<Option<Vec<_>>>()?;
+  let mut ret = Vec::with_capacity(digits.len() * 3 / 4);
+  for chunk in digits.chunks(4) {
+    if chunk.len() == 1 {
+      return None;
+    }
+    let n = chunk
+      .iter()
+      .enumerate()
+      .fold(0, |n, (i, d)| n | d << (18 - 6 * i));
+    for i in 0..chunk.len() - 1 {
+      ret.push((n >> (16 - 8 * i)) as u8);
+    }
+  }
+  Some(ret)
+}
+
+async fn transform(promise: Result<js_sys::Promise, JsValue>) -> Result<Vec<u8>, JsValue> {
+  let buffer = JsFuture::from(promise?).await?;
+  Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+fn invalid_link(detail: &str) -> JsValue {
+  let message = format!("not a share link of this playground: {}", detail);
+  error(Code::InvalidArgument, &message)
+}
+
+/// Encode `{ source, configYaml, lang }` into a compact base64url string for share links,
+/// a version byte and the deflated JSON of the state. Resolves to the string.
+/// Compression is the host's `CompressionStream`, so this needs a browser, Deno or Node.js 18.
+#[wasm_bindgen(js_name = encodeState)]
+pub async fn encode_state(state: JsValue) -> Result<JsValue, JsValue> {
+  let state: State =
+    serde_wasm_bindgen::from_value(state).map_err(|e| tag(Code::InvalidArgument, e))?;
+  let json = serde_json::to_vec(&state).expect("strings serialize to JSON");
+  let mut bytes = vec![VERSION];
+  bytes.extend(transform(deflate(&json)).await?);
+  Ok(to_base64url(&bytes).into())
+}
+
+/// Decode a string of `encodeState`, resolving to `{ source, configYaml, lang, diagnostics }`
+/// where `diagnostics` are those of `validateRule` on `configYaml`, so a shared config is
+/// checked by the code that runs it. Unknown fields are ignored and missing ones are empty.
+/// Throws `INVALID_ARGUMENT` for strings that are no share link or come from a newer version.
+#[wasm_bindgen(js_name = decodeState)]
+pub async fn decode_state(encoded: String) -> Result<JsValue, JsValue> {
+  global().decode_state(encoded).await
+}
+
+impl Context {
+  pub async fn decode_state(&self, encoded: String) -> Result<JsValue, JsValue> {
+    utils::enter("decodeState", encoded.len());
+    let bytes = from_base64url(&encoded).ok_or_else(|| invalid_link("not base64url"))?;
+    let (version, deflated) = match bytes.split_first() {
+      Some((version, deflated)) => (*version, deflated),
+      None => return Err(invalid_link("empty")),
+    };
+    if version != VERSION {
+      let detail = format!("version {} is not supported, only {}", version, VERSION);
+      return Err(invalid_link(&detail));
+    }
+    let json = transform(inflate(deflated))
+      .await
+      .map_err(|_| invalid_link("corrupt data"))?;
+    let state: State = serde_json::from_slice(&json).map_err(|e| invalid_link(&e.to_string()))?;
+    let ret = serialize::to_value(&state)?;
+    let diagnostics = self.validate_rule(state.config_yaml.into())?;
+    js_sys::Reflect::set(&ret, &"diagnostics".into(), &diagnostics)?;
+    Ok(ret)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_base64url_round_trip() {
+    let bytes = [0xfb, 0xff, 0x00, 0x3e];
+    for len in 0..=bytes.len() {
+      let encoded = to_base64url(&bytes[..len]);
+      assert_eq!(encoded.len(), (len * 4).div_ceil(3));
+      assert_eq!(from_base64url(&encoded).as_deref(), Some(&bytes[..len]));
+    }
+    assert_eq!(to_base64url(&[0xfb, 0xff]), "-_8");
+  }
+
+  #[test]
+  fn test_base64url_rejects() {
+    // a single digit holds 6 bits, less than a byte
+    assert_eq!(from_base64url("QUJD"), Some(b"ABC".to_vec()));
+    assert_eq!(from_base64url("QUJDR"), None);
+    assert_eq!(from_base64url("QU+D"), None);
+    assert_eq!(from_base64url("QU=="), None);
+  }
+}
@@ -0,0 +1,151 @@
+use crate::context::{global, Context};
+use crate::error::{error, tag, Code};
+use crate::rule::pattern_node;
+use crate::types::SimilarMatches;
+use crate::{limits, match_range, parse_root, serialize, utils};
+
+use ast_grep_core::language::Language;
+use ast_grep_core::Node;
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SimilarOptions {
+  /// most token edits a match may need, a quarter of the pattern tokens by default
+  max_distance: Option<usize>,
+}
+
+/// A token of the pattern, metavariables stand for any run of tokens.
+#[derive(PartialEq)]
+enum Token<'a> {
+  Text(std::borrow::Cow<'a, str>),
+  Wildcard,
+}
+
+#[derive(Serialize)]
+struct SimilarMatch {
+  range: [usize; 4],
+  text: String,
+  /// token insertions, deletions and substitutions turning the pattern into the match
+  distance: usize,
+  /// `1 - distance / tokens` of the longer of the two, 1 for an exact match
+  score: f64,
+}
+
+/// The leaves of `node`, without zero width ones like automatic semicolons.
+fn leaves<'r>(node: &Node<'r, ts::Language>) -> Vec<Node<'r, ts::Language>> {
+  node
+    .dfs()
+    .filter(|n| n.is_leaf() && !n.range().is_empty())
+    .collect()
+}
+
+/// Edit distance between the pattern and `text`, wildcards absorbing any tokens for free.
+fn distance(pattern: &[Token], text: &[Token]) -> usize {
+  let mut prev: Vec<usize> = (0..=text.len()).collect();
+  let mut row = vec![0; text.len() + 1];
+  for p in pattern {
+    row[0] = match p {
+      Token::Wildcard => prev[0],
+      Token::Text(_) => prev[0] + 1,
+    };
+    for (j, t) in text.iter().enumerate() {
+      row[j + 1] = match p {
+        Token::Wildcard => prev[j + 1].min(row[j]),
+        Token::Text(_) => {
+          let substitute = prev[j] + usize::from(p != t);
+          substitute.min(prev[j + 1] + 1).min(row[j] + 1)
+        }
+      };
+    }
+    std::mem::swap(&mut prev, &mut row);
+  }
+  prev[text.len()]
+}
+
+/// Nodes of `src` that look roughly like `pattern`, within `options.maxDistance` token edits,
+/// `[{ range, text, distance, score }]` with the closest first, e.g. to explore code similar to
+/// a snippet. Candidates are the nodes of the kind of the pattern, compared token by token,
+/// and metavariables of the pattern match any run of tokens.
+#[wasm_bindgen(js_name = findSimilar)]
+pub fn find_similar(
+  src: String,
+  pattern: String,
+  options: JsValue,
+  language: Option<String>,
+) -> Result<SimilarMatches, JsValue> {
+  global()
+    .find_similar(src, pattern, options, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn find_similar(
+    &self,
+    src: String,
+    pattern: String,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("findSimilar", src.len());
+    let options: SimilarOptions = if options.is_undefined() || options.is_null() {
+      SimilarOptions::default()
+    } else {
+      serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+    };
+    let lang = self.get_language(language.as_deref())?;
+    let processed = lang.pre_process_pattern(&pattern);
+    let pattern_root = lang.ast_grep(&processed);
+    let target = pattern_node(pattern_root.root());
+    let tokens: Vec<_> = leaves(&target)
+      .into_iter()
+      .map(|leaf| match lang.extract_meta_var(&leaf.text()) {
+        Some(_) => Token::Wildcard,
+        None => Token::Text(leaf.text()),
+      })
+      .collect();
+    let exact = tokens.iter().filter(|t| **t != Token::Wildcard).count();
+    if exact == 0 {
+      return Err(error(
+        Code::InvalidArgument,
+        "the pattern has no tokens to compare",
+      ));
+    }
+    let wildcards = exact < tokens.len();
+    let max_distance = options.max_distance.unwrap_or((exact / 4).max(1));
+    let root = parse_root(&src, &lang)?;
+    let mut ret = vec![];
+    for node in root
+      .root()
+      .dfs()
+      .filter(|n| n.kind_id() == target.kind_id())
+    {
+      let text: Vec<_> = leaves(&node)
+        .into_iter()
+        .map(|leaf| Token::Text(leaf.text()))
+        .collect();
+      // without wildcards the lengths alone bound the distance
+      if !wildcards && text.len().abs_diff(tokens.len()) > max_distance {
+        continue;
+      }
+      let distance = distance(&tokens, &text);
+      if distance > max_distance {
+        continue;
+      }
+      limits::check_matches(ret.len() + 1)?;
+      let longer = exact.max(text.len()) as f64;
+      ret.push(SimilarMatch {
+        range: match_range(&node),
+        text: node.text().into_owned(),
+        distance,
+        score: (1.0 - distance as f64 / longer).max(0.0),
+      });
+    }
+    // stable, so equally close matches stay in document order
+    ret.sort_by_key(|m| m.distance);
+    Ok(serialize::to_value(&ret)?)
+  }
+}
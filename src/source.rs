@@ -0,0 +1,74 @@
+use crate::error::{error, Code};
+use crate::serialize;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Bytes replaced by U+FFFD because they are no UTF-8, offsets are into the given bytes.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvalidSequence {
+  byte_offset: usize,
+  length: usize,
+}
+
+/// A source given as a string, or as bytes decoded lossily.
+pub struct Decoded {
+  pub text: String,
+  pub invalid: Vec<InvalidSequence>,
+}
+
+/// Decode a `string | Uint8Array` source, positions of the results are in the decoded text.
+pub fn decode(src: JsValue) -> Result<Decoded, JsValue> {
+  if let Some(text) = src.as_string() {
+    let invalid = vec![];
+    return Ok(Decoded { text, invalid });
+  }
+  let bytes = match src.dyn_ref::<js_sys::Uint8Array>() {
+    Some(bytes) => bytes.to_vec(),
+    None => {
+      let message = "the source must be a string or a Uint8Array";
+      return Err(error(Code::InvalidArgument, message));
+    }
+  };
+  let mut text = String::with_capacity(bytes.len());
+  let mut invalid = vec![];
+  let mut rest = &bytes[..];
+  while !rest.is_empty() {
+    match std::str::from_utf8(rest) {
+      Ok(valid) => {
+        text.push_str(valid);
+        break;
+      }
+      Err(e) => {
+        let valid = e.valid_up_to();
+        text.push_str(std::str::from_utf8(&rest[..valid]).expect("valid up to the error"));
+        text.push(char::REPLACEMENT_CHARACTER);
+        // a sequence cut off by the end of the input has no error length
+        let length = e.error_len().unwrap_or(rest.len() - valid);
+        invalid.push(InvalidSequence {
+          byte_offset: bytes.len() - rest.len() + valid,
+          length,
+        });
+        rest = &rest[valid + length..];
+      }
+    }
+  }
+  Ok(Decoded { text, invalid })
+}
+
+/// Run `scan` on the decoded `src`, setting `invalidUtf8` on its result
+/// if bytes were replaced while decoding.
+pub fn scan_decoded(
+  src: JsValue,
+  scan: impl FnOnce(String) -> Result<JsValue, JsValue>,
+) -> Result<JsValue, JsValue> {
+  let src = decode(src)?;
+  let ret = scan(src.text)?;
+  if !src.invalid.is_empty() {
+    let value = serialize::to_value(&src.invalid)?;
+    js_sys::Reflect::set(&ret, &"invalidUtf8".into(), &value)?;
+  }
+  Ok(ret)
+}
@@ -0,0 +1,62 @@
+use crate::context::global;
+use crate::serialize;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use std::cell::Cell;
+
+/// Counts of handles owned by JS, updated when handles are created and freed.
+#[derive(Clone, Copy, Default)]
+pub struct Live {
+  pub roots: usize,
+  pub root_source_bytes: usize,
+  pub rules: usize,
+  pub match_lists: usize,
+  pub nodes: usize,
+}
+
+thread_local! {
+  static LIVE: Cell<Live> = Cell::new(Live::default());
+}
+
+pub fn track(update: impl FnOnce(&mut Live)) {
+  LIVE.with(|live| {
+    let mut current = live.get();
+    update(&mut current);
+    live.set(current);
+  });
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryStats {
+  /// size of this module's linear memory, which never shrinks
+  heap_bytes: f64,
+  live_roots: usize,
+  /// trees are kept in web-tree-sitter's own memory, only their sources are counted here
+  root_source_bytes: usize,
+  live_rules: usize,
+  live_match_lists: usize,
+  live_nodes: usize,
+  cached_rules: usize,
+}
+
+/// Memory held by this module, handles not yet freed and the compiled rule cache,
+/// for long running sessions to spot leaked handles.
+#[wasm_bindgen(js_name = getMemoryStats)]
+pub fn get_memory_stats() -> Result<JsValue, JsValue> {
+  let buffer = js_sys::Reflect::get(&wasm_bindgen::memory(), &"buffer".into())?;
+  let heap_bytes = js_sys::Reflect::get(&buffer, &"byteLength".into())?;
+  let live = LIVE.with(Cell::get);
+  let stats = MemoryStats {
+    heap_bytes: heap_bytes.as_f64().unwrap_or_default(),
+    live_roots: live.roots,
+    root_source_bytes: live.root_source_bytes,
+    live_rules: live.rules,
+    live_match_lists: live.match_lists,
+    live_nodes: live.nodes,
+    cached_rules: global().rules.borrow().len(),
+  };
+  Ok(serialize::to_value(&stats)?)
+}
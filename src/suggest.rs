@@ -0,0 +1,172 @@
+use crate::context::{global, Context};
+use crate::error::{error, tag, Code};
+use crate::lines::LineIndex;
+use crate::types::MatchRange;
+use crate::{parse_root, serialize, utils};
+
+use ast_grep_core::Node;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+/// Which nodes of the selection become metavariables.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SuggestOptions {
+  /// nodes whose kind ends with `identifier`
+  identifiers: bool,
+  /// strings, numbers, booleans and other nodes whose kind looks like a literal
+  literals: bool,
+}
+
+impl Default for SuggestOptions {
+  fn default() -> Self {
+    Self {
+      identifiers: true,
+      literals: false,
+    }
+  }
+}
+
+impl SuggestOptions {
+  fn generalizes(&self, node: &Node<ts::Language>) -> bool {
+    if !node.is_named() {
+      return false;
+    }
+    let kind = node.kind();
+    let literal = ["string", "number", "integer", "float", "literal"]
+      .iter()
+      .any(|suffix| kind.ends_with(suffix))
+      || matches!(&*kind, "true" | "false" | "null" | "none" | "nil");
+    (self.identifiers && kind.ends_with("identifier")) || (self.literals && literal)
+  }
+}
+
+#[derive(Serialize)]
+struct Hole {
+  name: String,
+  /// the selected text it replaces
+  text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Suggestion {
+  pattern: String,
+  /// the kind of the selected node, e.g. for a `kind` rule next to the pattern
+  kind: String,
+  meta_variables: Vec<Hole>,
+  /// whether the pattern matches the selected node, false if generalizing broke it
+  matches_selection: bool,
+}
+
+/// `A` to `Z`, then `A1`, `B1`, ...
+fn meta_var_name(i: usize) -> String {
+  let letter = (b'A' + (i % 26) as u8) as char;
+  match i / 26 {
+    0 => letter.to_string(),
+    n => format!("{}{}", letter, n),
+  }
+}
+
+/// Suggest a pattern for the code selected by `range`, in the shape of a range of `findNodes`.
+/// The innermost node spanning the selection is taken with its identifiers, and with
+/// `options.literals` its literals, turned into metavariables, the same text into the same one.
+/// `options` is `{ identifiers?: true, literals?: false }`.
+/// Returns `{ pattern, kind, metaVariables: [{ name, text }], matchesSelection }`.
+#[wasm_bindgen(js_name = suggestPattern)]
+pub fn suggest_pattern(
+  src: String,
+  range: MatchRange,
+  options: JsValue,
+  language: Option<String>,
+) -> Result<JsValue, JsValue> {
+  global().suggest_pattern(src, range.into(), options, language)
+}
+
+impl Context {
+  pub fn suggest_pattern(
+    &self,
+    src: String,
+    range: JsValue,
+    options: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("suggestPattern", src.len());
+    let range: [usize; 4] =
+      serde_wasm_bindgen::from_value(range).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let options: SuggestOptions = if options.is_undefined() || options.is_null() {
+      SuggestOptions::default()
+    } else {
+      serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+    };
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    let (start, end) = ((range[0], range[1]), (range[2], range[3]));
+    let mut node = root.root();
+    if node.start_pos() > start || node.end_pos() < end {
+      return Err(error(Code::InvalidArgument, "range is out of the source"));
+    }
+    loop {
+      let inner = node
+        .children()
+        .find(|c| c.start_pos() <= start && c.end_pos() >= end);
+      match inner {
+        Some(c) => node = c,
+        None => break,
+      }
+    }
+    let lines = LineIndex::new(&src);
+    // `Node::text` slices the source by the node range, which is not in bytes on wasm32
+    let (offset, end) = (
+      lines.byte_of(node.range().start),
+      lines.byte_of(node.range().end),
+    );
+    let text = &src[offset..end];
+    let mut pattern = String::with_capacity(text.len());
+    let mut holes: Vec<Hole> = vec![];
+    let mut copied = 0;
+    // the selected node itself stays, a lone metavariable would match anything
+    let mut stack: Vec<_> = node.children().collect();
+    stack.reverse();
+    while let Some(n) = stack.pop() {
+      if !options.generalizes(&n) {
+        stack.extend(n.children().collect::<Vec<_>>().into_iter().rev());
+        continue;
+      }
+      let r = n.range();
+      let (hole_start, hole_end) = (lines.byte_of(r.start), lines.byte_of(r.end));
+      let hole_text = src[hole_start..hole_end].to_owned();
+      let name = match holes.iter().find(|h| h.text == hole_text) {
+        Some(hole) => hole.name.clone(),
+        None => {
+          let name = meta_var_name(holes.len());
+          holes.push(Hole {
+            name: name.clone(),
+            text: hole_text,
+          });
+          name
+        }
+      };
+      pattern.push_str(&text[copied..hole_start - offset]);
+      pattern.push('$');
+      pattern.push_str(&name);
+      copied = hole_end - offset;
+    }
+    pattern.push_str(&text[copied..]);
+    // compiled like a config, core panics on patterns it cannot use
+    let config = serialize::to_json_value(&json!({ "rule": { "pattern": &pattern } }))?;
+    let matches_selection = match self.compile_config_with(config, lang.clone()) {
+      Ok(rule) => node.matches(&*rule),
+      Err(_) => false,
+    };
+    let ret = Suggestion {
+      kind: node.kind().into_owned(),
+      pattern,
+      meta_variables: holes,
+      matches_selection,
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+}
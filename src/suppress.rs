@@ -0,0 +1,182 @@
+use crate::context::{global, Context};
+use crate::error::{error_with, tag, Code};
+use crate::lines::LineIndex;
+use crate::types::SuppressionEdit;
+use crate::{serialize, utils};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+const IGNORE: &str = "ast-grep-ignore";
+
+/// What `suppressionEdit` reads of a diagnostic of `lintDiagnostics` or `quickFixes`.
+#[derive(Deserialize)]
+struct Finding {
+  from: usize,
+  /// the id of the rule
+  #[serde(default)]
+  source: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Edit {
+  from: usize,
+  to: usize,
+  insert: String,
+}
+
+/// Start and end of a comment in `language`, line comments have no end.
+fn comment_delimiters(language: &str) -> (&'static str, &'static str) {
+  match language {
+    "python" | "ruby" | "bash" | "yaml" | "toml" | "elixir" | "r" | "perl" => ("#", ""),
+    "lua" | "haskell" | "sql" => ("--", ""),
+    "html" | "xml" | "vue" | "svelte" => ("<!--", " -->"),
+    "css" => ("/*", " */"),
+    _ => ("//", ""),
+  }
+}
+
+/// Byte offset of `part`, a slice of `src`.
+fn offset_in(src: &str, part: &str) -> usize {
+  part.as_ptr() as usize - src.as_ptr() as usize
+}
+
+/// The text after `ast-grep-ignore` if `line` is a suppression comment.
+fn ignore_comment<'a>(line: &'a str, (start, end): (&str, &str)) -> Option<&'a str> {
+  let body = line.trim().strip_prefix(start)?;
+  let body = body.strip_suffix(end.trim_start()).unwrap_or(body);
+  let rest = body.trim_start().strip_prefix(IGNORE)?.trim_end();
+  (rest.is_empty() || rest.starts_with(':')).then_some(rest)
+}
+
+/// Every comment syntax of `comment_delimiters`, a scan does not know the name of its language.
+const DELIMITERS: [(&str, &str); 5] = [
+  ("//", ""),
+  ("#", ""),
+  ("--", ""),
+  ("<!--", " -->"),
+  ("/*", " */"),
+];
+
+/// Whether an `ast-grep-ignore` comment on the line above byte `at` of `src` suppresses
+/// the rule `id`, a comment without ids suppresses every rule like in the CLI.
+pub fn is_suppressed(src: &str, at: usize, id: Option<&str>) -> bool {
+  let line_start = match src[..at].rfind('\n') {
+    Some(i) => i + 1,
+    None => return false,
+  };
+  let above = src[..line_start - 1].rfind('\n').map_or(0, |i| i + 1);
+  let line = &src[above..line_start - 1];
+  let mut comments = DELIMITERS.iter().filter_map(|&d| ignore_comment(line, d));
+  comments.any(|rest| match rest.strip_prefix(':') {
+    None => true,
+    Some(ids) => id.is_some_and(|id| ids.split(',').any(|i| i.trim() == id)),
+  })
+}
+
+/// The edit suppressing `diagnostic`, `{ from, to, insert }` in JS string indices, e.g. for a
+/// "suppress this finding" action. It inserts `// ast-grep-ignore: <source>` with the
+/// comment syntax of `language`, or the current one, above the line of `diagnostic.from`
+/// at its indentation, or adds the rule to such a comment there. A diagnostic without
+/// `source` suppresses every rule. Returns `null` if the finding is suppressed already.
+/// Scans reporting diagnostics leave out the findings such comments suppress.
+#[wasm_bindgen(js_name = suppressionEdit)]
+pub fn suppression_edit(
+  src: String,
+  diagnostic: JsValue,
+  language: Option<String>,
+) -> Result<SuppressionEdit, JsValue> {
+  global()
+    .suppression_edit(src, diagnostic, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn suppression_edit(
+    &self,
+    src: String,
+    diagnostic: JsValue,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("suppressionEdit", src.len());
+    let finding: Finding =
+      serde_wasm_bindgen::from_value(diagnostic).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let language = language.or_else(|| self.languages.borrow().current_name.clone());
+    let delimiters = comment_delimiters(&language.unwrap_or_default().to_lowercase());
+    let lines = LineIndex::new(&src);
+    let at = lines.checked_byte(&src, finding.from).ok_or_else(|| {
+      let message = format!("offset {} is out of the source", finding.from);
+      error_with(
+        Code::InvalidArgument,
+        &message,
+        &[("offset", finding.from.into())],
+      )
+    })?;
+    let line_start = src[..at].rfind('\n').map_or(0, |i| i + 1);
+    let edit = |from: usize, to: usize, insert: String| Edit {
+      from: lines.utf16(from),
+      to: lines.utf16(to),
+      insert,
+    };
+    if line_start > 0 {
+      let above = src[..line_start - 1].rfind('\n').map_or(0, |i| i + 1);
+      if let Some(rest) = ignore_comment(&src[above..line_start - 1], delimiters) {
+        let ids = rest.strip_prefix(':').map(str::trim);
+        let ret = match (ids, &finding.source) {
+          (None, _) => None,
+          (Some(ids), Some(id)) if ids.split(',').any(|i| i.trim() == id.as_str()) => None,
+          // append the rule to the ids
+          (Some(ids), Some(id)) => {
+            let end = offset_in(&src, ids) + ids.len();
+            Some(edit(end, end, format!(", {}", id)))
+          }
+          // drop the ids to suppress every rule
+          (Some(ids), None) => {
+            let start = offset_in(&src, rest);
+            let end = offset_in(&src, ids) + ids.len();
+            Some(edit(start, end, String::new()))
+          }
+        };
+        return Ok(serialize::to_json_value(&ret)?);
+      }
+    }
+    let line = &src[line_start..];
+    let indent_len = line.find(|c: char| c != ' ' && c != '\t');
+    let indent = &line[..indent_len.unwrap_or(line.len())];
+    let newline = if src.contains("\r\n") { "\r\n" } else { "\n" };
+    let (start, end) = delimiters;
+    let insert = match &finding.source {
+      Some(id) => format!("{}{} {}: {}{}{}", indent, start, IGNORE, id, end, newline),
+      None => format!("{}{} {}{}{}", indent, start, IGNORE, end, newline),
+    };
+    let ret = edit(line_start, line_start, insert);
+    Ok(serialize::to_json_value(&ret)?)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn suppressed(src: &str, id: Option<&str>) -> bool {
+    is_suppressed(src, src.find("foo").expect("a match"), id)
+  }
+
+  #[test]
+  fn test_is_suppressed() {
+    assert!(suppressed("// ast-grep-ignore\nfoo()", Some("a")));
+    assert!(suppressed(
+      "  # ast-grep-ignore: b, a\r\n  foo()",
+      Some("a")
+    ));
+    assert!(suppressed("<!-- ast-grep-ignore: a -->\n<foo/>", Some("a")));
+    assert!(suppressed("bar()\n-- ast-grep-ignore\nfoo()", None));
+    assert!(!suppressed("// ast-grep-ignore: b\nfoo()", Some("a")));
+    assert!(!suppressed("// ast-grep-ignore: a\nfoo()", None));
+    // only the line right above counts
+    assert!(!suppressed("// ast-grep-ignore\n\nfoo()", Some("a")));
+    assert!(!suppressed("foo() // ast-grep-ignore", Some("a")));
+    assert!(!suppressed("// ast-grep-ignored\nfoo()", Some("a")));
+  }
+}
@@ -0,0 +1,175 @@
+use crate::context::{global, Context};
+use crate::error::{error, tag, Code};
+use crate::types::Tags;
+use crate::{limits, serialize, utils};
+
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_tree_sitter_sg as web;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+thread_local! {
+  // the web-tree-sitter grammar behind each loaded language, queries need it and
+  // the facade keeps it private
+  static GRAMMARS: RefCell<Vec<(ts::Language, web::Language)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Remember the grammar `lang` was made of, for its tags query.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub fn remember_grammar(lang: &ts::Language, grammar: web::Language) {
+  GRAMMARS.with(|g| g.borrow_mut().push((lang.clone(), grammar)));
+}
+
+fn grammar_of(lang: &ts::Language) -> Option<web::Language> {
+  GRAMMARS.with(|g| {
+    let grammars = g.borrow();
+    grammars
+      .iter()
+      .find(|(l, _)| l == lang)
+      .map(|(_, grammar)| grammar.clone())
+  })
+}
+
+/// Compiled `tags.scm` queries of the languages of a context.
+#[derive(Default)]
+pub struct TagQueries(Vec<(Rc<ts::Language>, web::Query)>);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Tag {
+  name: String,
+  /// `definition` or `reference`
+  role: String,
+  /// the rest of the capture, e.g. `function` of `@definition.function`
+  kind: String,
+  /// the whole tagged node, e.g. the function declaration
+  range: [usize; 4],
+  /// the `@name` capture
+  name_range: [usize; 4],
+}
+
+fn collect_tags(query: &web::Query, tree: &web::Tree) -> Result<Vec<Tag>, JsValue> {
+  let mut ret = vec![];
+  for m in query.matches(&tree.root_node(), None, None).iter() {
+    let m: &web::QueryMatch = m.unchecked_ref();
+    let captures: Vec<web::QueryCapture> = m
+      .captures()
+      .iter()
+      .map(|c| c.clone().unchecked_into())
+      .collect();
+    let name = captures.iter().find(|c| c.name() == "name");
+    let tagged = captures.iter().find_map(|c| {
+      let capture = String::from(c.name());
+      let (role, kind) = capture.split_once('.')?;
+      matches!(role, "definition" | "reference").then(|| (role.to_string(), kind.to_string(), c))
+    });
+    if let (Some(name), Some((role, kind, tagged))) = (name, tagged) {
+      limits::check_matches(ret.len() + 1)?;
+      let name = name.node();
+      ret.push(Tag {
+        name: name.text().into(),
+        role,
+        kind,
+        range: node_range(&tagged.node()),
+        name_range: node_range(&name),
+      });
+    }
+  }
+  Ok(ret)
+}
+
+fn node_range(node: &web::SyntaxNode) -> [usize; 4] {
+  let (start, end) = (node.start_position(), node.end_position());
+  [
+    start.row() as usize,
+    start.column() as usize,
+    end.row() as usize,
+    end.column() as usize,
+  ]
+}
+
+/// Use `query`, the `tags.scm` of a grammar, for `extractTags` with `language`, or the current one.
+/// Throws `INVALID_ARGUMENT` for a query the grammar cannot compile.
+#[wasm_bindgen(js_name = loadTagsQuery)]
+pub fn load_tags_query(query: String, language: Option<String>) -> Result<(), JsValue> {
+  global().load_tags_query(query, language)
+}
+
+/// The symbols of `src` found by the tags query of its language, in document order,
+/// `[{ name, role, kind, range, nameRange }]` where `role` is `definition` or `reference`
+/// and `kind` is e.g. `function` or `call`. Matches without a `@name` capture are skipped.
+#[wasm_bindgen(js_name = extractTags)]
+pub fn extract_tags(src: String, language: Option<String>) -> Result<Tags, JsValue> {
+  global()
+    .extract_tags(src, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn load_tags_query(&self, query: String, language: Option<String>) -> Result<(), JsValue> {
+    utils::enter("loadTagsQuery", query.len());
+    let lang = self.get_language(language.as_deref())?;
+    let grammar = grammar_of(&lang).ok_or_else(|| {
+      error(
+        Code::GrammarLoad,
+        "the grammar of the language is not loaded",
+      )
+    })?;
+    let query = grammar
+      .query(&query.into())
+      .map_err(|e| tag(Code::InvalidArgument, e))?;
+    let mut queries = self.tags.borrow_mut();
+    queries.0.retain(|(l, old)| {
+      let same = l == &lang;
+      if same {
+        old.delete();
+      }
+      !same
+    });
+    queries.0.push((lang, query));
+    Ok(())
+  }
+
+  pub fn extract_tags(&self, src: String, language: Option<String>) -> Result<JsValue, JsValue> {
+    utils::enter("extractTags", src.len());
+    limits::check_source(src.len())?;
+    let lang = self.get_language(language.as_deref())?;
+    let queries = self.tags.borrow();
+    let query = queries
+      .0
+      .iter()
+      .find(|(l, _)| l == &lang)
+      .map(|(_, query)| query)
+      .ok_or_else(|| {
+        error(
+          Code::InvalidArgument,
+          "no tags query is loaded for the language",
+        )
+      })?;
+    let grammar = grammar_of(&lang).ok_or_else(|| {
+      error(
+        Code::GrammarLoad,
+        "the grammar of the language is not loaded",
+      )
+    })?;
+    // core does not expose its tree, queries run on a tree of their own
+    let parser = web::Parser::new().map_err(|e| tag(Code::ParserInit, e))?;
+    parser
+      .set_language(Some(&grammar))
+      .map_err(|e| tag(Code::GrammarLoad, e))?;
+    let tree = parser.parse_with_string(&src.as_str().into(), None, None);
+    parser.delete();
+    let tree = tree
+      .map_err(|e| tag(Code::ParserInit, e))?
+      .ok_or_else(|| error(Code::ParserInit, "the parser returned no tree"))?;
+    let ret = collect_tags(query, &tree);
+    tree.delete();
+    let mut ret = ret?;
+    ret.sort_by_key(|t| (t.range[0], t.range[1]));
+    Ok(serialize::to_value(&ret)?)
+  }
+}
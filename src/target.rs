@@ -0,0 +1,244 @@
+use crate::context::{global, Context};
+use crate::error::{error, error_with, Code};
+use crate::rule::CompiledRule;
+use crate::types::RuleForNode;
+use crate::{parse_root, serialize, shape, utils, SgRoot};
+
+use ast_grep_core::{AstGrep, Node};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::rc::Rc;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// The node with the `id` of a dump, its index in a pre-order walk over all children.
+pub fn node_by_id(
+  root: &AstGrep<ts::Language>,
+  id: usize,
+) -> Result<Node<'_, ts::Language>, JsValue> {
+  let mut stack = vec![root.root()];
+  let mut i = 0;
+  while let Some(node) = stack.pop() {
+    if i == id {
+      return Ok(node);
+    }
+    i += 1;
+    stack.extend(node.children().collect::<Vec<_>>().into_iter().rev());
+  }
+  let message = format!("no node has the id {}", id);
+  Err(error_with(
+    Code::InvalidArgument,
+    &message,
+    &[("id", id.into())],
+  ))
+}
+
+#[wasm_bindgen]
+impl SgRoot {
+  /// The source text of the node with the `id` of `dumpASTNodes`, e.g. for a tree view.
+  #[wasm_bindgen(js_name = getNodeText)]
+  pub fn get_node_text(&self, id: usize) -> Result<String, JsValue> {
+    Ok(node_by_id(&self.inner, id)?.text().into_owned())
+  }
+
+  /// The S-expression of the subtree under the node with the `id` of `dumpASTNodes`.
+  #[wasm_bindgen(js_name = getNodeSexp)]
+  pub fn get_node_sexp(&self, id: usize) -> Result<String, JsValue> {
+    Ok(node_by_id(&self.inner, id)?.to_sexp().into_owned())
+  }
+}
+
+#[derive(Serialize)]
+struct Targeted {
+  rule: Value,
+  /// how many nodes of the source the rule matches
+  matches: usize,
+  /// whether the node is the only one, false if no condition told it from the others
+  unique: bool,
+}
+
+fn rule_of(conditions: &[Value]) -> Value {
+  match conditions {
+    [only] => only.clone(),
+    all => json!({ "all": all }),
+  }
+}
+
+/// Conditions that may tell `node` from others of its kind, nearest context first.
+fn candidate_conditions(node: &Node<ts::Language>) -> Vec<Value> {
+  let mut ret = vec![];
+  let named_kind = |n: &Node<ts::Language>| n.is_named().then(|| n.kind().into_owned());
+  if let Some(parent) = node.parent() {
+    if let Some(kind) = named_kind(&parent) {
+      ret.push(json!({ "inside": { "kind": kind, "immediate": true } }));
+    }
+  }
+  // immediate relations look at the very next sibling, which may be anonymous
+  if let Some(kind) = node.next().as_ref().and_then(named_kind) {
+    ret.push(json!({ "precedes": { "kind": kind, "immediate": true } }));
+  }
+  if let Some(kind) = node.prev().as_ref().and_then(named_kind) {
+    ret.push(json!({ "follows": { "kind": kind, "immediate": true } }));
+  }
+  for child in node.children() {
+    if let Some(kind) = named_kind(&child) {
+      ret.push(json!({ "has": { "kind": kind, "immediate": true } }));
+    }
+  }
+  for ancestor in node.ancestors().skip(1) {
+    if let Some(kind) = named_kind(&ancestor) {
+      ret.push(json!({ "inside": { "kind": kind } }));
+    }
+  }
+  ret.push(json!({ "pattern": node.text() }));
+  ret.dedup();
+  ret
+}
+
+/// The smallest rule found, `kind` and conditions on its surroundings, matching the node
+/// with `id` in a dump of `src`, e.g. to learn how to target a node. Conditions are added
+/// one at a time, the one leaving the fewest other matches first, until the node is the only
+/// match. Returns `{ rule, matches, unique }`, `unique` is false if that was not reached.
+/// The rule of `ruleForNode` starting from the first of `starts` that `count` finds the node
+/// with, `None` if it finds it with none.
+fn narrow(
+  starts: Vec<Value>,
+  mut candidates: Vec<Value>,
+  count: impl Fn(&[Value]) -> Option<usize>,
+) -> Option<Targeted> {
+  let (mut conditions, mut matches) = starts
+    .into_iter()
+    .map(|start| vec![start])
+    .find_map(|conditions| count(&conditions).map(|n| (conditions, n)))?;
+  candidates.retain(|c| !conditions.contains(c));
+  while matches > 1 && !candidates.is_empty() {
+    let best = candidates
+      .iter()
+      .enumerate()
+      .filter_map(|(i, candidate)| {
+        let mut next = conditions.clone();
+        next.push(candidate.clone());
+        count(&next).map(|n| (n, i))
+      })
+      .min();
+    match best {
+      Some((n, i)) if n < matches => {
+        conditions.push(candidates.remove(i));
+        matches = n;
+      }
+      _ => break,
+    }
+  }
+  Some(Targeted {
+    rule: rule_of(&conditions),
+    matches,
+    unique: matches == 1,
+  })
+}
+
+#[wasm_bindgen(js_name = ruleForNode)]
+pub fn rule_for_node(
+  src: String,
+  id: usize,
+  language: Option<String>,
+) -> Result<RuleForNode, JsValue> {
+  global()
+    .rule_for_node(src, id, language)
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn rule_for_node(
+    &self,
+    src: String,
+    id: usize,
+    language: Option<String>,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("ruleForNode", src.len());
+    let lang = self.get_language(language.as_deref())?;
+    let root = parse_root(&src, &lang)?;
+    let node = node_by_id(&root, id)?;
+    let count = |conditions: &[Value]| count_matches(&root, &node, conditions, &lang);
+    // kind rules only match named nodes, anonymous ones start from their text
+    let starts = vec![
+      json!({ "kind": node.kind() }),
+      json!({ "pattern": node.text() }),
+    ];
+    let ret = narrow(starts, candidate_conditions(&node), count).ok_or_else(|| {
+      let message = format!("no rule can match the `{}` node {}", node.kind(), id);
+      error(Code::InvalidArgument, &message)
+    })?;
+    Ok(serialize::to_value(&ret)?)
+  }
+}
+
+/// How many nodes `conditions` match, `None` if `node` is not one of them
+/// or they do not compile.
+fn count_matches(
+  root: &AstGrep<ts::Language>,
+  node: &Node<ts::Language>,
+  conditions: &[Value],
+  lang: &Rc<ts::Language>,
+) -> Option<usize> {
+  let config = serialize::to_json_value(&json!({ "rule": rule_of(conditions) })).ok()?;
+  // compiled like a config, core panics on patterns it cannot use. Not cached,
+  // these rules are thrown away and would push the rules of the caller out
+  let config = shape::read_config(config).ok()?;
+  let rule = CompiledRule::try_new(config, lang.clone()).ok()?;
+  let mut found = false;
+  let mut count = 0;
+  for n in root.root().find_all(&rule) {
+    found |= n.range() == node.range() && n.kind_id() == node.kind_id();
+    count += 1;
+  }
+  found.then_some(count)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_unique_kind() {
+    let kind = json!({ "kind": "number" });
+    let candidates = vec![json!({ "inside": { "kind": "program" } })];
+    let count = |conditions: &[Value]| Some(conditions.len());
+    let ret = narrow(vec![kind.clone()], candidates, count).expect("should match");
+    assert_eq!(ret.rule, kind);
+    assert_eq!(ret.matches, 1);
+    assert!(ret.unique);
+  }
+
+  #[test]
+  fn test_narrow() {
+    let kind = json!({ "kind": "identifier" });
+    let pattern = json!({ "pattern": "a" });
+    let inside = json!({ "inside": { "kind": "call_expression" } });
+    // the kind rule misses the node, the pattern matches it and two others
+    let count = |conditions: &[Value]| match conditions {
+      [k, ..] if *k == kind => None,
+      [_] => Some(3),
+      [_, c] if *c == inside => Some(1),
+      _ => Some(2),
+    };
+    let candidates = vec![json!({ "has": { "kind": "x" } }), inside.clone()];
+    let ret = narrow(vec![kind.clone(), pattern.clone()], candidates, count).unwrap();
+    assert_eq!(ret.rule, json!({ "all": [pattern, inside] }));
+    assert!(ret.unique);
+    assert!(narrow(vec![kind.clone()], vec![], count).is_none());
+  }
+
+  #[test]
+  fn test_not_unique() {
+    let count = |_: &[Value]| Some(2);
+    let ret = narrow(
+      vec![json!({ "kind": "a" })],
+      vec![json!({ "kind": "b" })],
+      count,
+    )
+    .unwrap();
+    assert_eq!(ret.matches, 2);
+    assert!(!ret.unique);
+  }
+}
@@ -0,0 +1,483 @@
+use crate::context::{global, Context};
+use crate::error::{parse_yaml, tag, Code};
+use crate::lines::LineIndex;
+use crate::rule::CompiledRule;
+use crate::types::Config;
+use crate::utils;
+use crate::{fix_in_root, match_range, parse_root, serialize};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use std::collections::BTreeMap;
+
+/// Test cases in the shape of the `valid` and `invalid` lists of an `sg test` file.
+#[derive(Deserialize)]
+struct TestCases {
+  /// sources the rule must not match
+  #[serde(default)]
+  valid: Vec<String>,
+  /// sources the rule must match
+  #[serde(default)]
+  invalid: Vec<String>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Validity {
+  Valid,
+  Invalid,
+}
+
+/// Why a case failed, named like the reports of `sg test`.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Failure {
+  /// a valid case matched
+  Noisy,
+  /// an invalid case did not match
+  Missing,
+}
+
+#[derive(Serialize)]
+struct CaseResult {
+  case: Validity,
+  /// index in the `valid` or `invalid` list
+  index: usize,
+  passed: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  failure: Option<Failure>,
+  /// in the shape of the result of `findNodes`
+  matches: Vec<[usize; 4]>,
+}
+
+/// A primary label of the CLI's snapshot files, offsets are UTF-8 bytes.
+#[derive(Deserialize, PartialEq, Serialize)]
+struct Label {
+  source: String,
+  style: String,
+  start: usize,
+  end: usize,
+}
+
+/// What the rule reports on an invalid case, the entry of the case in a snapshot file.
+#[derive(Deserialize, PartialEq, Serialize)]
+struct Snapshot {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  fixed: Option<String>,
+  labels: Vec<Label>,
+}
+
+/// The shape of the CLI's `__snapshots__/<id>-snapshot.yml`, snapshots keyed by source.
+#[derive(Deserialize)]
+struct SnapshotFile {
+  #[serde(default)]
+  snapshots: BTreeMap<String, Snapshot>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Reason {
+  /// the case reports something else than its snapshot
+  Changed,
+  /// the case has no snapshot
+  New,
+  /// the snapshot belongs to no invalid case
+  Obsolete,
+}
+
+#[derive(Serialize)]
+struct Mismatch<'a> {
+  source: &'a str,
+  reason: Reason,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  expected: Option<&'a Snapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  actual: Option<&'a Snapshot>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TestReport<'a> {
+  passed: bool,
+  cases: Vec<CaseResult>,
+  /// snapshots of the invalid cases as they are now, to replace the stored ones with
+  snapshots: &'a BTreeMap<String, Snapshot>,
+  /// only when snapshots are given to compare with
+  #[serde(skip_serializing_if = "Option::is_none")]
+  snapshot_mismatches: Option<Vec<Mismatch<'a>>>,
+}
+
+/// Run `sg test` cases `{ valid, invalid }`, an object or YAML, against a config.
+/// A valid case passes if the rule matches nothing in it, an invalid one if it matches at least once.
+/// Returns `{ passed, cases: [{ case, index, passed, failure?, matches }], snapshots }`
+/// where `failure` is `noisy` for a matched valid case and `missing` for an unmatched invalid one.
+/// `snapshots` maps each invalid source to its `{ labels, fixed? }` like the CLI's snapshot files.
+/// Given the stored snapshot file, an object or YAML, the report also has `snapshotMismatches`,
+/// `[{ source, reason, expected?, actual? }]` with reason `changed`, `new` or `obsolete`,
+/// and `passed` is false unless they agree. Replacing the file with `snapshots` updates it.
+#[wasm_bindgen(js_name = testRule)]
+pub fn test_rule(config: Config, cases: JsValue, snapshots: JsValue) -> Result<JsValue, JsValue> {
+  global().test_rule(config.into(), cases, snapshots)
+}
+
+fn read<T: DeserializeOwned>(value: JsValue) -> Result<T, JsValue> {
+  match value.as_string() {
+    Some(yaml) => parse_yaml(&yaml, Code::InvalidArgument),
+    None => serde_wasm_bindgen::from_value(value).map_err(|e| tag(Code::InvalidArgument, e)),
+  }
+}
+
+impl Context {
+  pub fn test_rule(
+    &self,
+    config: JsValue,
+    cases: JsValue,
+    snapshots: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("testRule", 0);
+    let rule = self.compile_config(config)?;
+    let cases: TestCases = read(cases)?;
+    let stored: Option<SnapshotFile> = if snapshots.is_undefined() || snapshots.is_null() {
+      None
+    } else {
+      Some(read(snapshots)?)
+    };
+    let mut snapshots = BTreeMap::new();
+    let mut results = vec![];
+    for (i, src) in cases.valid.iter().enumerate() {
+      results.push(run_case(&rule, Validity::Valid, i, src)?.0);
+    }
+    for (i, src) in cases.invalid.iter().enumerate() {
+      let (result, snapshot) = run_case(&rule, Validity::Invalid, i, src)?;
+      results.push(result);
+      snapshots.insert(src.clone(), snapshot);
+    }
+    let mismatches = stored
+      .as_ref()
+      .map(|stored| compare(&stored.snapshots, &snapshots));
+    let agree = mismatches.as_ref().map_or(true, Vec::is_empty);
+    let ret = TestReport {
+      passed: agree && results.iter().all(|r| r.passed),
+      cases: results,
+      snapshots: &snapshots,
+      snapshot_mismatches: mismatches,
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+}
+
+fn compare<'a>(
+  stored: &'a BTreeMap<String, Snapshot>,
+  current: &'a BTreeMap<String, Snapshot>,
+) -> Vec<Mismatch<'a>> {
+  let mut mismatches = vec![];
+  for (source, actual) in current {
+    let expected = stored.get(source);
+    let reason = match expected {
+      Some(expected) if expected == actual => continue,
+      Some(_) => Reason::Changed,
+      None => Reason::New,
+    };
+    mismatches.push(Mismatch {
+      source,
+      reason,
+      expected,
+      actual: Some(actual),
+    });
+  }
+  for (source, expected) in stored {
+    if !current.contains_key(source) {
+      mismatches.push(Mismatch {
+        source,
+        reason: Reason::Obsolete,
+        expected: Some(expected),
+        actual: None,
+      });
+    }
+  }
+  mismatches
+}
+
+fn run_case(
+  rule: &CompiledRule,
+  case: Validity,
+  index: usize,
+  src: &str,
+) -> Result<(CaseResult, Snapshot), JsValue> {
+  utils::enter("testRule", src.len());
+  let root = parse_root(src, &rule.lang)?;
+  let lines = LineIndex::new(src);
+  let mut matches = vec![];
+  let mut labels = vec![];
+  for n in root.root().find_all(rule) {
+    let range = n.range();
+    let (start, end) = (lines.byte_of(range.start), lines.byte_of(range.end));
+    matches.push(match_range(&n));
+    labels.push(Label {
+      source: src[start..end].to_owned(),
+      style: "primary".into(),
+      start,
+      end,
+    });
+  }
+  let failure = match case {
+    Validity::Valid if !matches.is_empty() => Some(Failure::Noisy),
+    Validity::Invalid if matches.is_empty() => Some(Failure::Missing),
+    _ => None,
+  };
+  // like the CLI, only a case the rule reports on has a fixed source
+  let fixed = match &rule.fixer {
+    Some(_) if !labels.is_empty() => Some(fix_in_root(&root, rule)?),
+    _ => None,
+  };
+  let result = CaseResult {
+    case,
+    index,
+    passed: failure.is_none(),
+    failure,
+    matches,
+  };
+  Ok((result, Snapshot { fixed, labels }))
+}
+
+#[derive(Serialize)]
+struct MatchReport {
+  passed: bool,
+  /// expected ranges the rule did not match
+  missing: Vec<[usize; 4]>,
+  /// ranges the rule matched but were not expected
+  unexpected: Vec<[usize; 4]>,
+  actual: Vec<[usize; 4]>,
+}
+
+/// Check that `config` matches exactly `expectedRanges` in `src`, in the shape of `findNodes`.
+/// Order does not matter. Returns `{ passed, missing, unexpected, actual }` instead of throwing,
+/// so test frameworks can print the difference.
+#[wasm_bindgen(js_name = assertMatches)]
+pub fn assert_matches(
+  src: String,
+  config: Config,
+  expected_ranges: JsValue,
+) -> Result<JsValue, JsValue> {
+  global().assert_matches(src, config.into(), expected_ranges)
+}
+
+#[derive(Serialize)]
+struct Difference {
+  /// UTF-8 byte offset of the first differing char
+  offset: usize,
+  /// zero based, the column in bytes of the line
+  line: usize,
+  column: usize,
+  /// the text from the difference on, cut to `SNIPPET_CHARS`
+  expected: String,
+  actual: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FixReport {
+  passed: bool,
+  actual: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  first_difference: Option<Difference>,
+}
+
+const SNIPPET_CHARS: usize = 40;
+
+/// Check that fixing `src` with `config` gives `expectedOutput`.
+/// Returns `{ passed, actual, firstDifference? }` where `firstDifference` is
+/// `{ offset, line, column, expected, actual }` with the texts from the first differing char.
+#[wasm_bindgen(js_name = assertFixed)]
+pub fn assert_fixed(
+  src: String,
+  config: Config,
+  expected_output: String,
+) -> Result<JsValue, JsValue> {
+  global().assert_fixed(src, config.into(), expected_output)
+}
+
+impl Context {
+  pub fn assert_matches(
+    &self,
+    src: String,
+    config: JsValue,
+    expected_ranges: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("assertMatches", src.len());
+    let rule = self.compile_config(config)?;
+    let expected: Vec<[usize; 4]> =
+      serde_wasm_bindgen::from_value(expected_ranges).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let root = parse_root(&src, &rule.lang)?;
+    let actual: Vec<_> = root
+      .root()
+      .find_all(&*rule)
+      .map(|n| match_range(&n))
+      .collect();
+    let missing: Vec<_> = expected
+      .iter()
+      .filter(|r| !actual.contains(r))
+      .copied()
+      .collect();
+    let unexpected: Vec<_> = actual
+      .iter()
+      .filter(|r| !expected.contains(r))
+      .copied()
+      .collect();
+    let ret = MatchReport {
+      passed: missing.is_empty() && unexpected.is_empty(),
+      missing,
+      unexpected,
+      actual,
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+
+  pub fn assert_fixed(
+    &self,
+    src: String,
+    config: JsValue,
+    expected_output: String,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("assertFixed", src.len());
+    let rule = self.compile_config(config)?;
+    let root = parse_root(&src, &rule.lang)?;
+    let actual = fix_in_root(&root, &rule)?;
+    let ret = FixReport {
+      passed: actual == expected_output,
+      first_difference: first_difference(&expected_output, &actual),
+      actual,
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+}
+
+fn first_difference(expected: &str, actual: &str) -> Option<Difference> {
+  let offset = expected
+    .char_indices()
+    .zip(actual.chars())
+    .find(|((_, e), a)| e != a)
+    .map(|((i, _), _)| i)
+    // one is a prefix of the other
+    .unwrap_or_else(|| expected.len().min(actual.len()));
+  if expected.len() == actual.len() && offset == expected.len() {
+    return None;
+  }
+  let before = &expected[..offset];
+  let line = before.matches('\n').count();
+  let column = offset - before.rfind('\n').map_or(0, |i| i + 1);
+  let snippet = |text: &str| text[offset..].chars().take(SNIPPET_CHARS).collect();
+  Some(Difference {
+    offset,
+    line,
+    column,
+    expected: snippet(expected),
+    actual: snippet(actual),
+  })
+}
+
+/// A documented match, its text or its range in the shape of `findNodes`.
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+enum Expected {
+  Text(String),
+  Range([usize; 4]),
+}
+
+/// An example of a rule catalog page, the rule as YAML and the code it is shown on.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CatalogExample {
+  yaml: String,
+  code: String,
+  expected_matches: Option<Vec<Expected>>,
+}
+
+#[derive(Clone, Serialize)]
+struct Found {
+  range: [usize; 4],
+  text: String,
+}
+
+impl Found {
+  fn is(&self, expected: &Expected) -> bool {
+    match expected {
+      Expected::Text(text) => self.text == *text,
+      Expected::Range(range) => self.range == *range,
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct CatalogReport {
+  passed: bool,
+  matches: Vec<Found>,
+  missing: Vec<Expected>,
+  unexpected: Vec<Found>,
+  /// the code with the rule's `fix` applied
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fixed: Option<String>,
+}
+
+/// Check a rule catalog example `{ yaml, code, expectedMatches? }` against the engine.
+/// `expectedMatches` lists the documented matches by text or by range, in any order,
+/// and without it the example passes if the rule matches at all.
+/// Returns `{ passed, matches, missing, unexpected, fixed? }` with `matches` as `[{ range, text }]`
+/// and `fixed` the code after the rule's `fix`. A rule that does not compile throws like `findNodes`.
+#[wasm_bindgen(js_name = runCatalogExample)]
+pub fn run_catalog_example(example: JsValue) -> Result<JsValue, JsValue> {
+  global().run_catalog_example(example)
+}
+
+impl Context {
+  pub fn run_catalog_example(&self, example: JsValue) -> Result<JsValue, JsValue> {
+    let example: CatalogExample =
+      serde_wasm_bindgen::from_value(example).map_err(|e| tag(Code::InvalidArgument, e))?;
+    utils::enter("runCatalogExample", example.code.len());
+    let config: serde_json::Value = parse_yaml(&example.yaml, Code::InvalidConfig)?;
+    let rule = self.compile_config(serialize::to_json_value(&config)?)?;
+    let root = parse_root(&example.code, &rule.lang)?;
+    let matches: Vec<_> = root
+      .root()
+      .find_all(&*rule)
+      .map(|n| Found {
+        range: match_range(&n),
+        text: n.text().into_owned(),
+      })
+      .collect();
+    let (missing, unexpected) = match &example.expected_matches {
+      Some(expected) => {
+        let missing = expected
+          .iter()
+          .filter(|e| !matches.iter().any(|m| m.is(e)))
+          .cloned()
+          .collect();
+        let unexpected = matches
+          .iter()
+          .filter(|m| !expected.iter().any(|e| m.is(e)))
+          .cloned()
+          .collect();
+        (missing, unexpected)
+      }
+      None => (vec![], vec![]),
+    };
+    let passed = match &example.expected_matches {
+      Some(_) => missing.is_empty() && unexpected.is_empty(),
+      None => !matches.is_empty(),
+    };
+    let fixed = match &rule.fixer {
+      Some(_) => Some(fix_in_root(&root, &rule)?),
+      None => None,
+    };
+    let ret = CatalogReport {
+      passed,
+      matches,
+      missing,
+      unexpected,
+      fixed,
+    };
+    Ok(serialize::to_value(&ret)?)
+  }
+}
@@ -0,0 +1,369 @@
+use crate::context::{global, Context};
+use crate::error::{error, tag, Code};
+use crate::rule::{constraint_matchers, rule_key, split_relation, CompiledRule};
+use crate::shape;
+use crate::types::Config;
+use crate::utils;
+use crate::validate::check_compilable;
+use crate::{match_range, parse_root, serialize};
+
+use ast_grep_config::SerializableMetaVarMatcher;
+use ast_grep_config::{deserialize_rule, PatternStyle, Rule, SerializableRule};
+use ast_grep_core::meta_var::MetaVarEnv;
+use ast_grep_core::{AstGrep, Matcher, Node};
+use serde::Serialize;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+use std::collections::HashMap;
+
+/// A rule split into its sub-rules, each compiled on its own so it can be matched alone.
+pub struct TraceRule {
+  label: &'static str,
+  matcher: Rule<ts::Language>,
+  relational: bool,
+  /// the pattern or kind of a leaf rule, for explanations
+  detail: String,
+  immediate: bool,
+  /// sub-rules of `all`, `any` and `not` are tried on the same node,
+  /// the sub-rule of a relational rule on the related node
+  children: Vec<TraceRule>,
+}
+
+impl TraceRule {
+  pub fn try_new(rule: &SerializableRule, lang: &ts::Language) -> Result<Self, JsValue> {
+    use SerializableRule as S;
+    let sub = |rule: &SerializableRule| TraceRule::try_new(rule, lang);
+    let mut immediate = false;
+    let children = match rule {
+      S::All(rules) | S::Any(rules) => rules.iter().map(sub).collect::<Result<_, _>>()?,
+      S::Not(rule) => vec![sub(rule)?],
+      S::Inside(r) | S::Has(r) | S::Precedes(r) | S::Follows(r) => {
+        let relation = split_relation(r)?;
+        immediate = relation.immediate;
+        vec![sub(&relation.rule)?]
+      }
+      S::Pattern(_) | S::Kind(_) => vec![],
+    };
+    let label = rule_key(rule);
+    let detail = match rule {
+      S::Pattern(PatternStyle::Str(pattern)) => pattern.clone(),
+      S::Pattern(PatternStyle::Contextual { context, .. }) => context.clone(),
+      S::Kind(kind) => kind.clone(),
+      _ => String::new(),
+    };
+    let matcher = deserialize_rule(rule.clone(), lang.clone())
+      .map_err(|e| error(Code::InvalidRule, &e.to_string()))?;
+    let relational = matches!(
+      rule,
+      S::Inside(_) | S::Has(_) | S::Precedes(_) | S::Follows(_)
+    );
+    Ok(Self {
+      label,
+      matcher,
+      relational,
+      detail,
+      immediate,
+      children,
+    })
+  }
+
+  fn trace(&self, node: &Node<ts::Language>) -> Trace {
+    let mut env = self.matcher.get_meta_var_env();
+    let found = self.matcher.match_node_with_env(node.clone(), &mut env);
+    let (related, children) = match &found {
+      // a relational rule returns the node its sub-rule matched, trace the sub-rule there
+      Some(related) if self.relational => (
+        Some(match_range(related)),
+        self.children.iter().map(|c| c.trace(related)).collect(),
+      ),
+      None if self.relational => (None, vec![]),
+      _ => (None, self.children.iter().map(|c| c.trace(node)).collect()),
+    };
+    Trace {
+      rule: self.label,
+      matched: found.is_some(),
+      related,
+      children,
+    }
+  }
+}
+
+/// How each sub-rule fared on a match, attached as `traces` to results of configs with `trace: true`.
+/// Sub-rules are matched one by one, so metavariables shared between them are not required to agree.
+#[derive(Serialize)]
+struct Trace {
+  rule: &'static str,
+  matched: bool,
+  /// range of the node a relational rule related the match to
+  #[serde(skip_serializing_if = "Option::is_none")]
+  related: Option<[usize; 4]>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  children: Vec<Trace>,
+}
+
+/// Set `traces` on `ret` for the first `count` matches of `rule`, in the order of the matches,
+/// or `candidates` if there are no matches.
+pub fn attach_traces(
+  ret: &JsValue,
+  root: &AstGrep<ts::Language>,
+  rule: &CompiledRule,
+  count: usize,
+) -> Result<(), JsValue> {
+  let trace = match &rule.trace {
+    Some(trace) => trace,
+    None => return Ok(()),
+  };
+  if count == 0 {
+    let mut candidates = Candidates::new(trace);
+    for node in root.root().dfs() {
+      trace.tally(&node, &mut candidates);
+    }
+    let candidates = serialize::to_value(&candidates)?;
+    js_sys::Reflect::set(ret, &"candidates".into(), &candidates)?;
+    return Ok(());
+  }
+  let traces: Vec<_> = root
+    .root()
+    .dfs()
+    .filter(|node| rule.match_node(node.clone()).is_some())
+    .take(count)
+    .map(|node| trace.trace(&node))
+    .collect();
+  js_sys::Reflect::set(ret, &"traces".into(), &serialize::to_value(&traces)?)?;
+  Ok(())
+}
+
+/// How many nodes of the tree each sub-rule matches on its own, to find the over-restrictive one
+/// of a rule without matches.
+#[derive(Serialize)]
+struct Candidates {
+  rule: &'static str,
+  count: usize,
+  /// for sub-rules of `all`, the nodes matching this and every earlier sibling,
+  /// the first zero is where the matches run out
+  #[serde(skip_serializing_if = "Option::is_none")]
+  remaining: Option<usize>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  children: Vec<Candidates>,
+}
+
+impl Candidates {
+  fn new(rule: &TraceRule) -> Self {
+    let remaining = (rule.label == "all").then_some(0);
+    let children = rule
+      .children
+      .iter()
+      .map(|child| Candidates {
+        remaining,
+        ..Candidates::new(child)
+      })
+      .collect();
+    Self {
+      rule: rule.label,
+      count: 0,
+      remaining: None,
+      children,
+    }
+  }
+}
+
+impl TraceRule {
+  /// Count `node` for this rule and every sub-rule matching it, returns if this rule matched.
+  fn tally(&self, node: &Node<ts::Language>, candidates: &mut Candidates) -> bool {
+    // sub-rules of relational rules are counted on every node too, not only related ones
+    let mut all_so_far = true;
+    for (child, counts) in self.children.iter().zip(&mut candidates.children) {
+      all_so_far &= child.tally(node, counts);
+      if let Some(remaining) = &mut counts.remaining {
+        *remaining += usize::from(all_so_far);
+      }
+    }
+    let matched = self.matcher.match_node(node.clone()).is_some();
+    candidates.count += usize::from(matched);
+    matched
+  }
+}
+
+/// The first sub-rule, in config order, that rejects a node.
+#[derive(Serialize)]
+struct Failure {
+  /// where the sub-rule is in the config, e.g. `rule.all[1].kind`
+  path: String,
+  rule: &'static str,
+  message: String,
+  /// why each alternative of a failed `any` failed
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  causes: Vec<Failure>,
+}
+
+#[derive(Serialize)]
+struct Explanation {
+  matched: bool,
+  /// `{ kind, range }` of the node the rule was evaluated on
+  node: NodeRef,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  failure: Option<Failure>,
+}
+
+#[derive(Serialize)]
+struct NodeRef {
+  kind: String,
+  range: [usize; 4],
+}
+
+impl TraceRule {
+  fn explain(&self, node: &Node<ts::Language>, path: String) -> Option<Failure> {
+    if self.matcher.match_node(node.clone()).is_some() {
+      return None;
+    }
+    let location = match (self.label, self.immediate) {
+      ("inside", true) => "the parent",
+      ("inside", false) => "no ancestor",
+      ("has", true) => "no child",
+      ("has", false) => "no descendant",
+      ("precedes", true) => "the next sibling",
+      ("precedes", false) => "no later sibling",
+      ("follows", true) => "the previous sibling",
+      ("follows", false) => "no earlier sibling",
+      _ => "",
+    };
+    let mut causes = vec![];
+    let message = match self.label {
+      "all" => {
+        let failed = self
+          .children
+          .iter()
+          .enumerate()
+          .find_map(|(i, child)| child.explain(node, format!("{}.all[{}]", path, i)));
+        match failed {
+          Some(failure) => return Some(failure),
+          None => "sub-rules match one by one but bind metavariables differently".into(),
+        }
+      }
+      "any" => {
+        causes = self
+          .children
+          .iter()
+          .enumerate()
+          .filter_map(|(i, child)| child.explain(node, format!("{}.any[{}]", path, i)))
+          .collect();
+        format!("none of the {} sub-rules matched", self.children.len())
+      }
+      "not" => "the sub-rule matched, which `not` rejects".into(),
+      "pattern" => format!(
+        "`{}` does not match the pattern `{}`",
+        node.text(),
+        self.detail
+      ),
+      "kind" => format!("expected kind `{}`, got `{}`", self.detail, node.kind()),
+      _ if self.immediate => format!("{} does not match the `{}` rule", location, self.label),
+      _ => format!("{} matches the `{}` rule", location, self.label),
+    };
+    // relational paths name the relation, the others are named by their key
+    let path = format!("{}.{}", path, self.label);
+    Some(Failure {
+      path,
+      rule: self.label,
+      message,
+      causes,
+    })
+  }
+}
+
+/// The smallest named node whose range holds `position`, `[row, column]` like match positions.
+fn node_at<'r>(
+  root: &'r AstGrep<ts::Language>,
+  position: (usize, usize),
+) -> Node<'r, ts::Language> {
+  let mut node = root.root();
+  loop {
+    let inside = |n: &Node<ts::Language>| n.start_pos() <= position && position < n.end_pos();
+    let child = node.children().find(|c| inside(c) && c.is_named());
+    match child {
+      Some(child) => node = child,
+      None => return node,
+    }
+  }
+}
+
+/// Evaluate `config` on the node at `position` (`[row, column]`, zero based)
+/// and report the first sub-rule that rejects it.
+/// Returns `{ matched, node: { kind, range }, failure?: { path, rule, message, causes? } }`
+/// where `path` locates the sub-rule in the config like in `validateRule`.
+#[wasm_bindgen(js_name = explainNoMatch)]
+pub fn explain_no_match(
+  src: String,
+  config: Config,
+  position: JsValue,
+) -> Result<JsValue, JsValue> {
+  global().explain_no_match(src, config.into(), position)
+}
+
+impl Context {
+  pub fn explain_no_match(
+    &self,
+    src: String,
+    config: JsValue,
+    position: JsValue,
+  ) -> Result<JsValue, JsValue> {
+    utils::enter("explainNoMatch", src.len());
+    let position: (usize, usize) =
+      serde_wasm_bindgen::from_value(position).map_err(|e| tag(Code::InvalidArgument, e))?;
+    let config = shape::read_config(config)?;
+    let lang = self.get_language(config.language.as_deref())?;
+    let regexes = check_compilable(&config, &lang)?;
+    let rule = TraceRule::try_new(&config.rule, &lang)?;
+    let root = parse_root(&src, &lang)?;
+    let node = node_at(&root, position);
+    let failure = match rule.explain(&node, "rule".into()) {
+      Some(failure) => Some(failure),
+      None => explain_constraints(&rule, &node, config.constraints, &regexes, &lang)?,
+    };
+    let explanation = Explanation {
+      matched: failure.is_none(),
+      node: NodeRef {
+        kind: node.kind().into(),
+        range: match_range(&node),
+      },
+      failure,
+    };
+    Ok(serialize::to_value(&explanation)?)
+  }
+}
+
+/// The first constraint the metavariables of a match fail, each constraint checked alone.
+fn explain_constraints(
+  rule: &TraceRule,
+  node: &Node<ts::Language>,
+  constraints: Option<HashMap<String, SerializableMetaVarMatcher>>,
+  regexes: &HashMap<String, regex::Regex>,
+  lang: &ts::Language,
+) -> Result<Option<Failure>, JsValue> {
+  for (key, matcher) in constraints.into_iter().flatten() {
+    let matchers = HashMap::from([(key.clone(), matcher)]);
+    let matchers = constraint_matchers(matchers, regexes, lang)?;
+    let mut env = MetaVarEnv::from_matchers(matchers);
+    if rule
+      .matcher
+      .match_node_with_env(node.clone(), &mut env)
+      .is_none()
+    {
+      continue;
+    }
+    if !env.match_constraints() {
+      let text = env.get_match(&key).map(|n| n.text().to_string());
+      let message = match text {
+        Some(text) => format!("`${}` is `{}`, which fails the constraint", key, text),
+        None => format!("`${}` fails the constraint", key),
+      };
+      return Ok(Some(Failure {
+        path: format!("constraints.{}", key),
+        rule: "constraints",
+        message,
+        causes: vec![],
+      }));
+    }
+  }
+  Ok(None)
+}
@@ -0,0 +1,463 @@
+//! TypeScript types of the shapes passed across the boundary as plain JS values.
+//! wasm-bindgen only knows them as `any`, the extern types below name them in the `.d.ts`.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &'static str = r#"
+export type Rule =
+  | { all: Rule[] }
+  | { any: Rule[] }
+  | { not: Rule }
+  | { inside: Relation }
+  | { has: Relation }
+  | { precedes: Relation }
+  | { follows: Relation }
+  | { pattern: string | { context: string; selector: string } }
+  | { kind: string };
+
+/** a rule matched on a related node, `until` stops the search and `immediate` limits it to the next one */
+export type Relation = Rule & { until?: Rule; immediate?: boolean };
+
+export type Constraint = { regex: string } | { pattern: string } | { kind: string };
+
+export interface WASMConfig {
+  /** case insensitive name of a registered language, defaults to the one set by `setupParser` */
+  language?: string;
+  rule: Rule;
+  fix?: string;
+  /** keyed by metavariable name without `$` */
+  constraints?: Record<string, Constraint>;
+  timeoutMs?: number;
+  metrics?: boolean;
+  trace?: boolean;
+  coverage?: boolean;
+  /** reported by `lintDiagnostics` */
+  id?: string;
+  message?: string;
+  severity?: "hint" | "info" | "warning" | "error";
+  /** line breaks of the output of `fixErrors`, `auto` follows the first one of the source */
+  lineEndings?: "preserve" | "auto" | "lf" | "crlf";
+}
+
+/** `[startRow, startColumn, endRow, endColumn]` */
+export type Range = [number, number, number, number];
+
+export interface Metrics {
+  compileMs: number;
+  parseMs: number;
+  matchMs: number;
+  nodesVisited: number;
+  /** per config of a scan with many, in order */
+  rules?: { matchMs: number; nodesVisited: number }[];
+}
+
+export interface Trace {
+  rule: string;
+  matched: boolean;
+  related?: Range;
+  children?: Trace[];
+}
+
+export interface Candidates {
+  rule: string;
+  count: number;
+  remaining?: number;
+  children?: Candidates[];
+}
+
+/** set on result arrays by the config options of the same names */
+export interface ResultInfo {
+  timedOut?: true;
+  metrics?: Metrics;
+  traces?: Trace[];
+  candidates?: Candidates;
+  /** indices of the configs without matches, with `coverage: true` */
+  unmatched?: number[];
+  /** only for `Uint8Array` sources with bytes that are no UTF-8 */
+  invalidUtf8?: InvalidSequence[];
+}
+
+/** bytes of a `Uint8Array` source replaced by U+FFFD, offsets are into the bytes */
+export interface InvalidSequence {
+  byteOffset: number;
+  length: number;
+}
+
+export type Matches = Range[] & ResultInfo;
+
+/** a metavariable of `getMetaVariables`, `name` is without `$` */
+export interface MetaVariableHole {
+  name: string;
+  multi: boolean;
+  roles: ("pattern" | "constraint" | "fix")[];
+  paths: string[];
+}
+
+/** a match of `findByPatterns`, `pattern` is the index of the pattern */
+export interface PatternMatch {
+  pattern: number;
+  range: Range;
+}
+
+/** a node of `findByKind` */
+export interface KindMatch {
+  range: Range;
+  text: string;
+}
+
+/** an identifier of `findIdentifier`, `kind` tells e.g. a property from a type name */
+export interface IdentifierMatch {
+  range: Range;
+  kind: string;
+}
+
+/** the result of `getCompletions`, like a CodeMirror `CompletionResult` */
+export interface Completions {
+  from: number;
+  to: number;
+  options: {
+    label: string;
+    type: "keyword" | "type" | "property" | "enum";
+    apply?: string;
+  }[];
+}
+
+/** the result of `getNodeInfo` */
+export interface NodeInfo {
+  kind: string;
+  field?: string | null;
+  isNamed: boolean;
+  range: Range;
+  childCount: number;
+  isError: boolean;
+  isMissing: boolean;
+  /** markdown */
+  hover: string;
+}
+
+/** matches of `groupByCapture` capturing the same `text` */
+export interface CaptureGroup {
+  text: string;
+  count: number;
+  ranges: Range[];
+}
+
+/** copies of a subtree found by `findDuplicates` */
+export interface DuplicateGroup {
+  nodes: number;
+  kind: string;
+  ranges: Range[];
+}
+
+/** a match of `findSimilar`, `score` is 1 for an exact match */
+export interface SimilarMatch {
+  range: Range;
+  text: string;
+  distance: number;
+  score: number;
+}
+
+/** a symbol found by `extractTags` */
+export interface Tag {
+  name: string;
+  role: "definition" | "reference";
+  /** e.g. `function` of `@definition.function` */
+  kind: string;
+  range: Range;
+  nameRange: Range;
+}
+
+/** the result of `ruleForNode` */
+export interface RuleForNode {
+  rule: Rule;
+  matches: number;
+  unique: boolean;
+}
+
+export type ScanResult = Range[][] & ResultInfo;
+
+export type ScanManyResult = { name: string; matches: ScanResult }[] & ResultInfo;
+
+export type WorkspaceResult = {
+  name: string;
+  /** the registered name of the language the file was parsed with */
+  language: string | null;
+  matches: ScanResult;
+}[] & ResultInfo;
+
+export interface Diagnostic {
+  severity: "error" | "warning";
+  message: string;
+  /** e.g. `rule.all[1].kind` */
+  path: string;
+  /** zero based, only for YAML input */
+  span?: { line: number; column: number };
+  code?: ErrorCode;
+  /** the line of `span` with a caret under it, only for YAML syntax errors */
+  frame?: string;
+}
+
+/** a node changed by `SgRoot.applyEdit`, offsets are JS string indices of the new source */
+export interface ChangedRange {
+  range: Range;
+  startOffset: number;
+  endOffset: number;
+}
+
+/** the result of `SgRoot.watchRules` and `SgRoot.applyEditAndRescan` */
+export interface LiveDiff {
+  changed: ChangedRange[];
+  added: { rule: number; range: Range }[];
+  removed: { rule: number; range: Range }[];
+}
+
+export interface DumpNode {
+  /** pre-order index of the node, for `ruleForNode` and `getNodeInfo` */
+  id: number;
+  /** `kindId` instead with the serialize option `kindIds`, see `getKindTable` */
+  kind?: string;
+  kindId?: number;
+  start: [number, number];
+  end: [number, number];
+  is_named: boolean;
+  children: DumpNode[];
+}
+
+/** positions of `SgNode.range`, `index` is a JS string index into the source */
+export interface NodeRange {
+  start: { line: number; column: number; index: number };
+  end: { line: number; column: number; index: number };
+}
+
+/** of `SgRoot.offsetToPosition`, zero based with the column in JS string indices */
+export interface TextPosition {
+  line: number;
+  column: number;
+}
+
+export interface NodeEdit {
+  startPos: number;
+  endPos: number;
+  insertedText: string;
+}
+
+/** a `Diagnostic` of `@codemirror/lint` */
+/** a config of `checkRuleset`, `ok` if the grammar has every kind it names */
+export interface RuleReport {
+  id: string | null;
+  ok: boolean;
+  diagnostics: Diagnostic[];
+}
+
+export interface CodeMirrorDiagnostic {
+  from: number;
+  to: number;
+  severity: "hint" | "info" | "warning" | "error";
+  message: string;
+  source?: string;
+  actions: { name: string; apply: (view: any, from: number, to: number) => void }[];
+}
+
+/** a diagnostic of `quickFixes`, with edits instead of CodeMirror actions */
+export interface QuickFixDiagnostic {
+  from: number;
+  to: number;
+  severity: "hint" | "info" | "warning" | "error";
+  message: string;
+  source?: string;
+  actions: { title: string; edits: { from: number; to: number; insert: string }[] }[];
+}
+
+/** an `IMarkerData` of Monaco, `severity` is a `MarkerSeverity` */
+export interface MonacoMarker {
+  severity: 1 | 2 | 4 | 8;
+  message: string;
+  source?: string;
+  startLineNumber: number;
+  startColumn: number;
+  endLineNumber: number;
+  endColumn: number;
+}
+
+/** a result of ESLint's JSON formatter */
+export interface EslintResult {
+  filePath: string;
+  messages: {
+    ruleId: string | null;
+    severity: 1 | 2;
+    message: string;
+    line: number;
+    column: number;
+    endLine: number;
+    endColumn: number;
+    fix?: { range: [number, number]; text: string };
+  }[];
+  suppressedMessages: [];
+  errorCount: number;
+  fatalErrorCount: number;
+  warningCount: number;
+  fixableErrorCount: number;
+  fixableWarningCount: number;
+}
+
+export type ErrorCode =
+  | "NO_LANGUAGE"
+  | "LANGUAGE_MISMATCH"
+  | "MIXED_LANGUAGES"
+  | "INVALID_CONFIG"
+  | "INVALID_RULE"
+  | "INVALID_CONSTRAINT"
+  | "MISSING_FIX"
+  | "INVALID_ARGUMENT"
+  | "INVALID_EDIT"
+  | "PARSER_INIT"
+  | "GRAMMAR_LOAD"
+  | "CANCELLED"
+  | "LIMIT_EXCEEDED"
+  | "REGEX_TOO_EXPENSIVE"
+  | "PANIC";
+
+/** what every function throws, narrow it with `code` */
+export interface SgError extends Error {
+  code: ErrorCode;
+  /** the values the message is made of, e.g. `{ path, kind }` for an unknown kind, to localize it */
+  params: Record<string, unknown>;
+  /** INVALID_CONFIG for a config of the wrong shape */
+  path?: string;
+  received?: string;
+  /** LANGUAGE_MISMATCH */
+  requested?: string;
+  active?: string | null;
+  loaded?: string[];
+  /** LIMIT_EXCEEDED */
+  limit?: "maxMatches" | "maxSourceBytes" | "maxTreeDepth";
+  max?: number;
+  actual?: number;
+  /** PANIC */
+  operation?: string;
+  inputBytes?: number;
+  /** YAML syntax errors, zero based, `frame` is the line with a caret under the column */
+  line?: number;
+  column?: number;
+  frame?: string;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+  #[wasm_bindgen(typescript_type = "WASMConfig")]
+  pub type Config;
+
+  /// a config object or its YAML text
+  #[wasm_bindgen(typescript_type = "WASMConfig | string")]
+  pub type ConfigSource;
+
+  #[wasm_bindgen(extends = js_sys::Array, typescript_type = "(WASMConfig | string)[]")]
+  pub type ConfigSourceArray;
+
+  #[wasm_bindgen(typescript_type = "RuleReport[]")]
+  pub type RuleReports;
+
+  #[wasm_bindgen(extends = js_sys::Array, typescript_type = "WASMConfig[]")]
+  pub type ConfigArray;
+
+  /// a source text or the raw bytes of a file
+  #[wasm_bindgen(typescript_type = "string | Uint8Array")]
+  pub type SourceInput;
+
+  #[wasm_bindgen(typescript_type = "Matches")]
+  pub type Matches;
+
+  #[wasm_bindgen(typescript_type = "MetaVariableHole[]")]
+  pub type MetaVariableHoles;
+
+  #[wasm_bindgen(typescript_type = "string[]")]
+  pub type StringArray;
+
+  #[wasm_bindgen(typescript_type = "PatternMatch[]")]
+  pub type PatternMatches;
+
+  #[wasm_bindgen(typescript_type = "KindMatch[]")]
+  pub type KindMatches;
+
+  /// languages with the globs of the file names to parse with them
+  #[wasm_bindgen(typescript_type = "Record<string, string[]>")]
+  pub type LanguageGlobs;
+
+  #[wasm_bindgen(typescript_type = "LiveDiff")]
+  pub type LiveDiff;
+
+  #[wasm_bindgen(typescript_type = "IdentifierMatch[]")]
+  pub type IdentifierMatches;
+
+  #[wasm_bindgen(typescript_type = "Completions")]
+  pub type Completions;
+
+  #[wasm_bindgen(typescript_type = "NodeInfo")]
+  pub type NodeInfo;
+
+  #[wasm_bindgen(typescript_type = "RuleForNode")]
+  pub type RuleForNode;
+
+  #[wasm_bindgen(typescript_type = "SimilarMatch[]")]
+  pub type SimilarMatches;
+
+  #[wasm_bindgen(typescript_type = "{ from: number; to: number; insert: string } | null")]
+  pub type SuppressionEdit;
+
+  #[wasm_bindgen(typescript_type = "Tag[]")]
+  pub type Tags;
+
+  #[wasm_bindgen(typescript_type = "ScanResult")]
+  pub type ScanResult;
+
+  #[wasm_bindgen(typescript_type = "ScanManyResult")]
+  pub type ScanManyResult;
+
+  #[wasm_bindgen(typescript_type = "WorkspaceResult")]
+  pub type WorkspaceResult;
+
+  #[wasm_bindgen(typescript_type = "Diagnostic[]")]
+  pub type Diagnostics;
+
+  #[wasm_bindgen(typescript_type = "DuplicateGroup[]")]
+  pub type DuplicateGroups;
+
+  #[wasm_bindgen(typescript_type = "DumpNode")]
+  pub type DumpNode;
+
+  #[wasm_bindgen(typescript_type = "CaptureGroup[]")]
+  pub type CaptureGroups;
+
+  #[wasm_bindgen(typescript_type = "ChangedRange[]")]
+  pub type ChangedRanges;
+
+  #[wasm_bindgen(typescript_type = "CodeMirrorDiagnostic[]")]
+  pub type CodeMirrorDiagnostics;
+
+  #[wasm_bindgen(typescript_type = "QuickFixDiagnostic[]")]
+  pub type QuickFixDiagnostics;
+
+  #[wasm_bindgen(typescript_type = "MonacoMarker[]")]
+  pub type MonacoMarkers;
+
+  #[wasm_bindgen(typescript_type = "EslintResult[]")]
+  pub type EslintResults;
+
+  /// a pattern or a config
+  #[wasm_bindgen(typescript_type = "string | WASMConfig")]
+  pub type NodeMatcher;
+
+  #[wasm_bindgen(typescript_type = "Range")]
+  pub type MatchRange;
+
+  #[wasm_bindgen(typescript_type = "NodeRange")]
+  pub type NodeRange;
+
+  #[wasm_bindgen(typescript_type = "TextPosition")]
+  pub type TextPosition;
+
+  #[wasm_bindgen(typescript_type = "NodeEdit")]
+  pub type NodeEdit;
+}
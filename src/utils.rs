@@ -1,10 +1,63 @@
-#[cfg(feature = "console_error_panic_hook")]
-pub fn set_panic_hook() {
-  // When the `console_error_panic_hook` feature is enabled, we can call the
-  // `set_panic_hook` function at least once during initialization, and then
-  // we will get better error messages if our code ever panics.
-  //
-  // For more details see
-  // https://github.com/rustwasm/console_error_panic_hook#readme
-  console_error_panic_hook::set_once();
+use crate::error::{error, set_params, Code};
+use wasm_bindgen::prelude::*;
+
+use std::cell::Cell;
+
+thread_local! {
+  // the running call, for panic reports
+  static OPERATION: Cell<(&'static str, usize)> = const { Cell::new(("", 0)) };
+}
+
+/// Record the call now running and the size of its input, e.g. `("findNodes", src.len())`.
+pub fn enter(operation: &'static str, input_bytes: usize) {
+  OPERATION.with(|op| op.set((operation, input_bytes)));
+}
+
+/// Update the input size of the running call, for calls that learn it while running.
+pub fn input(input_bytes: usize) {
+  OPERATION.with(|op| op.set((op.get().0, input_bytes)));
+}
+
+const STACK_LINES: usize = 12;
+
+/// Throw every panic to the calling JS as an `Error` with code `PANIC`,
+/// `operation`, `inputBytes` and the first lines of the `stack`, instead of "unreachable executed".
+/// Throwing skips destructors, so the module may be left inconsistent and should be reloaded.
+fn panic_to_error(info: &dyn std::fmt::Display) -> ! {
+  let (operation, input_bytes) = OPERATION.with(Cell::get);
+  let message = format!(
+    "ast-grep panicked in {} with {} bytes of input: {}",
+    if operation.is_empty() {
+      "setup"
+    } else {
+      operation
+    },
+    input_bytes,
+    info
+  );
+  let error = error(Code::Panic, &message);
+  let stack = js_sys::Reflect::get(&js_sys::Error::new(""), &"stack".into())
+    .ok()
+    .and_then(|s| s.as_string())
+    .unwrap_or_default();
+  // the first line is the message of the probe error
+  let stack: Vec<_> = stack.lines().skip(1).take(STACK_LINES).collect();
+  let props = [
+    ("operation", JsValue::from(operation)),
+    ("inputBytes", JsValue::from(input_bytes as f64)),
+    ("stack", JsValue::from(stack.join("\n"))),
+  ];
+  set_params(&error, &props);
+  wasm_bindgen::throw_val(error);
+}
+
+#[wasm_bindgen(start)]
+pub fn install_panic_hook() {
+  std::panic::set_hook(Box::new(|info| {
+    // better messages in the console too, see
+    // https://github.com/rustwasm/console_error_panic_hook#readme
+    #[cfg(feature = "console_error_panic_hook")]
+    console_error_panic_hook::hook(info);
+    panic_to_error(info)
+  }));
 }
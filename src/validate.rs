@@ -0,0 +1,725 @@
+use crate::context::{global, Context};
+use crate::error::{error_with, source_frame, tag, Code};
+use crate::rule::{rule_key, walk_rule};
+use crate::serialize;
+use crate::shape;
+use crate::types::{ConfigSource, ConfigSourceArray, Diagnostics, RuleReports};
+use crate::utils;
+use crate::WASMConfig;
+
+use ast_grep_config::{
+  deserialize_rule, PatternStyle, SerializableMetaVarMatcher, SerializableRule,
+};
+use ast_grep_core::language::Language;
+use ast_grep_core::{KindMatcher, MetaVariable};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::collections::{BTreeMap, HashMap};
+
+// keys of `WASMConfig`
+pub const KNOWN_KEYS: [&str; 12] = [
+  "language",
+  "rule",
+  "fix",
+  "constraints",
+  "timeoutMs",
+  "metrics",
+  "trace",
+  "coverage",
+  "id",
+  "message",
+  "severity",
+  "lineEndings",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+  Error,
+  Warning,
+}
+
+/// zero based like the positions of matches
+#[derive(Serialize)]
+struct Span {
+  line: usize,
+  column: usize,
+}
+
+#[derive(Serialize)]
+pub struct Diagnostic {
+  severity: Severity,
+  message: String,
+  /// where the problem is in the config, e.g. `rule.all[1].kind`
+  path: String,
+  /// position in the YAML text, only for YAML input.
+  /// Beyond syntax errors it is found by key, see `locate`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  span: Option<Span>,
+  /// the `code` compiling the config would throw, only set where it is not obvious from `path`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  code: Option<Code>,
+  /// the line of `span` with a caret under its column, only for YAML syntax errors
+  #[serde(skip_serializing_if = "Option::is_none")]
+  frame: Option<String>,
+  /// an unknown kind, a param of the error compiling throws
+  #[serde(skip)]
+  kind: Option<String>,
+}
+
+/// Cap on the compiled size of a constraint regex.
+/// There is no step budget for matching: the `regex` crate never backtracks and matches in
+/// time linear in the text times the program size, which this caps and `limits` caps the
+/// source of. The cost left is in compiling e.g. `\w{1000}{1000}`, which would freeze the worker.
+const REGEX_SIZE_LIMIT: usize = 1 << 21;
+
+fn build_regex(regex: &str) -> Result<regex::Regex, regex::Error> {
+  regex::RegexBuilder::new(regex)
+    .size_limit(REGEX_SIZE_LIMIT)
+    .build()
+}
+
+struct Validator<'a> {
+  yaml: Option<&'a str>,
+  diagnostics: Vec<Diagnostic>,
+  /// constraint regexes compiled by `check_constraint`, by metavariable
+  regexes: HashMap<String, regex::Regex>,
+}
+
+/// Check a config, either a YAML string or an object, without running it.
+/// Returns `[{ severity, message, path, span? }]`, empty if the config is fine.
+/// `severity` is `"error"` for configs that would fail to compile
+/// and `"warning"` for ones that compile but likely not as intended, e.g. unknown keys.
+#[wasm_bindgen(js_name = validateRule)]
+pub fn validate_rule(config: ConfigSource) -> Result<Diagnostics, JsValue> {
+  global()
+    .validate_rule(config.into())
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn validate_rule(&self, config: JsValue) -> Result<JsValue, JsValue> {
+    utils::enter("validateRule", 0);
+    let yaml = config.as_string();
+    let mut validator = Validator::new(yaml.as_deref());
+    if let Some(config) = validator.parse(&config) {
+      match self.get_language(config.language.as_deref()) {
+        Ok(lang) => validator.check_config(config, &lang),
+        Err(e) => validator.error("language", error_message(&e)),
+      }
+    }
+    Ok(serialize::to_value(&validator.diagnostics)?)
+  }
+}
+
+/// Flag rules that compile but are likely written by mistake, in the shape of `validateRule`:
+/// patterns of a single metavariable, which match every node,
+/// metavariables captured but used by neither `constraints`, `fix`, `message` nor another pattern,
+/// and `any` or `all` with a single rule.
+/// Only a config that cannot be read is reported as an error, other problems are left to `validateRule`.
+/// Set `metaVariableStyle` of `options` to also flag `$` words of patterns and fixes
+/// that look like metavariables but are matched as text, like `$foo`, `$A1` or `$$X`.
+/// It is opt-in since `$name` is plain code in languages like PHP or Bash.
+#[wasm_bindgen(js_name = lintRule)]
+pub fn lint_rule(config: ConfigSource, options: JsValue) -> Result<Diagnostics, JsValue> {
+  global()
+    .lint_rule(config.into(), options)
+    .map(JsCast::unchecked_into)
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct LintOptions {
+  meta_variable_style: bool,
+}
+
+/// Why the `$` word `word` is no metavariable, `None` if it is one.
+fn meta_var_mistake(word: &str) -> Option<String> {
+  let dollars = word.len() - word.trim_start_matches('$').len();
+  let name = &word[dollars..];
+  if dollars == 2 || dollars > 3 {
+    return Some(format!(
+      "`{}` is matched as text, `$NAME` matches one node and `$$$NAME` any number of them",
+      word
+    ));
+  }
+  if name.chars().all(|c| matches!(c, 'A'..='Z' | '_')) {
+    return None;
+  }
+  let hint = if name.chars().any(|c| c.is_ascii_digit()) {
+    "names have no digits"
+  } else {
+    "names are uppercase"
+  };
+  let fixed: String = name
+    .chars()
+    .filter(|c| !c.is_ascii_digit())
+    .collect::<String>()
+    .to_uppercase();
+  let fixed = if fixed.is_empty() { "_".into() } else { fixed };
+  Some(format!(
+    "`{}` is matched as text, {}: `{}{}`",
+    word,
+    hint,
+    "$".repeat(dollars),
+    fixed
+  ))
+}
+
+/// The words of `pattern` made of `$` and then letters, digits or `_`.
+fn dollar_words(pattern: &str) -> Vec<&str> {
+  let mut ret = vec![];
+  let bytes = pattern.as_bytes();
+  let mut i = 0;
+  while i < bytes.len() {
+    // `$` inside a word, like in `a$b`, is no metavariable either way
+    let starts = bytes[i] == b'$' && (i == 0 || !is_word_byte(bytes[i - 1]));
+    if !starts {
+      i += 1;
+      continue;
+    }
+    let start = i;
+    while i < bytes.len() && bytes[i] == b'$' {
+      i += 1;
+    }
+    let name_start = i;
+    while i < bytes.len() && is_word_byte(bytes[i]) {
+      i += 1;
+    }
+    if i > name_start {
+      ret.push(&pattern[start..i]);
+    }
+  }
+  ret
+}
+
+fn is_word_byte(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || b == b'_'
+}
+
+impl Context {
+  pub fn lint_rule(&self, config: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    utils::enter("lintRule", 0);
+    let options: LintOptions = if options.is_undefined() || options.is_null() {
+      LintOptions::default()
+    } else {
+      serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+    };
+    let yaml = config.as_string();
+    let mut validator = Validator::new(yaml.as_deref());
+    if let Some(config) = validator.parse(&config) {
+      // unknown keys are for validateRule
+      validator.diagnostics.clear();
+      match self.get_language(config.language.as_deref()) {
+        Ok(lang) => validator.lint_config(&config, &lang),
+        Err(e) => validator.error("language", error_message(&e)),
+      }
+      if options.meta_variable_style {
+        validator.lint_meta_var_style(&config);
+      }
+    }
+    Ok(serialize::to_value(&validator.diagnostics)?)
+  }
+}
+
+/// Warnings about a config that compiles, kept by `compileRule` as `warnings` of the handle.
+pub fn load_warnings(config: &WASMConfig, lang: &ts::Language) -> Vec<Diagnostic> {
+  let mut validator = Validator::new(None);
+  validator.check_orphans(config, lang);
+  validator.diagnostics
+}
+
+/// Err with the first problem that would make compiling `config` panic or fail,
+/// the checks of `validateRule` beyond the shape of the config.
+/// Returns the constraint regexes it compiled, to match with instead of compiling them again.
+pub fn check_compilable(
+  config: &WASMConfig,
+  lang: &ts::Language,
+) -> Result<HashMap<String, regex::Regex>, JsValue> {
+  let mut validator = Validator::new(None);
+  validator.check_parts(config, lang);
+  let first = validator
+    .diagnostics
+    .into_iter()
+    .find(|d| matches!(d.severity, Severity::Error));
+  let d = match first {
+    Some(d) => d,
+    None => return Ok(validator.regexes),
+  };
+  let code = match d.code {
+    Some(code) => code,
+    None if d.path.starts_with("constraints") => Code::InvalidConstraint,
+    None => Code::InvalidRule,
+  };
+  let message = format!("{}: {}", d.path, d.message);
+  let mut params = vec![("path", JsValue::from(d.path))];
+  if let Some(kind) = d.kind {
+    params.push(("kind", kind.into()));
+  }
+  Err(error_with(code, &message, &params))
+}
+
+fn find_mistake(value: Value) -> Option<shape::Mistake> {
+  shape::check_config(&value).err()
+}
+
+fn error_message(e: &JsValue) -> String {
+  match e.dyn_ref::<js_sys::Error>() {
+    Some(error) => error.message().into(),
+    None => e.as_string().unwrap_or_else(|| format!("{:?}", e)),
+  }
+}
+
+#[derive(Serialize)]
+struct RuleReport {
+  id: Option<String>,
+  ok: bool,
+  diagnostics: Vec<Diagnostic>,
+}
+
+/// Check that the grammars have every kind the configs name, in rules, selectors and
+/// constraints, e.g. before moving a ruleset to a new grammar version. Returns
+/// `[{ id, ok, diagnostics }]` in config order, `diagnostics` in the shape of `validateRule`
+/// with configs that cannot be read or whose language is not registered reported too.
+/// Rules of this version name no fields, so only kinds are checked.
+#[wasm_bindgen(js_name = checkRuleset)]
+pub fn check_ruleset(configs: ConfigSourceArray) -> Result<RuleReports, JsValue> {
+  global()
+    .check_ruleset(configs.into())
+    .map(JsCast::unchecked_into)
+}
+
+impl Context {
+  pub fn check_ruleset(&self, configs: JsValue) -> Result<JsValue, JsValue> {
+    utils::enter("checkRuleset", 0);
+    let configs: js_sys::Array = configs.unchecked_into();
+    let mut reports = vec![];
+    for config in configs.iter() {
+      let yaml = config.as_string();
+      let mut validator = Validator::new(yaml.as_deref());
+      let mut id = None;
+      if let Some(config) = validator.parse(&config) {
+        // unknown keys are for validateRule
+        validator.diagnostics.clear();
+        id = config.id.clone();
+        match self.get_language(config.language.as_deref()) {
+          Ok(lang) => validator.check_kinds(&config, &lang),
+          Err(e) => validator.error("language", error_message(&e)),
+        }
+      }
+      reports.push(RuleReport {
+        id,
+        ok: validator.diagnostics.is_empty(),
+        diagnostics: validator.diagnostics,
+      });
+    }
+    Ok(serialize::to_value(&reports)?)
+  }
+}
+
+impl<'a> Validator<'a> {
+  fn new(yaml: Option<&'a str>) -> Self {
+    Self {
+      yaml,
+      diagnostics: vec![],
+      regexes: HashMap::new(),
+    }
+  }
+
+  fn push(&mut self, severity: Severity, path: &str, message: String) {
+    let span = self.locate(path);
+    self.diagnostics.push(Diagnostic {
+      severity,
+      message,
+      path: path.into(),
+      span,
+      code: None,
+      frame: None,
+      kind: None,
+    });
+  }
+
+  fn error(&mut self, path: &str, message: String) {
+    self.push(Severity::Error, path, message);
+  }
+
+  fn warning(&mut self, path: &str, message: String) {
+    self.push(Severity::Warning, path, message);
+  }
+
+  /// Best effort position of `path`: the first line, after the lines of its parents, with its key.
+  fn locate(&self, path: &str) -> Option<Span> {
+    let yaml = self.yaml?;
+    let mut span = None;
+    let mut from = 0;
+    for key in path.split('.').filter(|k| !k.is_empty()) {
+      // `all[1]` is found by its key, items of a sequence have no key of their own
+      let needle = format!("{}:", key.split('[').next()?);
+      let (line, column) = yaml
+        .lines()
+        .enumerate()
+        .skip(from)
+        .find_map(|(i, text)| Some((i, text.find(&needle)?)))?;
+      from = line;
+      span = Some(Span { line, column });
+    }
+    span
+  }
+
+  fn parse(&mut self, config: &JsValue) -> Option<WASMConfig> {
+    if let Some(yaml) = self.yaml {
+      return self.parse_yaml(yaml);
+    }
+    let keys = js_sys::Object::keys(config.unchecked_ref());
+    self.check_keys(keys.iter().filter_map(|k| k.as_string()));
+    match serde_wasm_bindgen::from_value(config.clone()) {
+      Ok(config) => Some(config),
+      Err(e) => {
+        let value = serde_wasm_bindgen::from_value(config.clone()).ok();
+        match value.and_then(find_mistake) {
+          Some(mistake) => self.error(&mistake.path, mistake.message()),
+          None => self.error("", e.to_string()),
+        }
+        None
+      }
+    }
+  }
+
+  fn parse_yaml(&mut self, yaml: &str) -> Option<WASMConfig> {
+    // the top level keys first, so unknown ones are reported next to other errors
+    let parsed = ast_grep_config::from_str::<BTreeMap<String, IgnoredAny>>(yaml)
+      .map(|keys| self.check_keys(keys.into_keys()))
+      .and_then(|_| ast_grep_config::from_str(yaml));
+    match parsed {
+      Ok(config) => Some(config),
+      Err(e) => {
+        let value = ast_grep_config::from_str(yaml).ok();
+        match value.and_then(find_mistake) {
+          Some(mistake) => self.error(&mistake.path, mistake.message()),
+          // e.g. a YAML syntax error, the position of serde is all there is
+          None => {
+            let span = e.location().map(|l| Span {
+              line: l.line() - 1,
+              column: l.column() - 1,
+            });
+            let frame = span
+              .as_ref()
+              .and_then(|span| source_frame(yaml, span.line, span.column));
+            self.diagnostics.push(Diagnostic {
+              severity: Severity::Error,
+              message: e.to_string(),
+              path: String::new(),
+              span,
+              code: None,
+              frame,
+              kind: None,
+            });
+          }
+        }
+        None
+      }
+    }
+  }
+
+  fn check_keys(&mut self, keys: impl Iterator<Item = String>) {
+    for key in keys {
+      if !KNOWN_KEYS.contains(&key.as_str()) {
+        let message = format!("unknown key `{}` is ignored", key);
+        self.push(Severity::Warning, &key, message);
+      }
+    }
+  }
+
+  fn errors(&self) -> usize {
+    let errors = self.diagnostics.iter();
+    errors
+      .filter(|d| matches!(d.severity, Severity::Error))
+      .count()
+  }
+
+  fn check_config(&mut self, config: WASMConfig, lang: &ts::Language) {
+    let errors = self.errors();
+    self.check_parts(&config, lang);
+    // compiling panics on the problems above, only compile a config that passed
+    if self.errors() == errors {
+      if let Err(e) = deserialize_rule(config.rule, lang.clone()) {
+        self.error("rule", e.to_string());
+      }
+    }
+  }
+
+  fn check_parts(&mut self, config: &WASMConfig, lang: &ts::Language) {
+    self.check_rule(&config.rule, "rule", lang);
+    if let Some(fix) = &config.fix {
+      self.check_pattern(fix, "fix", lang);
+    }
+    for (key, matcher) in config.constraints.iter().flatten() {
+      self.check_constraint(key, matcher, lang);
+    }
+    self.check_orphans(config, lang);
+  }
+
+  /// Constraints on metavariables no pattern captures, which silently never apply.
+  fn check_orphans(&mut self, config: &WASMConfig, lang: &ts::Language) {
+    let constraints = match &config.constraints {
+      Some(constraints) => constraints,
+      None => return,
+    };
+    let mut captured = vec![];
+    collect_captures(&config.rule, lang, &mut captured);
+    let mut orphans: Vec<_> = constraints
+      .keys()
+      .filter(|key| !captured.contains(key))
+      .collect();
+    // constraints are a HashMap, sort for stable output
+    orphans.sort();
+    for key in orphans {
+      let message = format!(
+        "constraint on `${}` never applies, no pattern of the rule captures it",
+        key
+      );
+      self.warning(&format!("constraints.{}", key), message);
+    }
+  }
+
+  fn check_rule(&mut self, rule: &SerializableRule, path: &str, lang: &ts::Language) {
+    use SerializableRule as S;
+    walk_rule(rule, path, &mut |path, rule| match rule {
+      Ok(S::Pattern(PatternStyle::Str(pattern))) => {
+        self.check_pattern(pattern, &format!("{}.pattern", path), lang);
+      }
+      Ok(S::Pattern(PatternStyle::Contextual { context, selector })) => {
+        let path = format!("{}.pattern", path);
+        self.check_pattern(context, &format!("{}.context", path), lang);
+        self.check_kind(selector, &format!("{}.selector", path), lang);
+        let context = lang.ast_grep(lang.pre_process_pattern(context));
+        if context
+          .root()
+          .find(KindMatcher::new(selector, lang.clone()))
+          .is_none()
+        {
+          let message = format!("the context has no `{}` node to select", selector);
+          self.error(&format!("{}.selector", path), message);
+        }
+      }
+      Ok(S::Kind(kind)) => self.check_kind(kind, &format!("{}.kind", path), lang),
+      Ok(_) => (),
+      Err(e) => self.error(path, e.to_string()),
+    });
+  }
+
+  /// Only the kinds of `check_parts`.
+  fn check_kinds(&mut self, config: &WASMConfig, lang: &ts::Language) {
+    self.check_rule_kinds(&config.rule, "rule", lang);
+    for (key, matcher) in config.constraints.iter().flatten() {
+      if let SerializableMetaVarMatcher::Kind(kind) = matcher {
+        self.check_kind(kind, &format!("constraints.{}.kind", key), lang);
+      }
+    }
+  }
+
+  fn check_rule_kinds(&mut self, rule: &SerializableRule, path: &str, lang: &ts::Language) {
+    use SerializableRule as S;
+    walk_rule(rule, path, &mut |path, rule| match rule {
+      Ok(S::Pattern(PatternStyle::Contextual { selector, .. })) => {
+        self.check_kind(selector, &format!("{}.pattern.selector", path), lang);
+      }
+      Ok(S::Kind(kind)) => self.check_kind(kind, &format!("{}.kind", path), lang),
+      Ok(_) => (),
+      Err(e) => self.error(path, e.to_string()),
+    });
+  }
+
+  fn check_kind(&mut self, kind: &str, path: &str, lang: &ts::Language) {
+    if KindMatcher::new(kind, lang.clone()).is_invalid() {
+      self.error(path, format!("unknown kind `{}`", kind));
+      if let Some(d) = self.diagnostics.last_mut() {
+        d.kind = Some(kind.into());
+      }
+    }
+  }
+
+  fn check_pattern(&mut self, pattern: &str, path: &str, lang: &ts::Language) {
+    let processed = lang.pre_process_pattern(pattern);
+    let roots = lang.ast_grep(&processed).root().children().len();
+    if roots != 1 {
+      let message = format!("pattern must parse to one node, got {}", roots);
+      self.error(path, message);
+    }
+  }
+
+  fn check_constraint(
+    &mut self,
+    key: &str,
+    matcher: &SerializableMetaVarMatcher,
+    lang: &ts::Language,
+  ) {
+    use SerializableMetaVarMatcher as M;
+    let path = format!("constraints.{}", key);
+    match matcher {
+      M::Kind(kind) => self.check_kind(kind, &format!("{}.kind", path), lang),
+      M::Pattern(pattern) => self.check_pattern(pattern, &format!("{}.pattern", path), lang),
+      M::Regex(regex) => match build_regex(regex) {
+        Ok(regex) => {
+          self.regexes.insert(key.into(), regex);
+        }
+        Err(regex::Error::CompiledTooBig(limit)) => {
+          let message = format!("regex too expensive, it compiles to over {} bytes", limit);
+          self.push(Severity::Error, &format!("{}.regex", path), message);
+          if let Some(d) = self.diagnostics.last_mut() {
+            d.code = Some(Code::RegexTooExpensive);
+          }
+        }
+        Err(e) => self.error(&format!("{}.regex", path), format!("invalid regex: {}", e)),
+      },
+    }
+  }
+
+  fn lint_config(&mut self, config: &WASMConfig, lang: &ts::Language) {
+    // metavariables with the pattern path they are first captured at and how often
+    let mut captures: Vec<(String, String, usize)> = vec![];
+    self.lint_rule(&config.rule, "rule", lang, &mut captures);
+    let mut used: Vec<String> = config
+      .constraints
+      .iter()
+      .flatten()
+      .map(|(key, _)| key.clone())
+      .collect();
+    if let Some(fix) = &config.fix {
+      used.extend(meta_vars(fix, lang));
+    }
+    if let Some(message) = &config.message {
+      // messages are prose, which would not parse to metavariable nodes like a fix
+      let names =
+        dollar_words(message)
+          .into_iter()
+          .filter_map(|word| match lang.extract_meta_var(word)? {
+            MetaVariable::Named(name) | MetaVariable::NamedEllipsis(name) => Some(name),
+            _ => None,
+          });
+      used.extend(names);
+    }
+    for (name, path, count) in captures {
+      // a metavariable captured twice must match the same text, which is a use
+      if count == 1 && !used.contains(&name) {
+        let message = format!(
+          "`${}` is captured but never used, `$_` matches without capturing",
+          name
+        );
+        self.warning(&path, message);
+      }
+    }
+  }
+
+  fn lint_meta_var_style(&mut self, config: &WASMConfig) {
+    let mut patterns = vec![];
+    rule_patterns(&config.rule, "rule", &mut patterns);
+    for (key, matcher) in config.constraints.iter().flatten() {
+      if let SerializableMetaVarMatcher::Pattern(pattern) = matcher {
+        patterns.push((format!("constraints.{}.pattern", key), pattern.clone()));
+      }
+    }
+    if let Some(fix) = &config.fix {
+      patterns.push(("fix".into(), fix.clone()));
+    }
+    for (path, pattern) in patterns {
+      for word in dollar_words(&pattern) {
+        if let Some(message) = meta_var_mistake(word) {
+          self.warning(&path, message);
+        }
+      }
+    }
+  }
+
+  fn lint_rule(
+    &mut self,
+    rule: &SerializableRule,
+    path: &str,
+    lang: &ts::Language,
+    captures: &mut Vec<(String, String, usize)>,
+  ) {
+    use SerializableRule as S;
+    let mut capture = |path: String, pattern: &str| {
+      for name in meta_vars(pattern, lang) {
+        match captures.iter_mut().find(|(n, _, _)| *n == name) {
+          Some((_, _, count)) => *count += 1,
+          None => captures.push((name, path.clone(), 1)),
+        }
+      }
+    };
+    // a relation that cannot be read is reported by validateRule
+    walk_rule(rule, path, &mut |path, rule| match rule {
+      Ok(rule @ S::All(rules)) | Ok(rule @ S::Any(rules)) if rules.len() == 1 => {
+        let key = rule_key(rule);
+        let message = format!(
+          "`{}` with a single rule is the same as the rule itself",
+          key
+        );
+        self.warning(&format!("{}.{}", path, key), message);
+      }
+      Ok(S::Pattern(PatternStyle::Str(pattern))) => {
+        let path = format!("{}.pattern", path);
+        let processed = lang.pre_process_pattern(pattern);
+        if lang.extract_meta_var(processed.trim()).is_some() {
+          let message = format!(
+            "pattern `{}` is a single metavariable and matches every node",
+            pattern
+          );
+          self.warning(&path, message);
+        }
+        capture(path, pattern);
+      }
+      Ok(S::Pattern(PatternStyle::Contextual { context, .. })) => {
+        capture(format!("{}.pattern.context", path), context);
+      }
+      _ => (),
+    });
+  }
+}
+
+/// Names of the metavariables captured by `pattern`, in order and with repeats.
+fn meta_vars(pattern: &str, lang: &ts::Language) -> Vec<String> {
+  use ast_grep_core::MetaVariable as M;
+  let root = lang.ast_grep(lang.pre_process_pattern(pattern));
+  let names = root
+    .root()
+    .dfs()
+    .filter(|node| node.is_leaf())
+    .filter_map(|node| match lang.extract_meta_var(&node.text()) {
+      Some(M::Named(name)) | Some(M::NamedEllipsis(name)) => Some(name),
+      _ => None,
+    })
+    .collect();
+  names
+}
+
+/// The patterns of `rule` and its sub-rules with their paths.
+fn rule_patterns(rule: &SerializableRule, path: &str, out: &mut Vec<(String, String)>) {
+  use SerializableRule as S;
+  walk_rule(rule, path, &mut |path, rule| match rule {
+    Ok(S::Pattern(PatternStyle::Str(pattern))) => {
+      out.push((format!("{}.pattern", path), pattern.clone()));
+    }
+    Ok(S::Pattern(PatternStyle::Contextual { context, .. })) => {
+      out.push((format!("{}.pattern.context", path), context.clone()));
+    }
+    _ => (),
+  });
+}
+
+/// Names of the metavariables captured by the patterns of `rule` and its sub-rules.
+pub fn collect_captures(rule: &SerializableRule, lang: &ts::Language, names: &mut Vec<String>) {
+  use SerializableRule as S;
+  walk_rule(rule, "", &mut |_, rule| match rule {
+    Ok(S::Pattern(PatternStyle::Str(pattern))) => names.extend(meta_vars(pattern, lang)),
+    Ok(S::Pattern(PatternStyle::Contextual { context, .. })) => {
+      names.extend(meta_vars(context, lang));
+    }
+    _ => (),
+  });
+}
@@ -0,0 +1,136 @@
+use crate::error::{tag, Code};
+use crate::rule::CompiledRule;
+use crate::{match_range, serialize};
+
+use ast_grep_core::{Matcher, Node};
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+
+use std::cell::RefCell;
+
+const BATCH_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Options {
+  /// events reported by one scan, the rest are only counted
+  max_events: usize,
+  /// chars of node text kept in an event
+  max_text: usize,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Self {
+      max_events: 1000,
+      max_text: 40,
+    }
+  }
+}
+
+thread_local! {
+  static TRACER: RefCell<Option<(js_sys::Function, Options)>> = const { RefCell::new(None) };
+}
+
+/// Report the nodes each scan tries and whether they matched to `callback`, `null` turns it off.
+/// For "matches in the CLI but not here": the nodes of the right kind tried and rejected show
+/// where the tree of the page differs. Only findNodes, scanRules and scanMany are traced.
+/// `callback` gets `{ events, dropped }` in batches while the scan runs,
+/// each event `{ rule, kind, range, text, matched }` where `rule` is the index of the config.
+/// Options `{ maxEvents = 1000, maxText = 40 }` cap the events of one scan and the text of each,
+/// `dropped` of the last batch counts the events over the cap.
+/// Nodes are matched a second time for the trace, so scans are slower while it is on.
+#[wasm_bindgen(js_name = setVisitTracing)]
+pub fn set_visit_tracing(
+  callback: Option<js_sys::Function>,
+  options: JsValue,
+) -> Result<(), JsValue> {
+  let options = if options.is_undefined() || options.is_null() {
+    Options::default()
+  } else {
+    serde_wasm_bindgen::from_value(options).map_err(|e| tag(Code::InvalidArgument, e))?
+  };
+  TRACER.with(|t| *t.borrow_mut() = callback.map(|c| (c, options)));
+  Ok(())
+}
+
+#[derive(Serialize)]
+struct Event {
+  rule: usize,
+  kind: String,
+  range: [usize; 4],
+  text: String,
+  matched: bool,
+}
+
+#[derive(Serialize)]
+struct Batch<'a> {
+  events: &'a [Event],
+  dropped: usize,
+}
+
+/// The trace of one scan, `None` from `start` when tracing is off.
+pub struct Visits {
+  callback: js_sys::Function,
+  options: Options,
+  events: Vec<Event>,
+  reported: usize,
+  dropped: usize,
+}
+
+impl Visits {
+  pub fn start() -> Option<Self> {
+    let (callback, options) = TRACER.with(|t| t.borrow().clone())?;
+    Some(Self {
+      callback,
+      options,
+      events: Vec::with_capacity(BATCH_SIZE),
+      reported: 0,
+      dropped: 0,
+    })
+  }
+
+  /// Record `node` if `rule` at index `index` considers it, i.e. it is of a kind the rule can match.
+  pub fn record(&mut self, index: usize, node: &Node<ts::Language>, rule: &CompiledRule) {
+    if !rule.may_match(node) {
+      return;
+    }
+    if self.reported + self.events.len() >= self.options.max_events {
+      self.dropped += 1;
+      return;
+    }
+    let text = node.text();
+    self.events.push(Event {
+      rule: index,
+      kind: node.kind().into_owned(),
+      range: match_range(node),
+      text: text.chars().take(self.options.max_text).collect(),
+      matched: rule.match_node(node.clone()).is_some(),
+    });
+    if self.events.len() == BATCH_SIZE {
+      self.flush();
+    }
+  }
+
+  fn flush(&mut self) {
+    let batch = Batch {
+      events: &self.events,
+      dropped: self.dropped,
+    };
+    // the trace is informational, a throwing callback should not abort the scan
+    if let Ok(batch) = serialize::to_value(&batch) {
+      let _ = self.callback.call1(&JsValue::NULL, &batch);
+    }
+    self.reported += self.events.len();
+    self.events.clear();
+  }
+}
+
+impl Drop for Visits {
+  fn drop(&mut self) {
+    if !self.events.is_empty() || self.dropped > 0 {
+      self.flush();
+    }
+  }
+}
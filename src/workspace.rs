@@ -0,0 +1,343 @@
+use crate::cancel::{CancelCheck, CancelSignal};
+use crate::context::{global, Context};
+use crate::error::{error_with, tag, Code};
+use crate::metrics::Metrics;
+use crate::rule::CompiledRule;
+use crate::types::{ConfigArray, LanguageGlobs, WorkspaceResult};
+use crate::{
+  attach_unmatched, find_language, min_timeout, parse_root, scan_ranges, scan_value, utils,
+  Languages,
+};
+
+use tree_sitter as ts;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use std::rc::Rc;
+
+/// Registered language names tried for a file extension, the first loaded one wins.
+fn languages_of_extension(ext: &str) -> &'static [&'static str] {
+  match ext {
+    "js" | "mjs" | "cjs" | "jsx" => &["javascript", "tsx"],
+    "ts" | "mts" | "cts" => &["typescript", "tsx"],
+    "tsx" => &["tsx", "typescript"],
+    "py" | "pyi" => &["python"],
+    "rs" => &["rust"],
+    "go" => &["go"],
+    "java" => &["java"],
+    "kt" | "kts" => &["kotlin"],
+    "c" | "h" => &["c", "cpp"],
+    "cc" | "cpp" | "cxx" | "hpp" | "hh" => &["cpp"],
+    "cs" => &["c_sharp", "csharp"],
+    "rb" => &["ruby"],
+    "swift" => &["swift"],
+    "lua" => &["lua"],
+    "css" => &["css"],
+    "html" | "htm" => &["html"],
+    "json" => &["json"],
+    "sh" | "bash" => &["bash"],
+    "yml" | "yaml" => &["yaml"],
+    _ => &[],
+  }
+}
+
+/// Whether `glob` matches `text`, `**` spanning directories, `*` and `?` within one.
+fn glob_matches(glob: &[u8], text: &[u8]) -> bool {
+  // outcomes by glob and text index, so `*a*a*a*b` stays quadratic
+  let mut memo = vec![None; (glob.len() + 1) * (text.len() + 1)];
+  matches_from(glob, text, 0, 0, &mut memo)
+}
+
+fn matches_from(glob: &[u8], text: &[u8], g: usize, t: usize, memo: &mut [Option<bool>]) -> bool {
+  let slot = g * (text.len() + 1) + t;
+  if let Some(done) = memo[slot] {
+    return done;
+  }
+  let rest = &text[t..];
+  let ret = match &glob[g..] {
+    [] => rest.is_empty(),
+    [b'*', b'*', b'/', ..] => {
+      // `**/` also matches no directory
+      matches_from(glob, text, g + 3, t, memo)
+        || (t..text.len()).any(|i| text[i] == b'/' && matches_from(glob, text, g + 3, i + 1, memo))
+    }
+    [b'*', b'*', ..] => (t..=text.len()).any(|i| matches_from(glob, text, g + 2, i, memo)),
+    [b'*', ..] => (t..=text.len())
+      .take_while(|i| *i == t || text[i - 1] != b'/')
+      .any(|i| matches_from(glob, text, g + 1, i, memo)),
+    [b'?', ..] => {
+      rest.first().is_some_and(|c| *c != b'/') && matches_from(glob, text, g + 1, t + 1, memo)
+    }
+    [c, ..] => rest.first() == Some(c) && matches_from(glob, text, g + 1, t + 1, memo),
+  };
+  memo[slot] = Some(ret);
+  ret
+}
+
+/// The language of the first glob matching `name`, globs without `/` match the file name.
+fn glob_language<'a>(globs: &'a [(String, Vec<String>)], name: &str) -> Option<&'a str> {
+  let base = name.rsplit('/').next().unwrap_or(name);
+  let matches = |glob: &String| {
+    let text = if glob.contains('/') { name } else { base };
+    glob_matches(glob.as_bytes(), text.as_bytes())
+  };
+  globs
+    .iter()
+    .find(|(_, globs)| globs.iter().any(matches))
+    .map(|(lang, _)| lang.as_str())
+}
+
+/// The language assigned to a file, else the one of its `languageGlobs`, its extension
+/// or the current language.
+fn detect_language(
+  languages: &Languages,
+  globs: &[(String, Vec<String>)],
+  file: &File,
+) -> Result<(Option<String>, Rc<ts::Language>), JsValue> {
+  let name = &file.name;
+  let assigned = file
+    .language
+    .as_deref()
+    .or_else(|| glob_language(globs, name));
+  if let Some(assigned) = assigned {
+    let lang = find_language(languages, Some(assigned))?;
+    return Ok((Some(assigned.to_lowercase()), lang));
+  }
+  let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+  let found = languages_of_extension(&ext.to_lowercase())
+    .iter()
+    .find_map(|lang| Some((*lang, languages.registered.get(*lang)?)));
+  if let Some((lang_name, lang)) = found {
+    return Ok((Some(lang_name.to_string()), lang.clone()));
+  }
+  match &languages.current {
+    Some(lang) => Ok((languages.current_name.clone(), lang.clone())),
+    None => {
+      let message = format!("no loaded language fits `{}`", name);
+      Err(error_with(
+        Code::NoLanguage,
+        &message,
+        &[("file", name.into())],
+      ))
+    }
+  }
+}
+
+struct File {
+  name: String,
+  src: String,
+  /// overrides the language of the extension, e.g. `tsx` for a `.ts` file with JSX
+  language: Option<String>,
+}
+
+/// Files kept in memory to scan together, e.g. to try rules of several languages on a
+/// made up project. Each file is parsed with the language assigned to it, else the one
+/// of its `languageGlobs`, the one its extension names if it is registered, or the
+/// current language.
+#[wasm_bindgen]
+pub struct Workspace {
+  ctx: Rc<Context>,
+  files: Vec<File>,
+  /// languages with their globs, in the order of `setLanguageGlobs`
+  globs: Vec<(String, Vec<String>)>,
+}
+
+impl Workspace {
+  pub(crate) fn new(ctx: Rc<Context>) -> Self {
+    Self {
+      ctx,
+      files: vec![],
+      globs: vec![],
+    }
+  }
+}
+
+/// An empty `Workspace` using the module wide languages.
+#[wasm_bindgen(js_name = createWorkspace)]
+pub fn create_workspace() -> Workspace {
+  Workspace::new(global())
+}
+
+#[wasm_bindgen]
+impl Workspace {
+  /// Add a file, replacing the one with the same name. `language` assigns the registered
+  /// language to parse it with instead of the one of its extension, a replaced file keeps
+  /// its language without one.
+  #[wasm_bindgen(js_name = addFile)]
+  pub fn add_file(&mut self, name: String, src: String, language: Option<String>) {
+    match self.files.iter_mut().find(|f| f.name == name) {
+      Some(file) => {
+        file.src = src;
+        if language.is_some() {
+          file.language = language;
+        }
+      }
+      None => self.files.push(File {
+        name,
+        src,
+        language,
+      }),
+    }
+  }
+
+  /// Assign the language of a file, or with `null` go back to its extension.
+  /// Returns whether the file is there.
+  #[wasm_bindgen(js_name = setFileLanguage)]
+  pub fn set_file_language(&mut self, name: String, language: Option<String>) -> bool {
+    match self.files.iter_mut().find(|f| f.name == name) {
+      Some(file) => {
+        file.language = language;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Map globs to languages like `languageGlobs` of `sgconfig.yml`, e.g.
+  /// `{ html: ["*.vue"], tsx: ["*.mjsx"] }`, replacing the globs set before. They win over
+  /// extensions but not over a language assigned to a file. Globs without `/` match
+  /// the file name, `**` spans directories and `*` and `?` stay within one.
+  #[wasm_bindgen(js_name = setLanguageGlobs)]
+  pub fn set_language_globs(&mut self, globs: LanguageGlobs) -> Result<(), JsValue> {
+    let globs: js_sys::Object = globs.unchecked_into();
+    let mut parsed = vec![];
+    // entries keep the order of the object, the first matching language wins
+    for entry in js_sys::Object::entries(&globs).iter() {
+      let (language, patterns) = serde_wasm_bindgen::from_value::<(String, Vec<String>)>(entry)
+        .map_err(|e| tag(Code::InvalidArgument, e))?;
+      parsed.push((language, patterns));
+    }
+    self.globs = parsed;
+    Ok(())
+  }
+
+  /// Remove a file, returning whether it was there.
+  #[wasm_bindgen(js_name = removeFile)]
+  pub fn remove_file(&mut self, name: String) -> bool {
+    let len = self.files.len();
+    self.files.retain(|f| f.name != name);
+    self.files.len() != len
+  }
+
+  /// The names of the files in the order they were added.
+  #[wasm_bindgen(js_name = fileNames)]
+  pub fn file_names(&self) -> Vec<JsValue> {
+    self.files.iter().map(|f| f.name.as_str().into()).collect()
+  }
+
+  /// Scan every file with the configs of its language, returning `[{ name, language, matches }]`
+  /// in file order where `matches` has the shape of the result of `scanRules`, empty for
+  /// configs of other languages. Configs may use different languages, unlike `scanMany`.
+  /// Files after the time budget is spent are left out and the array is marked `timedOut`.
+  #[wasm_bindgen(js_name = scanWorkspace)]
+  pub fn scan_workspace(
+    &self,
+    configs: ConfigArray,
+    cancel: Option<CancelSignal>,
+  ) -> Result<WorkspaceResult, JsValue> {
+    utils::enter("Workspace.scanWorkspace", 0);
+    let rules = configs
+      .iter()
+      .map(|config| self.ctx.compile_config(config))
+      .collect::<Result<Vec<_>, _>>()?;
+    let mut check = CancelCheck::new(cancel.as_ref(), min_timeout(&rules));
+    let ret = js_sys::Array::new();
+    let mut matched = vec![false; rules.len()];
+    for file in &self.files {
+      if check.timed_out {
+        js_sys::Reflect::set(&ret, &"timedOut".into(), &JsValue::TRUE)?;
+        break;
+      }
+      let (lang_name, lang) = detect_language(&self.ctx.languages.borrow(), &self.globs, file)?;
+      let ranges = scan_file(&file.src, &lang, &rules, &mut check)?;
+      for (matched, ranges) in matched.iter_mut().zip(&ranges) {
+        *matched |= !ranges.is_empty();
+      }
+      let matches = scan_value(&ranges, &rules, &check, Metrics::start(), None)?;
+      let entry = js_sys::Object::new();
+      js_sys::Reflect::set(&entry, &"name".into(), &file.name.as_str().into())?;
+      let lang_name = lang_name.map_or(JsValue::NULL, JsValue::from);
+      js_sys::Reflect::set(&entry, &"language".into(), &lang_name)?;
+      js_sys::Reflect::set(&entry, &"matches".into(), &matches)?;
+      ret.push(&entry);
+    }
+    attach_unmatched(&ret, &rules, &matched)?;
+    Ok(ret.unchecked_into())
+  }
+}
+
+/// Ranges of every rule in `src`, only the rules of `lang` are run.
+fn scan_file(
+  src: &str,
+  lang: &Rc<ts::Language>,
+  rules: &[Rc<CompiledRule>],
+  check: &mut CancelCheck,
+) -> Result<Vec<Vec<[usize; 4]>>, JsValue> {
+  let (indices, own): (Vec<_>, Vec<_>) = rules
+    .iter()
+    .enumerate()
+    .filter(|(_, rule)| *rule.lang == **lang)
+    .map(|(i, rule)| (i, rule.clone()))
+    .unzip();
+  let mut ret = vec![vec![]; rules.len()];
+  if own.is_empty() {
+    return Ok(ret);
+  }
+  let root = parse_root(src, lang)?;
+  let ranges = scan_ranges(&root, &own, check, None)?;
+  for (i, ranges) in indices.into_iter().zip(ranges) {
+    ret[i] = ranges;
+  }
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn globs(pairs: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+    pairs
+      .iter()
+      .map(|(lang, globs)| {
+        (
+          lang.to_string(),
+          globs.iter().map(|g| g.to_string()).collect(),
+        )
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_double_star() {
+    assert!(glob_matches(b"src/**/*.rs", b"src/a.rs"));
+    assert!(glob_matches(b"src/**/*.rs", b"src/a/b/c.rs"));
+    assert!(glob_matches(b"**/*.rs", b"a.rs"));
+    assert!(!glob_matches(b"src/**/*.rs", b"lib/a.rs"));
+  }
+
+  #[test]
+  fn test_star_and_question() {
+    assert!(glob_matches(b"*.rs", b"main.rs"));
+    assert!(!glob_matches(b"*.rs", b"src/main.rs"));
+    assert!(glob_matches(b"a?.rs", b"ab.rs"));
+    assert!(!glob_matches(b"a?.rs", b"a.rs"));
+    assert!(!glob_matches(b"a?b", b"a/b"));
+  }
+
+  #[test]
+  fn test_pathological() {
+    let text = "a".repeat(200);
+    assert!(!glob_matches(b"*a*a*a*a*a*a*b", text.as_bytes()));
+    assert!(!glob_matches(b"**a**a**a**a**b", text.as_bytes()));
+  }
+
+  #[test]
+  fn test_glob_language() {
+    let globs = globs(&[("tsx", &["*.js"]), ("html", &["views/**/*.vue"])]);
+    // globs without `/` match the file name
+    assert_eq!(glob_language(&globs, "src/deep/app.js"), Some("tsx"));
+    assert_eq!(glob_language(&globs, "views/a.vue"), Some("html"));
+    assert_eq!(glob_language(&globs, "src/views/a.vue"), None);
+    assert_eq!(glob_language(&globs, "a.ts"), None);
+  }
+}